@@ -6,14 +6,24 @@ use migration::{Migrator, MigratorTrait};
 use ripfy_server::{
     build_app, config,
     crypt::passwd::{gen_salt, passwd_encrypt},
-    db, keys, AppState,
+    db,
+    downloader::fake::FakeDownloader,
+    jobs, keys, AppState,
 };
 use sea_orm::Database;
-use std::net::SocketAddr;
+use std::{net::SocketAddr, sync::Arc};
 use tokio::net::TcpListener;
 
+/// Playlist the fake downloader knows, made of the songs of fake_downloader
+pub const DEMO_PLAYLIST_ID: &str = "PLripfyDemoQueenClassics000000000";
+/// Video whose download fails, as if it was private
+pub const UNAVAILABLE_VIDEO_ID: &str = "unavailable";
+
 /// Used for integration tests
-pub async fn spawn_test_app(port: u16, use_demo_users: bool) -> Result<()> {
+///
+/// Songs are "downloaded" by a FakeDownloader, so the tests run without network or yt-dlp
+/// Returns the state of the app, for tests that check the database directly
+pub async fn spawn_test_app(port: u16, use_demo_users: bool) -> Result<AppState> {
     start_global_subscriber();
 
     tracing::info!("BUILDING TEST APP");
@@ -22,7 +32,10 @@ pub async fn spawn_test_app(port: u16, use_demo_users: bool) -> Result<()> {
 
     Migrator::up(&db, None).await?;
 
-    let state = AppState { db };
+    let state = AppState {
+        db,
+        downloader: Arc::new(fake_downloader()),
+    };
 
     if use_demo_users {
         demo_users(&state).await?;
@@ -31,18 +44,52 @@ pub async fn spawn_test_app(port: u16, use_demo_users: bool) -> Result<()> {
     config();
     keys();
 
+    jobs::start_workers(&state).await?;
+
     let socket_addr = SocketAddr::from(([0, 0, 0, 0], port));
     let listener = TcpListener::bind(&socket_addr).await?;
+    let app = build_app(state.clone());
 
     tokio::spawn(async move {
-        axum::serve(listener, build_app(state).into_make_service()).await?;
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .await?;
 
         Ok::<(), anyhow::Error>(())
     });
 
     tracing::info!("Listening on {}", socket_addr);
 
-    Ok(())
+    Ok(state)
+}
+
+/// Knows the videos the integration tests use, any other valid video id downloads too, with a
+/// generic title
+fn fake_downloader() -> FakeDownloader {
+    FakeDownloader::default()
+        .with_video(
+            "fJ9rUzIMcZQ",
+            "Queen – Bohemian Rhapsody (Official Video Remastered)",
+            "Queen Official",
+        )
+        .with_video(
+            "2ZBtPf7FOoM",
+            "Queen - Killer Queen (Top Of The Pops, 1974)",
+            "Queen Official",
+        )
+        .with_video(
+            "Nnjh-zp6pP4",
+            "AC/DC - Highway to Hell (Official Video)",
+            "AC/DC",
+        )
+        .with_playlist(
+            DEMO_PLAYLIST_ID,
+            "Queen Classics",
+            &["fJ9rUzIMcZQ", UNAVAILABLE_VIDEO_ID, "2ZBtPf7FOoM"],
+        )
+        .with_unavailable(UNAVAILABLE_VIDEO_ID)
 }
 
 async fn demo_users(state: &AppState) -> Result<()> {
@@ -1,7 +1,17 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.6
 
+pub use super::api_key::Entity as ApiKey;
+pub use super::download_job::Entity as DownloadJob;
+pub use super::invite_code::Entity as InviteCode;
+pub use super::login_failure::Entity as LoginFailure;
+pub use super::mfa_challenge::Entity as MfaChallenge;
+pub use super::passwd_reset_token::Entity as PasswdResetToken;
 pub use super::playlist::Entity as Playlist;
 pub use super::playlist_song::Entity as PlaylistSong;
+pub use super::recovery_code::Entity as RecoveryCode;
+pub use super::refresh_token::Entity as RefreshToken;
+pub use super::session::Entity as Session;
 pub use super::song::Entity as Song;
+pub use super::totp::Entity as Totp;
 pub use super::user::Entity as User;
 pub use super::user_song::Entity as UserSong;
@@ -10,6 +10,17 @@ pub struct Model {
     pub id: String,
     pub title: String,
     pub channel: String,
+    pub duration_secs: Option<i32>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub track: Option<String>,
+    pub release_year: Option<i32>,
+    pub upload_date: Option<String>,
+    pub thumbnail_url: Option<String>,
+    pub created_at: String,
+    #[serde(skip)] // only the garbage collector cares about it
+    pub orphaned_at: Option<String>,
+    pub status: String,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
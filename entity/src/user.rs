@@ -9,22 +9,98 @@ pub struct Model {
     pub id: String,
     pub username: String,
     pub passwd: String,
+    #[sea_orm(unique)]
+    pub normalized_username: String,
+    pub role: String,
+    pub disabled: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::api_key::Entity")]
+    ApiKey,
+    #[sea_orm(has_many = "super::download_job::Entity")]
+    DownloadJob,
+    #[sea_orm(has_many = "super::invite_code::Entity")]
+    InviteCode,
+    #[sea_orm(has_many = "super::mfa_challenge::Entity")]
+    MfaChallenge,
+    #[sea_orm(has_many = "super::passwd_reset_token::Entity")]
+    PasswdResetToken,
     #[sea_orm(has_many = "super::playlist::Entity")]
     Playlist,
+    #[sea_orm(has_many = "super::recovery_code::Entity")]
+    RecoveryCode,
+    #[sea_orm(has_many = "super::refresh_token::Entity")]
+    RefreshToken,
+    #[sea_orm(has_many = "super::session::Entity")]
+    Session,
+    #[sea_orm(has_one = "super::totp::Entity")]
+    Totp,
     #[sea_orm(has_many = "super::user_song::Entity")]
     UserSong,
 }
 
+impl Related<super::api_key::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ApiKey.def()
+    }
+}
+
+impl Related<super::download_job::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::DownloadJob.def()
+    }
+}
+
+impl Related<super::invite_code::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::InviteCode.def()
+    }
+}
+
+impl Related<super::mfa_challenge::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::MfaChallenge.def()
+    }
+}
+
+impl Related<super::passwd_reset_token::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::PasswdResetToken.def()
+    }
+}
+
 impl Related<super::playlist::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Playlist.def()
     }
 }
 
+impl Related<super::recovery_code::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::RecoveryCode.def()
+    }
+}
+
+impl Related<super::refresh_token::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::RefreshToken.def()
+    }
+}
+
+impl Related<super::session::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Session.def()
+    }
+}
+
+impl Related<super::totp::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Totp.def()
+    }
+}
+
 impl Related<super::user_song::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::UserSong.def()
@@ -4,6 +4,22 @@ mod m20230920_191630_create_song_table;
 mod m20231008_182809_create_user;
 mod m20231027_000833_create_user_song;
 mod m20231219_224941_create_playlist_tables;
+mod m20240112_201533_create_refresh_token;
+mod m20240116_184210_create_session;
+mod m20240123_210947_create_api_key;
+mod m20240129_193402_create_passwd_reset_token;
+mod m20240202_154120_add_normalized_username;
+mod m20240207_120815_add_user_role;
+mod m20240212_181104_create_invite_code;
+mod m20240219_093015_create_login_failure;
+mod m20240226_201744_create_totp;
+mod m20240304_172230_create_download_job;
+mod m20240311_190422_add_playlist_import;
+mod m20240318_154630_add_song_metadata;
+mod m20240325_201218_namespace_song_ids;
+mod m20240401_184517_add_song_orphaned_at;
+mod m20240408_201044_add_song_status;
+mod m20240415_183207_renormalize_usernames;
 
 pub struct Migrator;
 
@@ -15,6 +31,22 @@ impl MigratorTrait for Migrator {
             Box::new(m20231008_182809_create_user::Migration),
             Box::new(m20231027_000833_create_user_song::Migration),
             Box::new(m20231219_224941_create_playlist_tables::Migration),
+            Box::new(m20240112_201533_create_refresh_token::Migration),
+            Box::new(m20240116_184210_create_session::Migration),
+            Box::new(m20240123_210947_create_api_key::Migration),
+            Box::new(m20240129_193402_create_passwd_reset_token::Migration),
+            Box::new(m20240202_154120_add_normalized_username::Migration),
+            Box::new(m20240207_120815_add_user_role::Migration),
+            Box::new(m20240212_181104_create_invite_code::Migration),
+            Box::new(m20240219_093015_create_login_failure::Migration),
+            Box::new(m20240226_201744_create_totp::Migration),
+            Box::new(m20240304_172230_create_download_job::Migration),
+            Box::new(m20240311_190422_add_playlist_import::Migration),
+            Box::new(m20240318_154630_add_song_metadata::Migration),
+            Box::new(m20240325_201218_namespace_song_ids::Migration),
+            Box::new(m20240401_184517_add_song_orphaned_at::Migration),
+            Box::new(m20240408_201044_add_song_status::Migration),
+            Box::new(m20240415_183207_renormalize_usernames::Migration),
         ]
     }
 }
@@ -0,0 +1,65 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20231008_182809_create_user::User;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Session::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(Session::Id).uuid().not_null().primary_key())
+                    .col(ColumnDef::new(Session::UserId).uuid().not_null())
+                    .col(ColumnDef::new(Session::Device).string())
+                    .col(ColumnDef::new(Session::Ip).string())
+                    .col(
+                        ColumnDef::new(Session::Revoked)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .col(
+                        ColumnDef::new(Session::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .extra("DEFAULT CURRENT_TIMESTAMP"),
+                    )
+                    .col(ColumnDef::new(Session::LastSeenAt).date_time().not_null())
+                    .col(ColumnDef::new(Session::ExpiresAt).date_time().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from_tbl(Session::Table)
+                            .from_col(Session::UserId)
+                            .to_tbl(User::Table)
+                            .to_col(User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Session::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum Session {
+    Table,
+    Id,
+    UserId,
+    Device,
+    Ip,
+    Revoked,
+    CreatedAt,
+    LastSeenAt,
+    ExpiresAt,
+}
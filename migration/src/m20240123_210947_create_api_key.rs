@@ -0,0 +1,60 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20231008_182809_create_user::User;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ApiKey::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(ApiKey::Id).uuid().not_null().primary_key())
+                    .col(ColumnDef::new(ApiKey::UserId).uuid().not_null())
+                    .col(ColumnDef::new(ApiKey::Name).string().not_null())
+                    .col(ColumnDef::new(ApiKey::KeyHash).string().not_null())
+                    .col(ColumnDef::new(ApiKey::Scope).string().not_null())
+                    .col(ColumnDef::new(ApiKey::ExpiresAt).date_time())
+                    .col(ColumnDef::new(ApiKey::LastUsedAt).date_time())
+                    .col(
+                        ColumnDef::new(ApiKey::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .extra("DEFAULT CURRENT_TIMESTAMP"),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from_tbl(ApiKey::Table)
+                            .from_col(ApiKey::UserId)
+                            .to_tbl(User::Table)
+                            .to_col(User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ApiKey::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum ApiKey {
+    Table,
+    Id,
+    UserId,
+    Name,
+    KeyHash,
+    Scope,
+    ExpiresAt,
+    LastUsedAt,
+    CreatedAt,
+}
@@ -0,0 +1,103 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20231008_182809_create_user::User;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .add_column(
+                        ColumnDef::new(NormalizedUser::NormalizedUsername)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // if two existing users only differ by case, the index creation fails and they have to be
+        // renamed by hand before migrating
+        backfill_normalized_usernames(manager).await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_user_normalized_username")
+                    .table(User::Table)
+                    .col(NormalizedUser::NormalizedUsername)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_user_normalized_username")
+                    .table(User::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .drop_column(NormalizedUser::NormalizedUsername)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+/// Sets normalized_username of every user, in Rust since lower() of SQLite only lowercases ASCII
+pub(crate) async fn backfill_normalized_usernames(
+    manager: &SchemaManager<'_>,
+) -> Result<(), DbErr> {
+    let db = manager.get_connection();
+    let builder = manager.get_database_backend();
+
+    let select = Query::select()
+        .columns([User::Id, User::Username])
+        .from(User::Table)
+        .to_owned();
+
+    for row in db.query_all(builder.build(&select)).await? {
+        let id: String = row.try_get("", &User::Id.to_string())?;
+        let username: String = row.try_get("", &User::Username.to_string())?;
+
+        let update = Query::update()
+            .table(User::Table)
+            .value(
+                NormalizedUser::NormalizedUsername,
+                normalize_username(&username),
+            )
+            .and_where(Expr::col(User::Id).eq(id))
+            .to_owned();
+
+        manager.exec_stmt(update).await?;
+    }
+
+    Ok(())
+}
+
+/// Must match db::user::normalize_username, the migrations can not depend on the server crate
+fn normalize_username(username: &str) -> String {
+    username.trim().to_lowercase()
+}
+
+#[derive(DeriveIden)]
+enum NormalizedUser {
+    NormalizedUsername,
+}
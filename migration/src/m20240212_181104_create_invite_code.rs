@@ -0,0 +1,66 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20231008_182809_create_user::User;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(InviteCode::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(InviteCode::Code)
+                            .string()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(InviteCode::CreatedBy).uuid().not_null())
+                    .col(ColumnDef::new(InviteCode::MaxUses).integer().not_null())
+                    .col(
+                        ColumnDef::new(InviteCode::Uses)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(ColumnDef::new(InviteCode::ExpiresAt).date_time())
+                    .col(
+                        ColumnDef::new(InviteCode::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .extra("DEFAULT CURRENT_TIMESTAMP"),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from_tbl(InviteCode::Table)
+                            .from_col(InviteCode::CreatedBy)
+                            .to_tbl(User::Table)
+                            .to_col(User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(InviteCode::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum InviteCode {
+    Table,
+    Code,
+    CreatedBy,
+    MaxUses,
+    Uses,
+    ExpiresAt,
+    CreatedAt,
+}
@@ -0,0 +1,153 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20231008_182809_create_user::User;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Totp::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(Totp::UserId).uuid().not_null().primary_key())
+                    .col(ColumnDef::new(Totp::Secret).string().not_null())
+                    .col(
+                        ColumnDef::new(Totp::Enabled)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .col(ColumnDef::new(Totp::LastUsedStep).big_integer())
+                    .col(
+                        ColumnDef::new(Totp::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .extra("DEFAULT CURRENT_TIMESTAMP"),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from_tbl(Totp::Table)
+                            .from_col(Totp::UserId)
+                            .to_tbl(User::Table)
+                            .to_col(User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(RecoveryCode::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(RecoveryCode::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(RecoveryCode::UserId).uuid().not_null())
+                    .col(ColumnDef::new(RecoveryCode::CodeHash).string().not_null())
+                    .col(
+                        ColumnDef::new(RecoveryCode::Used)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from_tbl(RecoveryCode::Table)
+                            .from_col(RecoveryCode::UserId)
+                            .to_tbl(User::Table)
+                            .to_col(User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(MfaChallenge::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(MfaChallenge::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(MfaChallenge::UserId).uuid().not_null())
+                    .col(ColumnDef::new(MfaChallenge::TokenHash).string().not_null())
+                    .col(
+                        ColumnDef::new(MfaChallenge::Used)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .col(
+                        ColumnDef::new(MfaChallenge::ExpiresAt)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from_tbl(MfaChallenge::Table)
+                            .from_col(MfaChallenge::UserId)
+                            .to_tbl(User::Table)
+                            .to_col(User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(MfaChallenge::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(RecoveryCode::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(Totp::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum Totp {
+    Table,
+    UserId,
+    Secret,
+    Enabled,
+    LastUsedStep,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+pub enum RecoveryCode {
+    Table,
+    Id,
+    UserId,
+    CodeHash,
+    Used,
+}
+
+#[derive(DeriveIden)]
+pub enum MfaChallenge {
+    Table,
+    Id,
+    UserId,
+    TokenHash,
+    Used,
+    ExpiresAt,
+}
@@ -0,0 +1,81 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20231008_182809_create_user::User;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(DownloadJob::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(DownloadJob::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(DownloadJob::UserId).uuid().not_null())
+                    .col(ColumnDef::new(DownloadJob::SongId).string().not_null())
+                    .col(
+                        ColumnDef::new(DownloadJob::State)
+                            .string()
+                            .not_null()
+                            .default("queued"),
+                    )
+                    .col(ColumnDef::new(DownloadJob::Error).text())
+                    .col(
+                        ColumnDef::new(DownloadJob::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .extra("DEFAULT CURRENT_TIMESTAMP"),
+                    )
+                    .col(ColumnDef::new(DownloadJob::StartedAt).date_time())
+                    .col(ColumnDef::new(DownloadJob::FinishedAt).date_time())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from_tbl(DownloadJob::Table)
+                            .from_col(DownloadJob::UserId)
+                            .to_tbl(User::Table)
+                            .to_col(User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // workers look up the oldest queued job every time they are free
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_download_job_state")
+                    .table(DownloadJob::Table)
+                    .col(DownloadJob::State)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(DownloadJob::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum DownloadJob {
+    Table,
+    Id,
+    UserId,
+    SongId,
+    State,
+    Error,
+    CreatedAt,
+    StartedAt,
+    FinishedAt,
+}
@@ -0,0 +1,87 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20240304_172230_create_download_job::DownloadJob;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// SQLite only allows one column per ALTER TABLE, so each column gets its own statement
+// it also can not add foreign keys to an existing table, a job whose playlist was deleted is
+// handled by the worker instead
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(PlaylistSong::Table)
+                    .add_column(
+                        ColumnDef::new(PlaylistSong::Position)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(DownloadJob::Table)
+                    .add_column(ColumnDef::new(PlaylistImport::PlaylistId).uuid())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(DownloadJob::Table)
+                    .add_column(ColumnDef::new(PlaylistImport::PlaylistPosition).integer())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(DownloadJob::Table)
+                    .drop_column(PlaylistImport::PlaylistPosition)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(DownloadJob::Table)
+                    .drop_column(PlaylistImport::PlaylistId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(PlaylistSong::Table)
+                    .drop_column(PlaylistSong::Position)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum PlaylistSong {
+    Table,
+    Position,
+}
+
+#[derive(DeriveIden)]
+enum PlaylistImport {
+    PlaylistId,
+    PlaylistPosition,
+}
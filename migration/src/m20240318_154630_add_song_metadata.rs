@@ -0,0 +1,94 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20230920_191630_create_song_table::Song;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// SQLite only allows one column per ALTER TABLE, so each column gets its own statement
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let columns = [
+            ColumnDef::new(SongMetadata::DurationSecs)
+                .integer()
+                .to_owned(),
+            ColumnDef::new(SongMetadata::Artist).string().to_owned(),
+            ColumnDef::new(SongMetadata::Album).string().to_owned(),
+            ColumnDef::new(SongMetadata::Track).string().to_owned(),
+            ColumnDef::new(SongMetadata::ReleaseYear)
+                .integer()
+                .to_owned(),
+            ColumnDef::new(SongMetadata::UploadDate).string().to_owned(),
+            ColumnDef::new(SongMetadata::ThumbnailUrl)
+                .string()
+                .to_owned(),
+            // SQLite can not add a column with a non constant default like CURRENT_TIMESTAMP, new
+            // songs get it from db::song::create_new
+            ColumnDef::new(SongMetadata::CreatedAt)
+                .date_time()
+                .not_null()
+                .default("")
+                .to_owned(),
+        ];
+
+        for mut column in columns {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Song::Table)
+                        .add_column(&mut column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        // songs downloaded before the migration count as added when it ran
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"UPDATE "song" SET "created_at" = strftime('%Y-%m-%dT%H:%M:%SZ', 'now')"#,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let columns = [
+            SongMetadata::CreatedAt,
+            SongMetadata::ThumbnailUrl,
+            SongMetadata::UploadDate,
+            SongMetadata::ReleaseYear,
+            SongMetadata::Track,
+            SongMetadata::Album,
+            SongMetadata::Artist,
+            SongMetadata::DurationSecs,
+        ];
+
+        for column in columns {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Song::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum SongMetadata {
+    DurationSecs,
+    Artist,
+    Album,
+    Track,
+    ReleaseYear,
+    UploadDate,
+    ThumbnailUrl,
+    CreatedAt,
+}
@@ -0,0 +1,65 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// Tables that reference songs by id
+const SONG_ID_COLUMNS: [(&str, &str); 3] = [
+    ("user_song", "song_id"),
+    ("playlist_song", "song_id"),
+    ("download_job", "song_id"),
+];
+
+// Every song downloaded so far came from youtube, so their ids become yt:<video id>
+//
+// The foreign keys to song.id do not cascade updates, so every song is copied with its new id,
+// the references are moved to the copy and only then the old song is deleted
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        rename_song_ids(manager, "'yt:' || {id}", "{id} NOT LIKE '%:%'").await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // songs of other providers keep their ids, there is nothing to turn them back into
+        rename_song_ids(manager, "substr({id}, 4)", "{id} LIKE 'yt:%'").await
+    }
+}
+
+/// Renames the songs that match filter to new_id, {id} in both is replaced by the column that
+/// holds the song id
+async fn rename_song_ids(
+    manager: &SchemaManager<'_>,
+    new_id: &str,
+    filter: &str,
+) -> Result<(), DbErr> {
+    let db = manager.get_connection();
+    let sql = |column: &str, template: &str| template.replace("{id}", &format!(r#""{column}""#));
+
+    db.execute_unprepared(&format!(
+        r#"INSERT INTO "song" ("id", "title", "channel", "duration_secs", "artist", "album", "track", "release_year", "upload_date", "thumbnail_url", "created_at")
+        SELECT {}, "title", "channel", "duration_secs", "artist", "album", "track", "release_year", "upload_date", "thumbnail_url", "created_at"
+        FROM "song" WHERE {}"#,
+        sql("id", new_id),
+        sql("id", filter),
+    ))
+    .await?;
+
+    for (table, column) in SONG_ID_COLUMNS {
+        db.execute_unprepared(&format!(
+            r#"UPDATE "{table}" SET "{column}" = {} WHERE {}"#,
+            sql(column, new_id),
+            sql(column, filter),
+        ))
+        .await?;
+    }
+
+    // the copies never match the filter, only the old songs are left to delete
+    db.execute_unprepared(&format!(
+        r#"DELETE FROM "song" WHERE {}"#,
+        sql("id", filter)
+    ))
+    .await?;
+
+    Ok(())
+}
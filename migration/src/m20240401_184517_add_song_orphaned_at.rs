@@ -0,0 +1,49 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20230920_191630_create_song_table::Song;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// Songs that nobody has left are kept until the garbage collector deletes them, orphaned_at is
+// when it first found them without owners
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Song::Table)
+                    .add_column(ColumnDef::new(SongGc::OrphanedAt).date_time())
+                    .to_owned(),
+            )
+            .await?;
+
+        // songs removed before the migration count as orphaned when it ran
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"UPDATE "song" SET "orphaned_at" = strftime('%Y-%m-%dT%H:%M:%SZ', 'now')
+                WHERE "id" NOT IN (SELECT "song_id" FROM "user_song")"#,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Song::Table)
+                    .drop_column(SongGc::OrphanedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum SongGc {
+    OrphanedAt,
+}
@@ -0,0 +1,43 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20230920_191630_create_song_table::Song;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// A song is unavailable when its audio file is missing or broken and could not be downloaded
+// again, every song is assumed available until the library is checked
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Song::Table)
+                    .add_column(
+                        ColumnDef::new(SongStatus::Status)
+                            .string()
+                            .not_null()
+                            .default("available"),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Song::Table)
+                    .drop_column(SongStatus::Status)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum SongStatus {
+    Status,
+}
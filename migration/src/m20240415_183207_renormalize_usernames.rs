@@ -0,0 +1,19 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20240202_154120_add_normalized_username::backfill_normalized_usernames;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// Earlier versions filled normalized_username with lower() of SQLite, which leaves non-ASCII
+// letters as they are, so users with such names could not log in
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        backfill_normalized_usernames(manager).await
+    }
+
+    async fn down(&self, _manager: &SchemaManager) -> Result<(), DbErr> {
+        Ok(())
+    }
+}
@@ -0,0 +1,296 @@
+use super::{
+    error::{Error, Result},
+    ModelResponse,
+};
+use crate::{
+    config,
+    context::{Ctx, Role},
+    crypt::{
+        self,
+        passwd::{gen_salt, passwd_encrypt},
+        token::PasswdResetToken,
+    },
+    db, jobs,
+    util::{source::media_stem, time::now_utc_plus_sec_str},
+    AppState,
+};
+use axum::{
+    extract::{Path, State},
+    routing::{get, post, put},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+
+/// Every route here is restricted to admins by the ctx_require_admin middleware
+pub fn router(state: AppState) -> Router {
+    Router::new()
+        .route("/admin/users", get(get_users_handler))
+        .route("/admin/users/:id/disable", post(disable_user_handler))
+        .route("/admin/users/:id/enable", post(enable_user_handler))
+        .route("/admin/users/:id/role", put(set_role_handler))
+        .route(
+            "/admin/users/:id/password-reset",
+            post(force_passwd_reset_handler),
+        )
+        .route("/admin/usage", get(get_usage_handler))
+        .route("/admin/gc", post(gc_handler))
+        .with_state(state)
+}
+
+async fn get_users_handler(State(state): State<AppState>) -> Result<Json<Value>> {
+    tracing::debug!("ADMIN - GET USERS HANDLER");
+
+    let users: Vec<UserResponse> = db::user::all(&state)
+        .await
+        .map_err(|_| Error::DbSelectFailed)?
+        .into_iter()
+        .map(|u| UserResponse {
+            id: u.id,
+            username: u.username,
+            role: u.role,
+            disabled: u.disabled,
+        })
+        .collect();
+
+    Ok(Json(json!(ModelResponse { data: users })))
+}
+
+/// Disables an account and logs it out of every device
+///
+/// Api keys of the user stop working as well, until the account is enabled again
+async fn disable_user_handler(
+    State(state): State<AppState>,
+    ctx: Ctx,
+    Path(id): Path<String>,
+) -> Result<Json<Value>> {
+    tracing::debug!("ADMIN - DISABLE USER HANDLER");
+
+    if id == ctx.user_id() {
+        return Err(Error::InvalidPayload(
+            "An admin can not disable their own account".to_string(),
+        ));
+    }
+
+    set_disabled(&state, &id, true).await?;
+
+    db::session::revoke_all_by_user_id(&state.db, &id)
+        .await
+        .map_err(|_| Error::DbUpdateFailed)?;
+
+    Ok(Json(json!(
+        {
+        "result": "success"
+        }
+    )))
+}
+
+async fn enable_user_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>> {
+    tracing::debug!("ADMIN - ENABLE USER HANDLER");
+
+    set_disabled(&state, &id, false).await?;
+
+    Ok(Json(json!(
+        {
+        "result": "success"
+        }
+    )))
+}
+
+/// Receives a payload of format: { role }
+async fn set_role_handler(
+    State(state): State<AppState>,
+    ctx: Ctx,
+    Path(id): Path<String>,
+    Json(payload): Json<RolePayload>,
+) -> Result<Json<Value>> {
+    tracing::debug!("ADMIN - SET ROLE HANDLER");
+
+    // keeps the last admin from locking everyone out of the admin api
+    if id == ctx.user_id() && payload.role != Role::Admin {
+        return Err(Error::InvalidPayload(
+            "An admin can not remove their own admin role".to_string(),
+        ));
+    }
+
+    let updated = db::user::set_role(&state, &id, payload.role.as_ref())
+        .await
+        .map_err(|_| Error::DbUpdateFailed)?;
+
+    if !updated {
+        return Err(Error::UserIdNotFound);
+    }
+
+    Ok(Json(json!(
+        {
+        "result": "success"
+        }
+    )))
+}
+
+/// Issues a one-time password reset token for the user and returns it, the admin is the one that
+/// hands it to the user
+///
+/// The current password stops working and every session is revoked, so the user has to use the
+/// token before logging in again
+async fn force_passwd_reset_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>> {
+    tracing::debug!("ADMIN - FORCE PASSWORD RESET HANDLER");
+
+    let user = db::user::first_by_id(&state, &id)
+        .await
+        .map_err(|_| Error::DbSelectFailed)?
+        .ok_or(Error::UserIdNotFound)?;
+
+    let token = PasswdResetToken::new();
+    let expires_at = now_utc_plus_sec_str(config().passwd_reset_token_duration_secs)
+        .map_err(crypt::error::Error::from)?;
+
+    db::passwd_reset_token::create_new(
+        &state,
+        &token.id,
+        &user.id,
+        &token.hashed_secret(),
+        &expires_at,
+    )
+    .await
+    .map_err(|_| Error::DbInsertFailed)?;
+
+    // nobody knows the secret of a fresh token, so it works as an unguessable password
+    let locked_passwd = passwd_encrypt(PasswdResetToken::new().secret, gen_salt())?;
+
+    db::user::update_passwd(&state.db, &user.id, &locked_passwd)
+        .await
+        .map_err(|_| Error::DbUpdateFailed)?;
+
+    db::session::revoke_all_by_user_id(&state.db, &user.id)
+        .await
+        .map_err(|_| Error::DbUpdateFailed)?;
+
+    Ok(Json(json!({
+            "result": {
+                "success": true,
+                "token": token.to_string(),
+                "expires_at": expires_at,
+            }
+        }
+    )))
+}
+
+/// Returns how many songs each user has and how much disk space their song files take
+///
+/// Songs shared by users count for each one of them, the total counts each file once
+async fn get_usage_handler(State(state): State<AppState>) -> Result<Json<Value>> {
+    tracing::debug!("ADMIN - GET USAGE HANDLER");
+
+    let users = db::user::all(&state)
+        .await
+        .map_err(|_| Error::DbSelectFailed)?;
+
+    let mut usage = Vec::with_capacity(users.len());
+    let mut counted_songs = HashSet::new();
+    let mut total_disk_usage_bytes = 0;
+
+    for user in users {
+        let song_ids = db::junctions::user_song::all_song_ids_by_user_id(&state, &user.id)
+            .await
+            .map_err(|_| Error::DbSelectFailed)?;
+
+        let mut disk_usage_bytes = 0;
+        for song_id in &song_ids {
+            let size = song_file_size(song_id).await;
+            disk_usage_bytes += size;
+
+            if counted_songs.insert(song_id.clone()) {
+                total_disk_usage_bytes += size;
+            }
+        }
+
+        usage.push(UsageResponse {
+            user_id: user.id,
+            username: user.username,
+            song_count: song_ids.len(),
+            disk_usage_bytes,
+        });
+    }
+
+    Ok(Json(json!({
+        "data": usage,
+        "total_disk_usage_bytes": total_disk_usage_bytes,
+    })))
+}
+
+/// Receives a payload of format: { dry_run }
+///
+/// Deletes the songs nobody has had for longer than the grace period right away, instead of
+/// waiting for the garbage collector, and Returns them along with the bytes their files took
+async fn gc_handler(
+    State(state): State<AppState>,
+    Json(payload): Json<GcPayload>,
+) -> Result<Json<Value>> {
+    tracing::debug!("ADMIN - GC HANDLER");
+
+    let report = jobs::gc::collect(&state, payload.dry_run)
+        .await
+        .map_err(|e| Error::GcError(e.to_string()))?;
+
+    Ok(Json(json!(ModelResponse { data: report })))
+}
+
+async fn set_disabled(state: &AppState, user_id: &str, disabled: bool) -> Result<()> {
+    let updated = db::user::set_disabled(state, user_id, disabled)
+        .await
+        .map_err(|_| Error::DbUpdateFailed)?;
+
+    if !updated {
+        return Err(Error::UserIdNotFound);
+    }
+
+    Ok(())
+}
+
+/// Size of the media file of the song, songs that were not downloaded yet take no space
+async fn song_file_size(song_id: &str) -> u64 {
+    let media_path = format!(
+        "./{}/{}.opus",
+        &config().yt_dlp_output_path,
+        media_stem(song_id)
+    );
+
+    tokio::fs::metadata(media_path)
+        .await
+        .map_or(0, |metadata| metadata.len())
+}
+
+#[derive(Debug, Deserialize)]
+struct RolePayload {
+    role: Role,
+}
+
+#[derive(Debug, Deserialize)]
+struct GcPayload {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct UserResponse {
+    id: String,
+    username: String,
+    role: String,
+    disabled: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct UsageResponse {
+    user_id: String,
+    username: String,
+    song_count: usize,
+    disk_usage_bytes: u64,
+}
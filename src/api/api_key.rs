@@ -0,0 +1,145 @@
+use super::{
+    error::{Error, Result},
+    ModelResponse,
+};
+use crate::{
+    context::{Ctx, Scope},
+    crypt::{
+        self,
+        passwd::{gen_salt, passwd_encrypt},
+        token::ApiKey,
+    },
+    db,
+    util::time::now_utc_plus_sec_str,
+    AppState,
+};
+use axum::{
+    extract::{Path, State},
+    routing::{delete, get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+pub fn router(state: AppState) -> Router {
+    Router::new()
+        .route("/keys", get(get_api_keys_handler))
+        .route("/keys", post(create_api_key_handler))
+        .route("/keys/:id", delete(delete_api_key_handler))
+        .with_state(state)
+}
+
+/// Returns every api key of the user, the keys themselves are never returned again
+async fn get_api_keys_handler(State(state): State<AppState>, ctx: Ctx) -> Result<Json<Value>> {
+    tracing::debug!("GET API KEYS HANDLER");
+
+    let api_keys: Vec<ApiKeyResponse> = db::api_key::all_by_user_id(&state, &ctx.user_id())
+        .await
+        .map_err(|_| Error::DbSelectFailed)?
+        .into_iter()
+        .map(|k| ApiKeyResponse {
+            id: k.id,
+            name: k.name,
+            scope: k.scope,
+            expires_at: k.expires_at,
+            last_used_at: k.last_used_at,
+            created_at: k.created_at,
+        })
+        .collect();
+
+    Ok(Json(json!(ModelResponse { data: api_keys })))
+}
+
+/// Receives a payload of format: { name, scope, expires_in_secs? }
+/// Creates a new api key for the user and returns it, this is the only time the key is shown
+///
+/// Api keys can not be created with another api key, the user has to be logged in
+async fn create_api_key_handler(
+    State(state): State<AppState>,
+    ctx: Ctx,
+    Json(payload): Json<CreateApiKeyPayload>,
+) -> Result<Json<Value>> {
+    tracing::debug!("CREATE API KEY HANDLER");
+
+    if ctx.session_id().is_none() {
+        return Err(Error::SessionRequired);
+    }
+
+    let CreateApiKeyPayload {
+        name,
+        scope,
+        expires_in_secs,
+    } = payload;
+
+    let key = ApiKey::new();
+    let key_hash = passwd_encrypt(key.secret.as_str(), gen_salt())?;
+    let expires_at = expires_in_secs
+        .map(now_utc_plus_sec_str)
+        .transpose()
+        .map_err(crypt::error::Error::from)?;
+
+    let stored = db::api_key::create_new(
+        &state,
+        &key.id,
+        &ctx.user_id(),
+        &name,
+        &key_hash,
+        scope.as_ref(),
+        expires_at,
+    )
+    .await
+    .map_err(|_| Error::DbInsertFailed)?;
+
+    Ok(Json(json!({
+            "result": {
+                "success": true,
+                "id": stored.id,
+                "key": key.to_string(),
+                "scope": stored.scope,
+                "expires_at": stored.expires_at,
+            }
+        }
+    )))
+}
+
+/// Revokes an api key of the user, scripts using it will stop working
+///
+/// WILL NOT delete an api key owned by another user
+async fn delete_api_key_handler(
+    State(state): State<AppState>,
+    ctx: Ctx,
+    Path(id): Path<String>,
+) -> Result<Json<Value>> {
+    tracing::debug!("DELETE API KEY HANDLER");
+
+    let deleted = db::api_key::delete(&state, &id, &ctx.user_id())
+        .await
+        .map_err(|_| Error::DbDeleteFailed)?;
+
+    if !deleted {
+        return Err(Error::ApiKeyNotFound);
+    }
+
+    Ok(Json(json!(
+        {
+        "result": "success"
+        }
+    )))
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateApiKeyPayload {
+    name: String,
+    scope: Scope,
+    expires_in_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct ApiKeyResponse {
+    id: String,
+    name: String,
+    scope: String,
+    expires_at: Option<String>,
+    last_used_at: Option<String>,
+    created_at: String,
+}
@@ -0,0 +1,91 @@
+use super::error::Result;
+use crate::{
+    api::error::Error,
+    util::{
+        art::{art_path, ART_SIZES},
+        source::Source,
+    },
+    AppState,
+};
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{
+        header::{CACHE_CONTROL, CONTENT_TYPE},
+        HeaderValue, Request, StatusCode,
+    },
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use serde::Deserialize;
+use tower::util::ServiceExt;
+use tower_http::services::ServeFile;
+
+/// Covers never change once downloaded
+const ART_CACHE_CONTROL: &str = "public, max-age=604800";
+/// Served for songs that have no cover yet, so clients can always show something
+const PLACEHOLDER_SVG: &str = r##"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 512 512"><rect width="512" height="512" fill="#2a2a2a"/><circle cx="256" cy="256" r="150" fill="#1a1a1a"/><circle cx="256" cy="256" r="40" fill="#5a5a5a"/></svg>"##;
+
+pub fn router(state: AppState) -> Router {
+    Router::new()
+        .route("/art/:song_id", get(art_handler))
+        .with_state(state)
+}
+
+/// Returns the cover of a song, ?size= picks one of the square variants, without it the cover is
+/// returned as downloaded
+///
+/// Songs without a cover get a placeholder image with 404 Not Found
+// TODO: Use ctx here, same as stream
+async fn art_handler(
+    State(_state): State<AppState>,
+    Path(song_id): Path<String>,
+    Query(params): Query<ArtParams>,
+    request: Request<Body>,
+) -> Result<Response> {
+    tracing::debug!("GET ART HANDLER");
+
+    if params.size.is_some_and(|size| !ART_SIZES.contains(&size)) {
+        return Err(Error::InvalidRestParameter);
+    }
+
+    // the id ends up in a path
+    if Source::from_song_id(&song_id).is_err() {
+        return Ok(placeholder());
+    }
+
+    let serve_file = ServeFile::new(art_path(&song_id, params.size))
+        .oneshot(request)
+        .await
+        .map_err(|_| Error::IOError)?;
+
+    if serve_file.status() == StatusCode::NOT_FOUND {
+        return Ok(placeholder());
+    }
+
+    let mut response = serve_file.into_response();
+    response
+        .headers_mut()
+        .insert(CACHE_CONTROL, HeaderValue::from_static(ART_CACHE_CONTROL));
+
+    Ok(response)
+}
+
+fn placeholder() -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        [
+            (CONTENT_TYPE, "image/svg+xml"),
+            // the cover may show up once the song is downloaded
+            (CACHE_CONTROL, "no-cache"),
+        ],
+        PLACEHOLDER_SVG,
+    )
+        .into_response()
+}
+
+#[derive(Debug, Deserialize)]
+struct ArtParams {
+    size: Option<u32>,
+}
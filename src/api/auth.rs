@@ -1,61 +1,321 @@
-use super::{error::Error, error::Result, gen_and_set_token_cookie, remove_token_cookie};
+use super::{
+    error::Error,
+    error::Result,
+    gen_refresh_token,
+    login_throttle::{self, Subject},
+    mw::REFRESH_TOKEN,
+    remove_refresh_token_cookie, remove_token_cookie, set_refresh_token_cookie, set_token_cookie,
+    totp,
+};
 use crate::{
-    crypt::passwd::{gen_salt, passwd_encrypt, verify_encrypted_passwd},
-    db, AppState,
+    conf::SignupMode,
+    config,
+    context::Ctx,
+    crypt::{
+        self,
+        passwd::{gen_salt, passwd_encrypt, verify_encrypted_passwd},
+        token::{issue_access_token, MfaToken, PasswdResetToken, RefreshToken},
+        totp::RecoveryCode,
+    },
+    db,
+    util::time::{now_utc, now_utc_plus_sec_str, parse_utc, utc_time_to_str},
+    AppState,
+};
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{header::USER_AGENT, HeaderMap},
+    routing::post,
+    Json, Router,
 };
-use axum::{extract::State, routing::post, Json, Router};
+use sea_orm::SqlErr;
 use serde::Deserialize;
 use serde_json::{json, Value};
+use std::{net::SocketAddr, sync::OnceLock};
 use tower_cookies::Cookies;
 
+const USERNAME_MIN_LEN: usize = 3;
+const USERNAME_MAX_LEN: usize = 32;
+
 pub fn router(state: AppState) -> Router {
     Router::new()
         .route("/login", post(login_handler))
+        .route("/login/mfa", post(login_mfa_handler))
         .route("/signup", post(signup_handler))
         .route("/logout", post(logout_handler))
+        .route("/refresh", post(refresh_handler))
+        .route("/password-reset", post(passwd_reset_handler))
         .with_state(state)
 }
 
-/// Receives a payload of format: { username, passwd }
+/// Receives a payload of format: { username, passwd, token_delivery? }
 /// Checks if user exists and it's password is correct
-/// If everything goes fine, generates an access token for said user and stores it on the cookies
+/// If everything goes fine, starts a new session and generates an access token and a refresh
+/// token for said user
+///
+/// The tokens are stored on the cookies by default, clients that can't keep cookies may ask for
+/// them in the response body instead (or as well) with token_delivery
+///
+/// Failed logins are counted per username and per ip, going over the limits of the config locks
+/// the username or the ip out for a while
+///
+/// Users with two-factor authentication on get an mfa token instead of the tokens, see
+/// login_mfa_handler
 async fn login_handler(
     State(state): State<AppState>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
     cookies: Cookies,
-    Json(payload): Json<AuthPayload>,
+    Json(payload): Json<LoginPayload>,
 ) -> Result<Json<Value>> {
     tracing::debug!("LOGIN HANDLER");
 
-    let AuthPayload { username, pwd } = payload;
+    let LoginPayload {
+        username,
+        pwd,
+        token_delivery,
+    } = payload;
+
+    let ip = connect_info.map(|ConnectInfo(addr)| addr.ip().to_string());
+    let normalized_username = db::user::normalize_username(&username);
+
+    let mut subjects = vec![Subject::Username(&normalized_username)];
+    if let Some(ip) = &ip {
+        subjects.push(Subject::Ip(ip));
+    }
+
+    login_throttle::check_lockout(&state, &subjects).await?;
 
     let user = match db::user::first_by_username(&state, &username).await {
-        Ok(u) => u.ok_or(Error::UserNotFound)?,
+        Ok(u) => u,
         Err(_) => return Err(Error::DbSelectFailed),
     };
 
-    let is_passwd_correct = verify_encrypted_passwd(pwd, user.passwd.as_str())?;
+    // unknown users go through a password check as well, so the response time does not tell
+    // which usernames exist
+    let is_passwd_correct = match &user {
+        Some(user) => verify_encrypted_passwd(pwd, user.passwd.as_str())?,
+        None => {
+            verify_encrypted_passwd(pwd, dummy_passwd_hash())?;
+            false
+        }
+    };
+
+    let Some(user) = user.filter(|_| is_passwd_correct) else {
+        for subject in &subjects {
+            login_throttle::record_failure(&state, subject).await?;
+        }
 
-    if !is_passwd_correct {
         return Err(Error::IncorrectPasswd);
+    };
+
+    if user.disabled {
+        return Err(Error::AccountDisabled);
+    }
+
+    // the failures are only cleared once the code is checked as well, otherwise the password
+    // alone would be enough to keep guessing codes
+    if totp::enabled_totp(&state, &user.id).await?.is_some() {
+        return start_mfa_challenge(&state, &user.id).await;
     }
 
-    gen_and_set_token_cookie(&cookies, &user.id).await?;
+    // the ip count is kept, otherwise logging into an own account would reset it
+    login_throttle::clear_failures(&state, &subjects[0]).await?;
+
+    start_session(&state, &user.id, ip, &headers, &cookies, token_delivery).await
+}
+
+/// Receives a payload of format: { mfa_token, code?, recovery_code?, token_delivery? }
+/// Second step of the login of users with two-factor authentication on, trades the mfa token
+/// given by /login and a totp code (or an unused recovery code) for the access and refresh tokens
+///
+/// Wrong codes count as failed logins of the user, so they are locked out the same way
+async fn login_mfa_handler(
+    State(state): State<AppState>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    cookies: Cookies,
+    Json(payload): Json<LoginMfaPayload>,
+) -> Result<Json<Value>> {
+    tracing::debug!("LOGIN MFA HANDLER");
+
+    let LoginMfaPayload {
+        mfa_token,
+        code,
+        recovery_code,
+        token_delivery,
+    } = payload;
+
+    let mfa_token: MfaToken = mfa_token.parse()?;
+
+    let challenge = db::mfa_challenge::first_by_id(&state, &mfa_token.id)
+        .await
+        .map_err(|_| Error::DbSelectFailed)?
+        .filter(|c| c.token_hash == mfa_token.hashed_secret() && !c.used)
+        .ok_or(Error::InvalidMfaToken)?;
+
+    let expiration_time = parse_utc(&challenge.expires_at).map_err(|_| Error::InvalidMfaToken)?;
+    if expiration_time < now_utc() {
+        return Err(Error::InvalidMfaToken);
+    }
+
+    let user = db::user::first_by_id(&state, &challenge.user_id)
+        .await
+        .map_err(|_| Error::DbSelectFailed)?
+        .ok_or(Error::InvalidMfaToken)?;
+
+    let ip = connect_info.map(|ConnectInfo(addr)| addr.ip().to_string());
+
+    let mut subjects = vec![Subject::Username(&user.normalized_username)];
+    if let Some(ip) = &ip {
+        subjects.push(Subject::Ip(ip));
+    }
+
+    login_throttle::check_lockout(&state, &subjects).await?;
+
+    let totp = totp::enabled_totp(&state, &user.id)
+        .await?
+        .ok_or(Error::InvalidMfaToken)?;
+
+    if user.disabled {
+        return Err(Error::AccountDisabled);
+    }
+
+    let is_recovery = code.is_none();
+
+    let is_code_valid = match (code, recovery_code) {
+        (Some(code), _) => match totp::verify_totp_code(&state, &totp, &code).await {
+            Err(Error::InvalidTotpCode) => false,
+            result => result.map(|_| true)?,
+        },
+        (None, Some(recovery_code)) => {
+            let unused_codes = db::recovery_code::all_unused_by_user_id(&state, &user.id)
+                .await
+                .map_err(|_| Error::DbSelectFailed)?;
+
+            match unused_codes
+                .iter()
+                .find(|c| RecoveryCode::verify(&recovery_code, &c.code_hash))
+            {
+                // the challenge is marked used along with the recovery code, not after it
+                Some(code) => {
+                    db::mfa_challenge::mark_used_with_recovery_code(&state, &challenge.id, &code.id)
+                        .await
+                        .map_err(|_| Error::DbUpdateFailed)?
+                        .ok_or(Error::InvalidMfaToken)?
+                }
+                None => false,
+            }
+        }
+        (None, None) => {
+            return Err(Error::InvalidPayload(
+                "Either code or recovery_code is required".to_string(),
+            ))
+        }
+    };
+
+    if !is_code_valid {
+        for subject in &subjects {
+            login_throttle::record_failure(&state, subject).await?;
+        }
+
+        return Err(Error::InvalidTotpCode);
+    }
+
+    if !is_recovery {
+        let was_unused = db::mfa_challenge::mark_used(&state, &challenge.id)
+            .await
+            .map_err(|_| Error::DbUpdateFailed)?;
+
+        if !was_unused {
+            return Err(Error::InvalidMfaToken);
+        }
+    }
+
+    login_throttle::clear_failures(&state, &subjects[0]).await?;
+
+    start_session(&state, &user.id, ip, &headers, &cookies, token_delivery).await
+}
+
+/// Starts a pending login that needs a totp code to be completed, and returns the mfa token the
+/// client has to send to /login/mfa along with the code
+async fn start_mfa_challenge(state: &AppState, user_id: &str) -> Result<Json<Value>> {
+    let mfa_token = MfaToken::new();
+    let expires_at = now_utc_plus_sec_str(config().mfa_token_duration_secs)
+        .map_err(crypt::error::Error::from)?;
+
+    db::mfa_challenge::create_new(
+        state,
+        &mfa_token.id,
+        user_id,
+        &mfa_token.hashed_secret(),
+        &expires_at,
+    )
+    .await
+    .map_err(|_| Error::DbInsertFailed)?;
 
     Ok(Json(json!({
             "result": {
-                "success": true
+                "success": false,
+                "mfa_required": true,
+                "mfa_token": mfa_token.to_string(),
+                "expires_in": config().mfa_token_duration_secs,
             }
         }
     )))
 }
 
+/// Starts a new session for the user and delivers its access and refresh tokens
+async fn start_session(
+    state: &AppState,
+    user_id: &str,
+    ip: Option<String>,
+    headers: &HeaderMap,
+    cookies: &Cookies,
+    token_delivery: TokenDelivery,
+) -> Result<Json<Value>> {
+    let device = headers
+        .get(USER_AGENT)
+        .and_then(|h| h.to_str().ok())
+        .map(|h| h.to_string());
+    let now = utc_time_to_str(now_utc()).map_err(crypt::error::Error::from)?;
+    let expires_at = now_utc_plus_sec_str(config().refresh_token_duration_secs)
+        .map_err(crypt::error::Error::from)?;
+
+    let session = db::session::create_new(state, user_id, device, ip, &now, &expires_at)
+        .await
+        .map_err(|_| Error::DbInsertFailed)?;
+
+    let tokens = IssuedTokens {
+        access_token: issue_access_token(user_id, &session.id)?,
+        refresh_token: gen_refresh_token(state, user_id, &session.id)
+            .await?
+            .to_string(),
+    };
+
+    Ok(Json(deliver_tokens(cookies, tokens, token_delivery)))
+}
+
+/// Receives a payload of format: { username, pwd, invite_code? }
+/// Creates a new user if the signup_mode of the config allows it:
+/// - open: anyone can sign up;
+/// - invite: a valid invite code is required, and one of its uses is consumed;
+/// - closed: nobody can sign up.
+///
+/// The first user of the server can always sign up and becomes an admin, so a fresh install can
+/// be managed without touching the database
 async fn signup_handler(
     State(state): State<AppState>,
-    Json(payload): Json<AuthPayload>,
+    Json(payload): Json<SignupPayload>,
 ) -> Result<Json<Value>> {
     tracing::debug!("SIGNUP HANDLER");
 
-    let AuthPayload { username, pwd } = payload;
+    let SignupPayload {
+        username,
+        pwd,
+        invite_code,
+    } = payload;
+
+    validate_username(&username)?;
 
     let user_already_exist = match db::user::first_by_username(&state, &username).await {
         Ok(u) => u.is_some(),
@@ -66,24 +326,113 @@ async fn signup_handler(
         return Err(Error::UserAlreadyExists);
     }
 
+    let admission = match config().signup_mode {
+        SignupMode::Open => db::user::Admission::Open,
+        SignupMode::Closed => db::user::Admission::Closed,
+        SignupMode::Invite => db::user::Admission::Invite(invite_code.as_deref()),
+    };
+
     let hashed_pwd = passwd_encrypt(pwd, gen_salt())?;
 
-    db::user::create_new_user(&state, &username, &hashed_pwd)
+    // the check above can race with another signup, the unique index has the final word
+    let role = db::user::create_signed_up_user(&state, &username, &hashed_pwd, admission)
         .await
-        .map_err(|_| Error::DbInsertFailed)?;
+        .map_err(|e| match e.sql_err() {
+            Some(SqlErr::UniqueConstraintViolation(_)) => Error::UserAlreadyExists,
+            _ => Error::DbInsertFailed,
+        })?
+        .map_err(|refusal| match refusal {
+            db::user::Refusal::SignupClosed => Error::SignupClosed,
+            db::user::Refusal::InviteCodeRequired => Error::InviteCodeRequired,
+            db::user::Refusal::InvalidInviteCode => Error::InvalidInviteCode,
+        })?;
 
     Ok(Json(json!({
             "result": {
-                "success": true
+                "success": true,
+                "role": role
             }
         }
     )))
 }
 
-async fn logout_handler(cookies: Cookies) -> Result<Json<Value>> {
+/// Hash of a random password, checked against on logins of unknown users so they take as long as
+/// the ones of existing users
+fn dummy_passwd_hash() -> &'static str {
+    static DUMMY_PASSWD_HASH: OnceLock<String> = OnceLock::new();
+
+    DUMMY_PASSWD_HASH.get_or_init(|| {
+        passwd_encrypt(RefreshToken::new().secret, gen_salt())
+            .unwrap_or_else(|err| panic!("FATAL - COULD NOT HASH DUMMY PASSWORD - CAUSE: {err:?}"))
+    })
+}
+
+/// Checks the username against the signup rules:
+/// - Between USERNAME_MIN_LEN and USERNAME_MAX_LEN characters long;
+/// - Only ASCII letters, digits, '_', '-' and '.';
+/// - Starts with a letter or a digit.
+fn validate_username(username: &str) -> Result<()> {
+    let len = username.chars().count();
+    if !(USERNAME_MIN_LEN..=USERNAME_MAX_LEN).contains(&len) {
+        return Err(Error::InvalidPayload(format!(
+            "The username must be between {USERNAME_MIN_LEN} and {USERNAME_MAX_LEN} characters long"
+        )));
+    }
+
+    let is_valid_char = |c: char| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.');
+    if !username.chars().all(is_valid_char) {
+        return Err(Error::InvalidPayload(
+            "The username can only contain letters, digits, '_', '-' and '.'".to_string(),
+        ));
+    }
+
+    if !username.starts_with(|c: char| c.is_ascii_alphanumeric()) {
+        return Err(Error::InvalidPayload(
+            "The username must start with a letter or a digit".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Removes the token cookies and revokes the current session, if any
+///
+/// The session is found through the access token or, if it already expired, the refresh token
+async fn logout_handler(
+    State(state): State<AppState>,
+    ctx: Result<Ctx>,
+    cookies: Cookies,
+) -> Result<Json<Value>> {
     tracing::debug!("LOGOUT HANDLER");
 
+    // requests authenticated with an api key have no session to revoke
+    let current_session = ctx.ok().and_then(|ctx| {
+        ctx.session_id()
+            .map(|session_id| (session_id, ctx.user_id()))
+    });
+
+    if let Some((session_id, user_id)) = current_session {
+        db::session::revoke(&state, &session_id, &user_id)
+            .await
+            .map_err(|_| Error::DbUpdateFailed)?;
+    } else if let Some(Ok(token)) = cookies
+        .get(REFRESH_TOKEN)
+        .map(|c| c.value().parse::<RefreshToken>())
+    {
+        let stored = db::refresh_token::first_by_id(&state, &token.id)
+            .await
+            .map_err(|_| Error::DbSelectFailed)?;
+
+        // the hash check keeps a forged cookie from logging out someone else
+        if let Some(stored) = stored.filter(|t| t.token_hash == token.hashed_secret()) {
+            db::session::revoke(&state, &stored.family_id, &stored.user_id)
+                .await
+                .map_err(|_| Error::DbUpdateFailed)?;
+        }
+    }
+
     remove_token_cookie(&cookies).await;
+    remove_refresh_token_cookie(&cookies).await;
 
     Ok(Json(json!({
             "result": {
@@ -93,8 +442,228 @@ async fn logout_handler(cookies: Cookies) -> Result<Json<Value>> {
     )))
 }
 
+/// Exchanges a refresh token for a new access token
+///
+/// The refresh token is read from the payload of format: { refresh_token } or, if there is none,
+/// from the refresh-token cookie. The new tokens are sent back the same way they were received
+///
+/// The used refresh token is revoked and replaced by a new one of the same family (rotation).
+/// If a token that was already rotated shows up again, it was probably stolen, so the whole
+/// session is revoked and both the attacker and the legitimate client have to log in again
+async fn refresh_handler(
+    State(state): State<AppState>,
+    cookies: Cookies,
+    payload: Option<Json<RefreshPayload>>,
+) -> Result<Json<Value>> {
+    tracing::debug!("REFRESH HANDLER");
+
+    let body_token = payload.and_then(|Json(p)| p.refresh_token);
+    let token_delivery = match body_token {
+        Some(_) => TokenDelivery::Body,
+        None => TokenDelivery::Cookie,
+    };
+
+    let token_str = match body_token {
+        Some(token_str) => Ok(token_str),
+        None => cookies
+            .get(REFRESH_TOKEN)
+            .map(|c| c.value().to_string())
+            .ok_or(Error::NoRefreshToken),
+    };
+
+    let result = match token_str {
+        Ok(token_str) => rotate_refresh_token(&state, &token_str).await,
+        Err(e) => Err(e),
+    };
+
+    // If the client sends an invalid cookie, we want to remove it
+    if let Err(ref e) = result {
+        if token_delivery.uses_cookies() && !matches!(*e, Error::NoRefreshToken) {
+            remove_refresh_token_cookie(&cookies).await;
+        }
+    }
+
+    Ok(Json(deliver_tokens(&cookies, result?, token_delivery)))
+}
+
+async fn rotate_refresh_token(state: &AppState, token_str: &str) -> Result<IssuedTokens> {
+    let token: RefreshToken = token_str.parse()?;
+
+    let stored = db::refresh_token::first_by_id(state, &token.id)
+        .await
+        .map_err(|_| Error::DbSelectFailed)?
+        .ok_or(Error::InvalidRefreshToken)?;
+
+    if stored.token_hash != token.hashed_secret() {
+        return Err(Error::InvalidRefreshToken);
+    }
+
+    // tokens of a revoked session are already revoked too, that is not a reuse
+    db::session::first_active_by_id(state, &stored.family_id, &stored.user_id)
+        .await
+        .map_err(|_| Error::DbSelectFailed)?
+        .ok_or(Error::InvalidRefreshToken)?;
+
+    let was_active = db::refresh_token::revoke(state, &stored.id)
+        .await
+        .map_err(|_| Error::DbUpdateFailed)?;
+
+    if !was_active {
+        tracing::warn!(
+            "REFRESH TOKEN REUSE DETECTED - REVOKING SESSION {} OF USER {}",
+            stored.family_id,
+            stored.user_id
+        );
+
+        db::session::revoke(state, &stored.family_id, &stored.user_id)
+            .await
+            .map_err(|_| Error::DbUpdateFailed)?;
+
+        return Err(Error::RefreshTokenReused);
+    }
+
+    let expiration_time = parse_utc(&stored.expires_at).map_err(|_| Error::InvalidRefreshToken)?;
+    if expiration_time < now_utc() {
+        return Err(Error::InvalidRefreshToken);
+    }
+
+    // the family of a refresh token is the session it was issued for
+    let session_id = stored.family_id;
+
+    Ok(IssuedTokens {
+        access_token: issue_access_token(&stored.user_id, &session_id)?,
+        refresh_token: gen_refresh_token(state, &stored.user_id, &session_id)
+            .await?
+            .to_string(),
+    })
+}
+
+/// Receives a payload of format: { token, new_pwd }
+/// Sets a new password for the user an admin issued the reset token to
+///
+/// Reset tokens can only be used once, and every session of the user is revoked after it
+async fn passwd_reset_handler(
+    State(state): State<AppState>,
+    Json(payload): Json<PasswdResetPayload>,
+) -> Result<Json<Value>> {
+    tracing::debug!("PASSWORD RESET HANDLER");
+
+    let PasswdResetPayload { token, new_pwd } = payload;
+
+    let token: PasswdResetToken = token.parse()?;
+
+    let stored = db::passwd_reset_token::first_by_id(&state, &token.id)
+        .await
+        .map_err(|_| Error::DbSelectFailed)?
+        .filter(|t| t.token_hash == token.hashed_secret())
+        .ok_or(Error::InvalidPasswdResetToken)?;
+
+    let expiration_time =
+        parse_utc(&stored.expires_at).map_err(|_| Error::InvalidPasswdResetToken)?;
+    if expiration_time < now_utc() {
+        return Err(Error::InvalidPasswdResetToken);
+    }
+
+    let hashed_pwd = passwd_encrypt(new_pwd, gen_salt())?;
+
+    let was_unused = db::passwd_reset_token::reset_passwd(&state, &stored, &hashed_pwd)
+        .await
+        .map_err(|_| Error::DbUpdateFailed)?;
+
+    if !was_unused {
+        return Err(Error::InvalidPasswdResetToken);
+    }
+
+    Ok(Json(json!({
+            "result": {
+                "success": true
+            }
+        }
+    )))
+}
+
+/// Stores the tokens on the cookies and/or returns them in the response body
+fn deliver_tokens(cookies: &Cookies, tokens: IssuedTokens, token_delivery: TokenDelivery) -> Value {
+    if token_delivery.uses_cookies() {
+        set_token_cookie(cookies, &tokens.access_token);
+        set_refresh_token_cookie(cookies, &tokens.refresh_token);
+    }
+
+    if !token_delivery.uses_body() {
+        return json!({
+            "result": {
+                "success": true
+            }
+        });
+    }
+
+    json!({
+        "result": {
+            "success": true,
+            "token_type": "Bearer",
+            "access_token": tokens.access_token,
+            "expires_in": config().access_token_duration_secs,
+            "refresh_token": tokens.refresh_token,
+        }
+    })
+}
+
+/// Where the tokens issued on login are delivered to the client
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum TokenDelivery {
+    #[default]
+    Cookie,
+    Body,
+    Both,
+}
+
+impl TokenDelivery {
+    fn uses_cookies(&self) -> bool {
+        matches!(self, Self::Cookie | Self::Both)
+    }
+
+    fn uses_body(&self) -> bool {
+        matches!(self, Self::Body | Self::Both)
+    }
+}
+
+struct IssuedTokens {
+    access_token: String,
+    refresh_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct LoginPayload {
+    username: String,
+    pwd: String,
+    #[serde(default)]
+    token_delivery: TokenDelivery,
+}
+
+#[derive(Debug, Deserialize)]
+struct LoginMfaPayload {
+    mfa_token: String,
+    code: Option<String>,
+    recovery_code: Option<String>,
+    #[serde(default)]
+    token_delivery: TokenDelivery,
+}
+
+#[derive(Debug, Deserialize)]
+struct RefreshPayload {
+    refresh_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PasswdResetPayload {
+    token: String,
+    new_pwd: String,
+}
+
 #[derive(Debug, Deserialize)]
-struct AuthPayload {
+struct SignupPayload {
     username: String,
     pwd: String,
+    invite_code: Option<String>,
 }
@@ -1,5 +1,5 @@
 use axum::{
-    http::StatusCode,
+    http::{header::RETRY_AFTER, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
@@ -20,14 +20,40 @@ pub enum Error {
     TokenError(#[from] crypt::error::Error),
     #[error("The context is missing from the request extension! Something may have gone wrong on the token validation.")]
     CtxNotInRequestExtensions,
+    #[error("The session the token was issued for does not exist or was revoked!")]
+    SessionRevoked,
+    #[error("The provided api key does not exist or is expired!")]
+    InvalidApiKey,
+    #[error("The api key used does not have the scope required by this route!")]
+    InsufficientScope,
+    #[error("This action requires logging in, it can not be done with an api key!")]
+    SessionRequired,
+    #[error("This route is restricted to admins!")]
+    AdminRequired,
+    #[error("The account was disabled by an admin!")]
+    AccountDisabled,
+    #[error("No refresh token was provided in the request header!")]
+    NoRefreshToken,
+    #[error("The provided refresh token does not exist, is expired or was revoked!")]
+    InvalidRefreshToken,
+    #[error("A rotated refresh token was used again! Its whole family was revoked.")]
+    RefreshTokenReused,
+    #[error("The provided password reset token does not exist, is expired or was already used!")]
+    InvalidPasswdResetToken,
 
     // DB
     #[error("Entered user does not exist!")]
     UserNotFound,
+    #[error("There is no user with the entered id!")]
+    UserIdNotFound,
     #[error("Entered song does not exist")]
     SongNotFound,
     #[error("Entered playlist does not exist!")]
     PlaylistNotFound,
+    #[error("Entered session does not exist!")]
+    SessionNotFound,
+    #[error("Entered api key does not exist!")]
+    ApiKeyNotFound,
     #[error("Failed to execute the insert query in the database!")]
     DbInsertFailed,
     #[error("Failed to execute the select query in the database!")]
@@ -44,20 +70,48 @@ pub enum Error {
     InvalidPayload(String),
     #[error("Requested file not found")]
     FileNotFound,
+    #[error("The uploaded file is bigger than the server allows!")]
+    UploadTooLarge,
+    #[error("The uploaded file is not audio the server can read!")]
+    UnsupportedAudioFormat,
+    #[error("Too many searches are running, try again later!")]
+    SearchBusy,
 
     // Login
     #[error("Password does not match")]
     IncorrectPasswd,
+    #[error("Too many failed logins, try again in {0} seconds!")]
+    LoginLockedOut(u64),
+    #[error("The provided mfa token does not exist, is expired or was already used!")]
+    InvalidMfaToken,
+    #[error("The provided totp or recovery code is not valid!")]
+    InvalidTotpCode,
 
     // SIGNUP
     #[error("User with that name already exists!")]
     UserAlreadyExists,
+    #[error("Signups are closed on this server!")]
+    SignupClosed,
+    #[error("Signups require an invite code on this server!")]
+    InviteCodeRequired,
+    #[error("The provided invite code does not exist, is expired or has no uses left!")]
+    InvalidInviteCode,
+    #[error("Entered invite code does not exist!")]
+    InviteCodeNotFound,
+
+    // DOWNLOAD JOBS
+    #[error("Requested download job not found")]
+    JobNotFound,
 
     // INTERNAL
     #[error("Something went wrong while working with password encryption!")]
     PasswdCryptError(#[from] argon2::password_hash::Error),
     #[error("Something went wrong running the yt-dlp process!\nReason: {0}")]
     YtDlpError(String),
+    #[error("Something went wrong running the ffmpeg process!\nReason: {0}")]
+    FfmpegError(String),
+    #[error("Something went wrong collecting garbage!\nReason: {0}")]
+    GcError(String),
     #[error("Something went wrong when attempting IO operations!")]
     IOError,
 }
@@ -68,15 +122,32 @@ impl IntoResponse for Error {
 
         let (status_code, client_error) = self.parse_server_error_to_client();
 
-        let client_error_body = json!({
+        let mut client_error_body = json!({
             "error": {
                 "type": client_error.as_ref(),
             }
         });
 
+        // the reason a payload was rejected is safe to share and tells the client what to fix
+        if let Self::InvalidPayload(reason) = &self {
+            client_error_body["error"]["reason"] = json!(reason);
+        }
+
+        if let Self::LoginLockedOut(retry_after_secs) = &self {
+            client_error_body["error"]["retry_after_secs"] = json!(retry_after_secs);
+        }
+
         tracing::debug!("SENT CLIENT ERROR: {client_error_body}");
 
-        (status_code, Json(client_error_body)).into_response()
+        let mut response = (status_code, Json(client_error_body)).into_response();
+
+        if let Self::LoginLockedOut(retry_after_secs) = &self {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(*retry_after_secs));
+        }
+
+        response
     }
 }
 
@@ -88,14 +159,44 @@ impl Error {
             Self::IncorrectPasswd | Self::UserNotFound => {
                 (StatusCode::UNAUTHORIZED, ClientError::LOGIN_FAIL)
             }
-            Self::SongNotFound | Self::FileNotFound | Self::PlaylistNotFound => {
-                (StatusCode::NOT_FOUND, ClientError::RESOURCE_NOT_FOUND)
+            Self::SongNotFound
+            | Self::UserIdNotFound
+            | Self::FileNotFound
+            | Self::PlaylistNotFound
+            | Self::SessionNotFound
+            | Self::ApiKeyNotFound
+            | Self::InviteCodeNotFound
+            | Self::JobNotFound => (StatusCode::NOT_FOUND, ClientError::RESOURCE_NOT_FOUND),
+            Self::InvalidPayload(..) | Self::InvalidRestParameter => {
+                (StatusCode::BAD_REQUEST, ClientError::INVALID_BODY)
             }
-            Self::InvalidPayload(..) => (StatusCode::BAD_REQUEST, ClientError::INVALID_BODY),
-            Self::NoAuthToken | Self::TokenError(..) | Self::CtxNotInRequestExtensions => {
-                (StatusCode::UNAUTHORIZED, ClientError::NO_AUTH)
+            Self::NoAuthToken
+            | Self::TokenError(..)
+            | Self::CtxNotInRequestExtensions
+            | Self::SessionRevoked
+            | Self::InvalidApiKey => (StatusCode::UNAUTHORIZED, ClientError::NO_AUTH),
+            Self::NoRefreshToken
+            | Self::InvalidRefreshToken
+            | Self::RefreshTokenReused
+            | Self::InvalidPasswdResetToken
+            | Self::InvalidMfaToken => (StatusCode::UNAUTHORIZED, ClientError::NO_AUTH),
+            Self::InvalidTotpCode => (StatusCode::UNAUTHORIZED, ClientError::INVALID_TOTP_CODE),
+            Self::InsufficientScope | Self::SessionRequired | Self::AdminRequired => {
+                (StatusCode::FORBIDDEN, ClientError::PERMISSION_DENIED)
             }
+            Self::LoginLockedOut(..) => (StatusCode::TOO_MANY_REQUESTS, ClientError::LOGIN_LOCKED),
+            Self::AccountDisabled => (StatusCode::FORBIDDEN, ClientError::ACCOUNT_DISABLED),
             Self::UserAlreadyExists => (StatusCode::CONFLICT, ClientError::USERNAME_ALREADY_USED),
+            Self::SignupClosed => (StatusCode::FORBIDDEN, ClientError::SIGNUP_CLOSED),
+            Self::InviteCodeRequired | Self::InvalidInviteCode => {
+                (StatusCode::FORBIDDEN, ClientError::INVALID_INVITE_CODE)
+            }
+            Self::UploadTooLarge => (StatusCode::PAYLOAD_TOO_LARGE, ClientError::UPLOAD_TOO_LARGE),
+            Self::SearchBusy => (StatusCode::TOO_MANY_REQUESTS, ClientError::SEARCH_BUSY),
+            Self::UnsupportedAudioFormat => (
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                ClientError::UNSUPPORTED_AUDIO_FORMAT,
+            ),
             _ => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 ClientError::SERVICE_ERROR,
@@ -108,9 +209,18 @@ impl Error {
 #[allow(non_camel_case_types)]
 pub enum ClientError {
     LOGIN_FAIL,
+    LOGIN_LOCKED,
+    INVALID_TOTP_CODE,
     NO_AUTH,
     INVALID_BODY,
     SERVICE_ERROR,
     RESOURCE_NOT_FOUND,
     USERNAME_ALREADY_USED,
+    PERMISSION_DENIED,
+    ACCOUNT_DISABLED,
+    SIGNUP_CLOSED,
+    INVALID_INVITE_CODE,
+    UPLOAD_TOO_LARGE,
+    UNSUPPORTED_AUDIO_FORMAT,
+    SEARCH_BUSY,
 }
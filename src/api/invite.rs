@@ -0,0 +1,144 @@
+use super::{
+    error::{Error, Result},
+    ModelResponse,
+};
+use crate::{
+    config,
+    context::{Ctx, Role},
+    crypt::{self, token::InviteCode},
+    db,
+    util::time::now_utc_plus_sec_str,
+    AppState,
+};
+use axum::{
+    extract::{Path, State},
+    routing::{delete, get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+pub fn router(state: AppState) -> Router {
+    Router::new()
+        .route("/invites", get(get_invites_handler))
+        .route("/invites", post(create_invite_handler))
+        .route("/invites/:code", delete(delete_invite_handler))
+        .with_state(state)
+}
+
+/// Returns every invite code created by the user
+async fn get_invites_handler(State(state): State<AppState>, ctx: Ctx) -> Result<Json<Value>> {
+    tracing::debug!("GET INVITES HANDLER");
+
+    let invites: Vec<InviteResponse> = db::invite_code::all_by_creator(&state, &ctx.user_id())
+        .await
+        .map_err(|_| Error::DbSelectFailed)?
+        .into_iter()
+        .map(|i| InviteResponse {
+            code: i.code,
+            max_uses: i.max_uses,
+            uses: i.uses,
+            expires_at: i.expires_at,
+            created_at: i.created_at,
+        })
+        .collect();
+
+    Ok(Json(json!(ModelResponse { data: invites })))
+}
+
+/// Receives a payload of format: { max_uses, expires_in_secs? }
+/// Creates a new invite code that can be used for max_uses signups
+///
+/// Admins can always create codes, users only if users_can_invite is set on the config
+async fn create_invite_handler(
+    State(state): State<AppState>,
+    ctx: Ctx,
+    Json(payload): Json<CreateInvitePayload>,
+) -> Result<Json<Value>> {
+    tracing::debug!("CREATE INVITE HANDLER");
+
+    if ctx.session_id().is_none() {
+        return Err(Error::SessionRequired);
+    }
+
+    if ctx.role() != Role::Admin && !config().users_can_invite {
+        return Err(Error::AdminRequired);
+    }
+
+    let CreateInvitePayload {
+        max_uses,
+        expires_in_secs,
+    } = payload;
+
+    if max_uses < 1 {
+        return Err(Error::InvalidPayload(
+            "max_uses must be at least 1".to_string(),
+        ));
+    }
+
+    let code = InviteCode::new();
+    let expires_at = expires_in_secs
+        .map(now_utc_plus_sec_str)
+        .transpose()
+        .map_err(crypt::error::Error::from)?;
+
+    let stored = db::invite_code::create_new(&state, &code.0, &ctx.user_id(), max_uses, expires_at)
+        .await
+        .map_err(|_| Error::DbInsertFailed)?;
+
+    Ok(Json(json!({
+            "result": {
+                "success": true,
+                "code": stored.code,
+                "max_uses": stored.max_uses,
+                "expires_at": stored.expires_at,
+            }
+        }
+    )))
+}
+
+/// Deletes an invite code so it can not be used anymore
+///
+/// WILL NOT delete a code created by another user, unless the user is an admin
+async fn delete_invite_handler(
+    State(state): State<AppState>,
+    ctx: Ctx,
+    Path(code): Path<String>,
+) -> Result<Json<Value>> {
+    tracing::debug!("DELETE INVITE HANDLER");
+
+    let user_id = ctx.user_id();
+    let created_by = match ctx.role() {
+        Role::Admin => None,
+        Role::User => Some(user_id.as_str()),
+    };
+
+    let deleted = db::invite_code::delete(&state, &code, created_by)
+        .await
+        .map_err(|_| Error::DbDeleteFailed)?;
+
+    if !deleted {
+        return Err(Error::InviteCodeNotFound);
+    }
+
+    Ok(Json(json!(
+        {
+        "result": "success"
+        }
+    )))
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateInvitePayload {
+    max_uses: i32,
+    expires_in_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct InviteResponse {
+    code: String,
+    max_uses: i32,
+    uses: i32,
+    expires_at: Option<String>,
+    created_at: String,
+}
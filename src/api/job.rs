@@ -0,0 +1,106 @@
+use super::{
+    error::{Error, Result},
+    ModelResponse,
+};
+use crate::{context::Ctx, db, jobs, AppState};
+use axum::{
+    extract::{Path, State},
+    response::sse::{Event, KeepAlive, Sse},
+    routing::get,
+    Json, Router,
+};
+use entity::download_job;
+use serde::Serialize;
+use serde_json::{json, Value};
+use tokio_stream::{wrappers::BroadcastStream, Stream, StreamExt};
+
+pub fn router(state: AppState) -> Router {
+    Router::new()
+        .route("/jobs", get(get_jobs_handler))
+        .route("/jobs/events", get(job_events_handler))
+        .route("/jobs/:id", get(get_job_handler))
+        .with_state(state)
+}
+
+/// Returns every download job of the user, newest first
+async fn get_jobs_handler(State(state): State<AppState>, ctx: Ctx) -> Result<Json<Value>> {
+    tracing::debug!("GET JOBS HANDLER");
+
+    let jobs: Vec<JobResponse> = db::download_job::all_by_user_id(&state, &ctx.user_id())
+        .await
+        .map_err(|_| Error::DbSelectFailed)?
+        .into_iter()
+        .map(JobResponse::from)
+        .collect();
+
+    Ok(Json(json!(ModelResponse { data: jobs })))
+}
+
+/// Returns a download job of the user, failed jobs carry the reason they failed
+///
+/// WILL NOT return a job requested by another user
+async fn get_job_handler(
+    State(state): State<AppState>,
+    ctx: Ctx,
+    Path(id): Path<String>,
+) -> Result<Json<Value>> {
+    tracing::debug!("GET JOB HANDLER");
+
+    let job = db::download_job::first_by_id(&state, &id, &ctx.user_id())
+        .await
+        .map_err(|_| Error::DbSelectFailed)?
+        .ok_or(Error::JobNotFound)?;
+
+    Ok(Json(json!(ModelResponse {
+        data: JobResponse::from(job),
+    })))
+}
+
+/// Streams the state changes and the download progress of the jobs of the user as Server-Sent
+/// Events, every event is a `job` event with a json payload of format:
+/// { job_id, song_id, state, error, phase?, percent?, speed_bytes_per_sec?, eta_secs? }
+///
+/// Only what happens after connecting is sent, /api/jobs has the current state of every job
+async fn job_events_handler(
+    ctx: Ctx,
+) -> Sse<impl Stream<Item = core::result::Result<Event, axum::Error>>> {
+    tracing::debug!("JOB EVENTS HANDLER");
+
+    let user_id = ctx.user_id();
+
+    // a client that falls too far behind misses some events, the stream goes on with the next ones
+    let events = BroadcastStream::new(jobs::job_events().subscribe()).filter_map(move |event| {
+        let event = event.ok().filter(|e| e.user_id == user_id)?;
+
+        Some(Event::default().event("job").json_data(event))
+    });
+
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+#[derive(Debug, Serialize)]
+pub(super) struct JobResponse {
+    id: String,
+    song_id: String,
+    state: String,
+    error: Option<String>,
+    created_at: String,
+    started_at: Option<String>,
+    finished_at: Option<String>,
+    playlist_id: Option<String>, // set for jobs queued by a playlist import
+}
+
+impl From<download_job::Model> for JobResponse {
+    fn from(job: download_job::Model) -> Self {
+        Self {
+            id: job.id,
+            song_id: job.song_id,
+            state: job.state,
+            error: job.error,
+            created_at: job.created_at,
+            started_at: job.started_at,
+            finished_at: job.finished_at,
+            playlist_id: job.playlist_id,
+        }
+    }
+}
@@ -0,0 +1,23 @@
+use crate::{crypt::jwt::Jwk, keys};
+use axum::{routing::get, Json, Router};
+use serde_json::{json, Value};
+
+pub fn router() -> Router {
+    Router::new().route("/.well-known/jwks.json", get(jwks_handler))
+}
+
+/// Publishes every key that access tokens may be signed with as a JSON Web Key Set, so other
+/// services can verify the tokens without sharing any secret
+///
+/// Retired keys are left out, tokens signed with them are no longer accepted anyway
+async fn jwks_handler() -> Json<Value> {
+    tracing::debug!("GET JWKS HANDLER");
+
+    let jwks: Vec<Jwk> = keys()
+        .verifying_keys()
+        .into_iter()
+        .map(|(kid, key)| Jwk::new(kid, key))
+        .collect();
+
+    Json(json!({ "keys": jwks }))
+}
@@ -0,0 +1,123 @@
+use super::error::{Error, Result};
+use crate::{
+    config, crypt, db,
+    util::time::{now_utc, parse_utc, utc_time_to_str},
+    AppState,
+};
+use std::time::Duration;
+
+/// What failed logins are counted against, each one is tracked and locked out on its own
+pub enum Subject<'a> {
+    Username(&'a str), // normalized, so every spelling of a name shares the same count
+    Ip(&'a str),
+}
+
+impl Subject<'_> {
+    fn key(&self) -> String {
+        match self {
+            Self::Username(username) => format!("user:{username}"),
+            Self::Ip(ip) => format!("ip:{ip}"),
+        }
+    }
+
+    fn max_failures(&self) -> u32 {
+        match self {
+            Self::Username(_) => config().login_max_failures,
+            Self::Ip(_) => config().login_max_failures_per_ip,
+        }
+    }
+}
+
+/// Returns Err(Error::LoginLockedOut) if any of the subjects is locked out
+///
+/// Attempts made during a lockout are rejected before the password is checked, so they don't
+/// count as failures and tell nothing about the password
+pub async fn check_lockout(state: &AppState, subjects: &[Subject<'_>]) -> Result<()> {
+    let now = now_utc();
+
+    for subject in subjects {
+        let Some(login_failure) = db::login_failure::first_by_key(state, &subject.key())
+            .await
+            .map_err(|_| Error::DbSelectFailed)?
+        else {
+            continue;
+        };
+
+        let Some(locked_until) = &login_failure.locked_until else {
+            continue;
+        };
+
+        let locked_until = parse_utc(locked_until).map_err(crypt::error::Error::from)?;
+
+        if locked_until > now {
+            let retry_after_secs = (locked_until - now).whole_seconds().max(1) as u64;
+            return Err(Error::LoginLockedOut(retry_after_secs));
+        }
+    }
+
+    Ok(())
+}
+
+/// Counts a failed login against the subject and locks it out once it goes over its limit
+///
+/// The first lockout lasts login_lockout_secs and every failure after it doubles the time, up to
+/// login_max_lockout_secs
+pub async fn record_failure(state: &AppState, subject: &Subject<'_>) -> Result<()> {
+    let key = subject.key();
+    let now = now_utc();
+
+    // failures older than the window start a new count
+    if let Some(login_failure) = db::login_failure::first_by_key(state, &key)
+        .await
+        .map_err(|_| Error::DbSelectFailed)?
+    {
+        let last_activity = login_failure
+            .locked_until
+            .as_deref()
+            .unwrap_or(&login_failure.last_failure_at);
+        let last_activity = parse_utc(last_activity).map_err(crypt::error::Error::from)?;
+
+        if last_activity + Duration::from_secs(config().login_failure_window_secs) < now {
+            db::login_failure::delete(state, &key)
+                .await
+                .map_err(|_| Error::DbDeleteFailed)?;
+        }
+    }
+
+    let failed_at = utc_time_to_str(now).map_err(crypt::error::Error::from)?;
+    let login_failure = db::login_failure::increment(state, &key, &failed_at)
+        .await
+        .map_err(|_| Error::DbUpdateFailed)?;
+
+    let failures = login_failure.failures.max(0) as u32;
+    let max_failures = subject.max_failures();
+
+    if failures < max_failures {
+        return Ok(());
+    }
+
+    let lockout_secs = config()
+        .login_lockout_secs
+        .saturating_mul(2u64.saturating_pow(failures - max_failures))
+        .min(config().login_max_lockout_secs);
+
+    let locked_until = utc_time_to_str(now + Duration::from_secs(lockout_secs))
+        .map_err(crypt::error::Error::from)?;
+
+    db::login_failure::set_locked_until(state, &key, &locked_until)
+        .await
+        .map_err(|_| Error::DbUpdateFailed)?;
+
+    tracing::warn!(
+        "LOGIN LOCKOUT - {key} LOCKED FOR {lockout_secs}s AFTER {failures} FAILED ATTEMPTS"
+    );
+
+    Ok(())
+}
+
+/// Forgets the failed logins of the subject, called after a successful login
+pub async fn clear_failures(state: &AppState, subject: &Subject<'_>) -> Result<()> {
+    db::login_failure::delete(state, &subject.key())
+        .await
+        .map_err(|_| Error::DbDeleteFailed)
+}
@@ -0,0 +1,119 @@
+use super::{
+    error::{Error, Result},
+    remove_refresh_token_cookie, remove_token_cookie,
+};
+use crate::{
+    context::Ctx,
+    crypt::passwd::{gen_salt, passwd_encrypt, verify_encrypted_passwd},
+    db, AppState,
+};
+use axum::{
+    extract::State,
+    routing::{delete, put},
+    Json, Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tower_cookies::Cookies;
+
+pub fn router(state: AppState) -> Router {
+    Router::new()
+        .route("/me/password", put(change_passwd_handler))
+        .route("/me", delete(delete_account_handler))
+        .with_state(state)
+}
+
+/// Receives a payload of format: { current_pwd, new_pwd }
+/// Replaces the password of the user if the current one is correct
+///
+/// Every other session of the user is revoked, the one that made the request stays logged in
+async fn change_passwd_handler(
+    State(state): State<AppState>,
+    ctx: Ctx,
+    Json(payload): Json<ChangePasswdPayload>,
+) -> Result<Json<Value>> {
+    tracing::debug!("CHANGE PASSWORD HANDLER");
+
+    let session_id = ctx.session_id().ok_or(Error::SessionRequired)?;
+
+    let ChangePasswdPayload {
+        current_pwd,
+        new_pwd,
+    } = payload;
+
+    check_passwd(&state, &ctx.user_id(), current_pwd).await?;
+
+    let hashed_pwd = passwd_encrypt(new_pwd, gen_salt())?;
+
+    db::user::update_passwd(&state.db, &ctx.user_id(), &hashed_pwd)
+        .await
+        .map_err(|_| Error::DbUpdateFailed)?;
+
+    db::session::revoke_all_other_by_user_id(&state, &ctx.user_id(), &session_id)
+        .await
+        .map_err(|_| Error::DbUpdateFailed)?;
+
+    Ok(Json(json!({
+            "result": {
+                "success": true
+            }
+        }
+    )))
+}
+
+/// Receives a payload of format: { pwd }
+/// Deletes the user along with their songs, playlists, sessions and api keys
+///
+/// The password is asked again so a stolen session is not enough to delete the account
+async fn delete_account_handler(
+    State(state): State<AppState>,
+    ctx: Ctx,
+    cookies: Cookies,
+    Json(payload): Json<DeleteAccountPayload>,
+) -> Result<Json<Value>> {
+    tracing::debug!("DELETE ACCOUNT HANDLER");
+
+    if ctx.session_id().is_none() {
+        return Err(Error::SessionRequired);
+    }
+
+    check_passwd(&state, &ctx.user_id(), payload.pwd).await?;
+
+    db::user::delete_with_data(&state, &ctx.user_id())
+        .await
+        .map_err(|_| Error::DbDeleteFailed)?;
+
+    remove_token_cookie(&cookies).await;
+    remove_refresh_token_cookie(&cookies).await;
+
+    Ok(Json(json!({
+            "result": {
+                "success": true
+            }
+        }
+    )))
+}
+
+pub(super) async fn check_passwd(state: &AppState, user_id: &str, pwd: String) -> Result<()> {
+    let user = db::user::first_by_id(state, user_id)
+        .await
+        .map_err(|_| Error::DbSelectFailed)?
+        .ok_or(Error::UserNotFound)?;
+
+    if !verify_encrypted_passwd(pwd, &user.passwd)? {
+        return Err(Error::IncorrectPasswd);
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+struct ChangePasswdPayload {
+    current_pwd: String,
+    new_pwd: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeleteAccountPayload {
+    pwd: String,
+}
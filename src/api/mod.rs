@@ -1,13 +1,33 @@
+pub mod admin;
+pub mod api_key;
+pub mod art;
 pub mod auth;
 mod error;
+pub mod invite;
+pub mod job;
+pub mod jwks;
+mod login_throttle;
+pub mod me;
 pub mod mw;
 pub mod playlist;
+pub mod search;
+pub mod session;
 pub mod song;
 pub mod stream;
+pub mod totp;
 
-use crate::crypt::token::Token;
-use error::Result;
-use mw::AUTH_TOKEN;
+use crate::{
+    config,
+    crypt::{
+        self,
+        token::{issue_access_token, RefreshToken},
+    },
+    db,
+    util::time::now_utc_plus_sec_str,
+    AppState,
+};
+use error::{Error, Result};
+use mw::{AUTH_TOKEN, REFRESH_TOKEN};
 use serde::{Deserialize, Serialize};
 use tower_cookies::{Cookie, Cookies};
 
@@ -16,19 +36,26 @@ pub struct ModelResponse<T> {
     pub data: T,
 }
 
-/// Receives a cookie jar ref and an identifier
+/// Receives a cookie jar ref, an identifier and the session the token belongs to
 /// Generates an access token for said identifier and stores it on the jar
 /// Returns Err if token generation fails
-async fn gen_and_set_token_cookie(cookies: &Cookies, identifier: &str) -> Result<()> {
-    let token = Token::new_access_token(identifier)?;
+async fn gen_and_set_token_cookie(
+    cookies: &Cookies,
+    identifier: &str,
+    session_id: &str,
+) -> Result<()> {
+    let token = issue_access_token(identifier, session_id)?;
 
-    // sets access token to cookies
+    set_token_cookie(cookies, &token);
+
+    Ok(())
+}
+
+fn set_token_cookie(cookies: &Cookies, token: &str) {
     let mut cookie = Cookie::new(AUTH_TOKEN, token.to_string());
     cookie.set_http_only(true);
     cookie.set_path("/");
     cookies.add(cookie);
-
-    Ok(())
 }
 
 async fn remove_token_cookie(cookies: &Cookies) {
@@ -37,3 +64,51 @@ async fn remove_token_cookie(cookies: &Cookies) {
 
     cookies.remove(cookie);
 }
+
+/// Generates a refresh token for the session and stores its hash in the database
+/// The session expiration is pushed to match the new token
+async fn gen_refresh_token(
+    state: &AppState,
+    user_id: &str,
+    session_id: &str,
+) -> Result<RefreshToken> {
+    let duration = config().refresh_token_duration_secs;
+    let token = RefreshToken::new();
+    let expires_at = now_utc_plus_sec_str(duration).map_err(crypt::error::Error::from)?;
+
+    // the session id is used as the family of all refresh tokens issued for it
+    db::refresh_token::create_new(
+        state,
+        &token.id,
+        user_id,
+        session_id,
+        &token.hashed_secret(),
+        &expires_at,
+    )
+    .await
+    .map_err(|_| Error::DbInsertFailed)?;
+
+    db::session::extend(state, session_id, &expires_at)
+        .await
+        .map_err(|_| Error::DbUpdateFailed)?;
+
+    Ok(token)
+}
+
+/// Unlike the access token cookie, this one has a max age so it survives the client being closed
+fn set_refresh_token_cookie(cookies: &Cookies, token: &str) {
+    let duration = config().refresh_token_duration_secs;
+
+    let mut cookie = Cookie::new(REFRESH_TOKEN, token.to_string());
+    cookie.set_http_only(true);
+    cookie.set_path("/api");
+    cookie.set_max_age(time::Duration::seconds(duration as i64));
+    cookies.add(cookie);
+}
+
+async fn remove_refresh_token_cookie(cookies: &Cookies) {
+    let mut cookie = Cookie::from(REFRESH_TOKEN);
+    cookie.set_path("/api");
+
+    cookies.remove(cookie);
+}
@@ -1,18 +1,33 @@
 use super::{
     error::{Error, Result},
-    gen_and_set_token_cookie, remove_token_cookie, AUTH_TOKEN,
+    gen_and_set_token_cookie, remove_token_cookie, AUTH_TOKEN, AUTH_TOKEN_HEADER,
+};
+use crate::{
+    context::{Ctx, Role, Scope},
+    crypt::{
+        self,
+        passwd::verify_encrypted_passwd,
+        token::{issue_access_token, verify_access_token, ApiKey},
+    },
+    db,
+    util::time::{now_utc, parse_utc, utc_time_to_str},
+    AppState,
 };
-use crate::{context::Ctx, crypt::token::Token, keys};
 use async_trait::async_trait;
 use axum::{
     body::Body,
-    extract::FromRequestParts,
-    http::{request::Parts, Request},
+    extract::{ConnectInfo, FromRequestParts, OriginalUri, State},
+    http::{header::AUTHORIZATION, request::Parts, HeaderMap, HeaderValue, Method, Request},
     middleware::Next,
     response::Response,
 };
+use std::{net::SocketAddr, time::Duration};
 use tower_cookies::Cookies;
 
+/// How long a session has to go unseen before its last_seen_at column is written again
+/// Keeps the ctx resolver from hitting the database with an UPDATE on every request
+const SESSION_TOUCH_INTERVAL: Duration = Duration::from_secs(60);
+
 // This middleware is useful to restrict access to routes only to authenticated users
 // When an extractor is wrapped in Result, axum will not immediately reject the request if it does
 // not match
@@ -23,26 +38,78 @@ pub async fn ctx_require_auth(
 ) -> Result<Response> {
     tracing::debug!("MIDDLEWARE - REQUIRE_AUTHENTICATION_CTX");
 
-    ctx?;
+    let ctx = ctx?;
+
+    // nested routers only see the path after the /api prefix, the original uri has all of it
+    let path = match request.extensions().get::<OriginalUri>() {
+        Some(OriginalUri(uri)) => uri.path(),
+        None => request.uri().path(),
+    };
+
+    if !scope_allows(ctx.scope(), request.method(), path) {
+        return Err(Error::InsufficientScope);
+    }
+
+    Ok(next.run(request).await)
+}
+
+/// Restricts routes to admins, must be layered after ctx_require_auth so the scope of api keys is
+/// still checked
+pub async fn ctx_require_admin(
+    ctx: Result<Ctx>,
+    request: Request<Body>,
+    next: Next,
+) -> Result<Response> {
+    tracing::debug!("MIDDLEWARE - REQUIRE_ADMIN_CTX");
+
+    if ctx?.role() != Role::Admin {
+        return Err(Error::AdminRequired);
+    }
 
     Ok(next.run(request).await)
 }
 
-/// Middleware for extracting token cookie from request header and returning a context
+/// Checks if a context with the given scope can make the request
+///
+/// read-only keys can only read, add-songs keys can also add songs to the library
+fn scope_allows(scope: Scope, method: &Method, path: &str) -> bool {
+    if scope == Scope::Full || method == Method::GET || method == Method::HEAD {
+        return true;
+    }
+
+    scope == Scope::AddSongs && method == Method::POST && path.trim_end_matches('/') == "/api/songs"
+}
+
+/// Middleware for extracting the token from the request header and returning a context
+/// The token is read from the Authorization header as a Bearer token or, if there is none, from
+/// the auth-token cookie
 /// Also refreshes token if valid or removes it if invalid
+///
+/// Clients using the Authorization header get the refreshed token in the x-auth-token response
+/// header, since they do not keep cookies
 pub async fn ctx_resolver(
+    State(state): State<AppState>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
     cookies: Cookies,
     mut req: Request<Body>,
     next: Next,
 ) -> Result<Response> {
     tracing::debug!("MIDDLEWARE - CTX_RESOLVER");
 
-    let ctx = verify_and_refresh_token(&cookies).await;
+    let ip = connect_info.map(|ConnectInfo(addr)| addr.ip().to_string());
+    let bearer = bearer_token(req.headers());
+    let uses_bearer = bearer.is_some();
+
+    let (ctx, refreshed_token) = match verify_and_refresh_token(&state, &cookies, bearer, ip).await
+    {
+        Ok((ctx, refreshed_token)) => (Ok(ctx), refreshed_token),
+        Err(e) => (Err(e), None),
+    };
 
     // If the client sends an invalid cookie, we want to remove it
     // these if statements take care of it
     if let Err(ref e) = ctx {
-        if !matches!(*e, Error::NoAuthToken) {
+        if !uses_bearer && !matches!(*e, Error::NoAuthToken) {
             tracing::debug!("MIDDLEWARE - CTX_RESOLVER - REMOVING INVALID COOKIE FROM HEADER");
             remove_token_cookie(&cookies).await;
         }
@@ -51,24 +118,139 @@ pub async fn ctx_resolver(
     // Store the ctx_result in the request extension.
     req.extensions_mut().insert(ctx);
 
-    Ok(next.run(req).await)
+    let mut response = next.run(req).await;
+
+    if let Some(header) = refreshed_token.and_then(|t| HeaderValue::from_str(&t).ok()) {
+        response.headers_mut().insert(AUTH_TOKEN_HEADER, header);
+    }
+
+    Ok(response)
 }
 
-async fn verify_and_refresh_token(cookies: &Cookies) -> Result<Ctx> {
-    // extracts auth token from cookies as a string
-    let token_str = cookies
-        .get(AUTH_TOKEN)
-        .map(|c| c.value().to_string())
-        .ok_or(Error::NoAuthToken)?;
+/// Returns the token of an `Authorization: Bearer <token>` header, if the request has one
+fn bearer_token(headers: &HeaderMap) -> Option<String> {
+    let (scheme, token) = headers
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .trim()
+        .split_once(' ')?;
+
+    if !scheme.eq_ignore_ascii_case("Bearer") {
+        return None;
+    }
+
+    Some(token.trim().to_string())
+}
+
+/// Returns the context of the token and, if it came from the Authorization header, the refreshed
+/// token that should be sent back in the response header
+async fn verify_and_refresh_token(
+    state: &AppState,
+    cookies: &Cookies,
+    bearer: Option<String>,
+    ip: Option<String>,
+) -> Result<(Ctx, Option<String>)> {
+    let uses_bearer = bearer.is_some();
+
+    // api keys are told apart from access tokens by their prefix
+    if let Some(key_str) = bearer.as_deref().filter(|b| b.starts_with(ApiKey::PREFIX)) {
+        let ctx = verify_api_key(state, key_str).await?;
+
+        return Ok((ctx, None));
+    }
+
+    // extracts auth token from the header or the cookies as a string
+    let token_str = match bearer {
+        Some(token_str) => token_str,
+        None => cookies
+            .get(AUTH_TOKEN)
+            .map(|c| c.value().to_string())
+            .ok_or(Error::NoAuthToken)?,
+    };
 
     // if the token exists and the parse is successful, the token is then validated
-    let token: Token = token_str.parse()?;
-    token.validate(&keys().verifying_key)?;
+    let token = verify_access_token(&token_str)?;
+
+    // a valid signature is not enough, the session it was issued for must not have been revoked
+    let session = db::session::first_active_by_id(state, &token.session_id, &token.identifier)
+        .await
+        .map_err(|_| Error::DbSelectFailed)?
+        .ok_or(Error::SessionRevoked)?;
+
+    let role = active_user_role(state, &token.identifier).await?;
+
+    let now = now_utc();
+    let last_seen = parse_utc(&session.last_seen_at).unwrap_or(now);
+    if now - last_seen > SESSION_TOUCH_INTERVAL || (ip.is_some() && session.ip != ip) {
+        let now = utc_time_to_str(now).map_err(crypt::error::Error::from)?;
+
+        db::session::touch(state, &session.id, ip, &now)
+            .await
+            .map_err(|_| Error::DbUpdateFailed)?;
+    }
+
+    let ctx = Ctx::new(&token.identifier, &token.session_id, role);
 
     // refreshes access token
-    gen_and_set_token_cookie(cookies, &token.identifier).await?;
+    if uses_bearer {
+        let refreshed_token = issue_access_token(&token.identifier, &token.session_id)?;
+
+        return Ok((ctx, Some(refreshed_token)));
+    }
+
+    gen_and_set_token_cookie(cookies, &token.identifier, &token.session_id).await?;
+
+    Ok((ctx, None))
+}
+
+/// Returns the context of an api key, api keys are never refreshed
+async fn verify_api_key(state: &AppState, key_str: &str) -> Result<Ctx> {
+    let key: ApiKey = key_str.parse()?;
+
+    let stored = db::api_key::first_by_id(state, &key.id)
+        .await
+        .map_err(|_| Error::DbSelectFailed)?
+        .ok_or(Error::InvalidApiKey)?;
+
+    if !verify_encrypted_passwd(key.secret, &stored.key_hash)? {
+        return Err(Error::InvalidApiKey);
+    }
+
+    let now = now_utc();
+
+    if let Some(expires_at) = &stored.expires_at {
+        let expiration_time = parse_utc(expires_at).map_err(|_| Error::InvalidApiKey)?;
+        if expiration_time < now {
+            return Err(Error::InvalidApiKey);
+        }
+    }
+
+    let scope: Scope = stored.scope.parse().map_err(|_| Error::InvalidApiKey)?;
+    let role = active_user_role(state, &stored.user_id).await?;
+
+    let now = utc_time_to_str(now).map_err(crypt::error::Error::from)?;
+    db::api_key::touch(state, &stored.id, &now)
+        .await
+        .map_err(|_| Error::DbUpdateFailed)?;
+
+    Ok(Ctx::new_api_key(&stored.user_id, scope, role))
+}
+
+/// Returns the role of the user, read on every request so role changes and disabled accounts
+/// take effect right away
+async fn active_user_role(state: &AppState, user_id: &str) -> Result<Role> {
+    let user = db::user::first_by_id(state, user_id)
+        .await
+        .map_err(|_| Error::DbSelectFailed)?
+        .ok_or(Error::UserNotFound)?;
+
+    if user.disabled {
+        return Err(Error::AccountDisabled);
+    }
 
-    Ok(Ctx::new(&token.identifier))
+    // an unknown role gets the least privileges
+    Ok(user.role.parse().unwrap_or(Role::User))
 }
 
 // Implements FromRequestParts and not FromRequest because it does not need the request body
@@ -3,3 +3,10 @@ use super::{error, gen_and_set_token_cookie, remove_token_cookie};
 
 /// The expected name for the auth-token in the request header
 pub const AUTH_TOKEN: &str = "auth-token";
+
+/// The expected name for the refresh-token in the request header
+pub const REFRESH_TOKEN: &str = "refresh-token";
+
+/// The response header where refreshed access tokens are sent to clients that authenticate with
+/// the Authorization header instead of the auth-token cookie
+pub const AUTH_TOKEN_HEADER: &str = "x-auth-token";
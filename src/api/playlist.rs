@@ -1,19 +1,29 @@
 use super::error::{Error, Result};
-use crate::{api::ModelResponse, context::Ctx, db, AppState};
+use crate::{
+    api::ModelResponse,
+    context::Ctx,
+    db, jobs,
+    util::{self, link::parse_yt_playlist_link, source::Source, yt_dlp::YtDlpPlaylistEntry},
+    AppState,
+};
 use axum::{
     extract::{Path, State},
+    http::StatusCode,
     routing::{delete, get, post},
     Json, Router,
 };
 use entity::playlist::Model as Playlist;
-use serde::Deserialize;
+use sea_orm::SqlErr;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use std::collections::HashSet;
 
 pub fn router(state: AppState) -> Router {
     Router::new()
         .route("/playlists", get(get_playlists_handler))
         .route("/playlists/:id/songs", get(get_playlist_songs_handler))
         .route("/playlists", post(create_playlist_handler))
+        .route("/playlists/import", post(import_playlist_handler))
         .route("/playlists/:id/songs", post(add_playlist_song_handler))
         .route("/playlists/:id", delete(delete_playlist_handler))
         .route(
@@ -76,6 +86,167 @@ async fn create_playlist_handler(
     })))
 }
 
+/// Creates a playlist out of a youtube or youtube music playlist or album, with its title and the
+/// order of its songs
+///
+/// Songs that were already downloaded are added right away, the rest is queued for download and
+/// added once its job is done, in that case it Returns with 202 Accepted
+///
+/// Returns the playlist along with a report of every entry, the ones that could not be imported
+/// carry the reason
+async fn import_playlist_handler(
+    State(state): State<AppState>,
+    ctx: Ctx,
+    Json(payload): Json<ImportPlaylistPayload>,
+) -> Result<(StatusCode, Json<Value>)> {
+    tracing::debug!("IMPORT PLAYLIST HANDLER");
+
+    let ImportPlaylistPayload { link } = payload;
+    let list_id =
+        parse_yt_playlist_link(&link).map_err(|e| Error::InvalidPayload(e.to_string()))?;
+
+    let listed = state
+        .downloader
+        .list_playlist(&list_id)
+        .await
+        .map_err(|e| match e {
+            // the stderr of yt-dlp is no business of the client
+            util::error::Error::YtDlpExitCode(_, stderr) => {
+                tracing::warn!(
+                    "COULD NOT LIST PLAYLIST {list_id} - STDERR: {}",
+                    stderr.trim()
+                );
+
+                Error::InvalidPayload("The playlist does not exist or is private".to_string())
+            }
+            e => Error::YtDlpError(e.to_string()),
+        })?;
+
+    let title = listed.title.unwrap_or_else(|| "Imported playlist".into());
+
+    let playlist = db::playlist::create_new(&state, &ctx.user_id(), &title)
+        .await
+        .map_err(|_| Error::DbInsertFailed)?;
+
+    let mut entries = vec![];
+    let mut imported_ids = HashSet::new();
+
+    for (position, entry) in listed.entries.into_iter().enumerate() {
+        let position = position as i32;
+        let song_id = entry
+            .as_ref()
+            .and_then(|e| e.id.as_deref())
+            .map(|id| Source::youtube(id).song_id());
+        let song_title = entry.as_ref().and_then(|e| e.title.clone());
+
+        let (status, job_id, error) = match import_entry(
+            &state,
+            &ctx,
+            &playlist.id,
+            position,
+            entry,
+            &mut imported_ids,
+        )
+        .await
+        {
+            Ok((status, job_id)) => (status, job_id, None),
+            Err(reason) => (ImportStatus::Failed, None, Some(reason)),
+        };
+
+        entries.push(ImportedEntry {
+            position,
+            song_id,
+            title: song_title,
+            status,
+            job_id,
+            error,
+        });
+    }
+
+    let status_code = if entries.iter().any(|e| e.status == ImportStatus::Queued) {
+        jobs::queue_notify().notify_waiters();
+        StatusCode::ACCEPTED
+    } else {
+        StatusCode::OK
+    };
+
+    Ok((
+        status_code,
+        Json(json!(ModelResponse {
+            data: ImportResponse { playlist, entries },
+        })),
+    ))
+}
+
+/// Adds an entry of an imported playlist to the playlist, or queues its download if nobody
+/// downloaded it yet
+///
+/// Returns whether it was added or queued, along with the id of the job, or the reason it could
+/// not be imported
+async fn import_entry(
+    state: &AppState,
+    ctx: &Ctx,
+    playlist_id: &str,
+    position: i32,
+    entry: Option<YtDlpPlaylistEntry>,
+    imported_ids: &mut HashSet<String>,
+) -> core::result::Result<(ImportStatus, Option<String>), String> {
+    let Some(entry) = entry else {
+        return Err("The entry could not be read".into());
+    };
+
+    let Some(song_id) = entry
+        .id
+        .as_deref()
+        .filter(|_| entry.is_available())
+        .map(|id| Source::youtube(id).song_id())
+    else {
+        return Err("The video is private or was deleted".into());
+    };
+
+    // a song can only be in a playlist once
+    if !imported_ids.insert(song_id.clone()) {
+        return Err("The video is already in the playlist".into());
+    }
+
+    // unavailable songs are downloaded again
+    let existing_song = db::song::first_by_id_any_user(state, &song_id)
+        .await
+        .map_err(|_| Error::DbSelectFailed.to_string())?
+        .filter(db::song::is_available);
+
+    if existing_song.is_none() {
+        let job_id = uuid::Uuid::new_v4().to_string();
+
+        db::download_job::create_new_for_playlist(
+            state,
+            &job_id,
+            &ctx.user_id(),
+            &song_id,
+            playlist_id,
+            position,
+        )
+        .await
+        .map_err(|_| Error::DbInsertFailed.to_string())?;
+
+        return Ok((ImportStatus::Queued, Some(job_id)));
+    }
+
+    db::junctions::user_song::create_new(state, &ctx.user_id(), &song_id)
+        .await
+        .or_else(|e| match e.sql_err() {
+            // the user already has it
+            Some(SqlErr::UniqueConstraintViolation(_)) => Ok(()),
+            _ => Err(Error::DbInsertFailed.to_string()),
+        })?;
+
+    db::junctions::playlist_song::create_new_at(state, playlist_id, &song_id, position)
+        .await
+        .map_err(|_| Error::DbInsertFailed.to_string())?;
+
+    Ok((ImportStatus::Added, None))
+}
+
 async fn add_playlist_song_handler(
     State(state): State<AppState>,
     ctx: Ctx,
@@ -160,3 +331,33 @@ struct PlaylistPayload {
 struct PlaylistSongPayload {
     song_id: String,
 }
+
+#[derive(Debug, Deserialize)]
+struct ImportPlaylistPayload {
+    link: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ImportResponse {
+    playlist: Playlist,
+    entries: Vec<ImportedEntry>,
+}
+
+/// What happened to an entry of an imported playlist, in the order of the original playlist
+#[derive(Debug, Serialize)]
+struct ImportedEntry {
+    position: i32,
+    song_id: Option<String>,
+    title: Option<String>,
+    status: ImportStatus,
+    job_id: Option<String>, // set for queued entries, the job adds the song once it is done
+    error: Option<String>,  // set for failed entries
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum ImportStatus {
+    Added,
+    Queued,
+    Failed,
+}
@@ -0,0 +1,179 @@
+use super::{
+    error::{Error, Result},
+    ModelResponse,
+};
+use crate::{
+    config,
+    context::Ctx,
+    db,
+    util::{source::Source, yt_dlp::YtDlpPlaylistEntry},
+    AppState,
+};
+use axum::{
+    extract::{Query, State},
+    routing::get,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+use tokio::sync::Semaphore;
+
+const DEFAULT_LIMIT: usize = 10;
+const MAX_QUERY_CHARS: usize = 200;
+/// Queries cached at the same time, the oldest one is dropped to make room for a new one
+const SEARCH_CACHE_CAPACITY: usize = 256;
+
+pub fn router(state: AppState) -> Router {
+    Router::new()
+        .route("/search", get(search_handler))
+        .with_state(state)
+}
+
+/// Searches youtube for q and Returns up to limit videos, best match first
+///
+/// Videos the user already has in the library are marked as owned
+///
+/// Results are cached by query for config().search_cache_secs, so searching again while typing or
+/// paging back does not run the downloader every time
+/// Searches that miss the cache are refused while config().search_max_concurrent of them run
+async fn search_handler(
+    State(state): State<AppState>,
+    ctx: Ctx,
+    Query(params): Query<SearchParams>,
+) -> Result<Json<Value>> {
+    tracing::debug!("SEARCH HANDLER");
+
+    let query = params.q.trim();
+
+    if query.is_empty() {
+        return Err(Error::InvalidPayload("The search query is empty".into()));
+    }
+
+    if query.chars().count() > MAX_QUERY_CHARS {
+        return Err(Error::InvalidPayload(format!(
+            "The search query is longer than {MAX_QUERY_CHARS} characters"
+        )));
+    }
+
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_LIMIT)
+        .clamp(1, config().search_max_results);
+
+    let entries = match cached_results(query, limit) {
+        Some(entries) => entries,
+        None => {
+            // every search that misses the cache is a yt-dlp process
+            let _permit = search_permits()
+                .try_acquire()
+                .map_err(|_| Error::SearchBusy)?;
+
+            let entries = state
+                .downloader
+                .search(query, limit)
+                .await
+                .map_err(|e| Error::YtDlpError(e.to_string()))?;
+
+            cache_results(query, limit, entries.clone());
+
+            entries
+        }
+    };
+
+    // never cached, it is different for every user
+    let owned_ids: HashSet<String> =
+        db::junctions::user_song::all_song_ids_by_user_id(&state, &ctx.user_id())
+            .await
+            .map_err(|_| Error::DbSelectFailed)?
+            .into_iter()
+            .collect();
+
+    let results: Vec<SearchResult> = entries
+        .into_iter()
+        .filter_map(|entry| {
+            let id = entry
+                .id
+                .as_deref()
+                .filter(|_| entry.is_available())
+                .map(|id| Source::youtube(id).song_id())?;
+
+            Some(SearchResult {
+                owned: owned_ids.contains(&id),
+                id,
+                title: entry.title,
+                channel: entry.channel,
+                duration_secs: entry.duration.map(|d| d as u64),
+                // the last one is the biggest
+                thumbnail: entry.thumbnails.into_iter().last().map(|t| t.url),
+            })
+        })
+        .collect();
+
+    Ok(Json(json!(ModelResponse { data: results })))
+}
+
+fn search_permits() -> &'static Semaphore {
+    static SEARCH_PERMITS: OnceLock<Semaphore> = OnceLock::new();
+
+    SEARCH_PERMITS.get_or_init(|| Semaphore::new(config().search_max_concurrent.max(1)))
+}
+
+type SearchCache = HashMap<(String, usize), (Instant, Vec<YtDlpPlaylistEntry>)>;
+
+/// Results of recent searches by lowercased query and limit, along with when they were cached
+fn search_cache() -> &'static Mutex<SearchCache> {
+    static SEARCH_CACHE: OnceLock<Mutex<SearchCache>> = OnceLock::new();
+
+    SEARCH_CACHE.get_or_init(Default::default)
+}
+
+fn cached_results(query: &str, limit: usize) -> Option<Vec<YtDlpPlaylistEntry>> {
+    let cache = search_cache().lock().unwrap_or_else(|e| e.into_inner());
+    let max_age = Duration::from_secs(config().search_cache_secs);
+
+    cache
+        .get(&(query.to_lowercase(), limit))
+        .filter(|(cached_at, _)| cached_at.elapsed() < max_age)
+        .map(|(_, entries)| entries.clone())
+}
+
+fn cache_results(query: &str, limit: usize, entries: Vec<YtDlpPlaylistEntry>) {
+    let mut cache = search_cache().lock().unwrap_or_else(|e| e.into_inner());
+    let max_age = Duration::from_secs(config().search_cache_secs);
+
+    cache.retain(|_, (cached_at, _)| cached_at.elapsed() < max_age);
+
+    if cache.len() >= SEARCH_CACHE_CAPACITY {
+        let oldest = cache
+            .iter()
+            .min_by_key(|(_, (cached_at, _))| *cached_at)
+            .map(|(key, _)| key.clone());
+
+        if let Some(oldest) = oldest {
+            cache.remove(&oldest);
+        }
+    }
+
+    cache.insert((query.to_lowercase(), limit), (Instant::now(), entries));
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchParams {
+    q: String,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct SearchResult {
+    id: String, // song id, the same one it gets once added
+    title: Option<String>,
+    channel: Option<String>,
+    duration_secs: Option<u64>,
+    thumbnail: Option<String>,
+    owned: bool, // already in the library of the user
+}
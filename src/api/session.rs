@@ -0,0 +1,105 @@
+use super::{
+    error::{Error, Result},
+    remove_refresh_token_cookie, remove_token_cookie, ModelResponse,
+};
+use crate::{context::Ctx, db, AppState};
+use axum::{
+    extract::{Path, State},
+    routing::{delete, get},
+    Json, Router,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tower_cookies::Cookies;
+
+pub fn router(state: AppState) -> Router {
+    Router::new()
+        .route("/sessions", get(get_sessions_handler))
+        .route("/sessions", delete(revoke_all_sessions_handler))
+        .route("/sessions/:id", delete(revoke_session_handler))
+        .with_state(state)
+}
+
+/// Returns every active session of the user, the one that made the request is marked as current
+async fn get_sessions_handler(State(state): State<AppState>, ctx: Ctx) -> Result<Json<Value>> {
+    tracing::debug!("GET SESSIONS HANDLER");
+
+    let sessions: Vec<SessionResponse> = db::session::all_active_by_user_id(&state, &ctx.user_id())
+        .await
+        .map_err(|_| Error::DbSelectFailed)?
+        .into_iter()
+        .map(|s| SessionResponse {
+            current: ctx.session_id().is_some_and(|current| current == s.id),
+            id: s.id,
+            device: s.device,
+            ip: s.ip,
+            created_at: s.created_at,
+            last_seen_at: s.last_seen_at,
+        })
+        .collect();
+
+    Ok(Json(json!(ModelResponse { data: sessions })))
+}
+
+/// Revokes one session of the user, the device using it will have to log in again
+///
+/// WILL NOT revoke a session owned by another user
+async fn revoke_session_handler(
+    State(state): State<AppState>,
+    ctx: Ctx,
+    cookies: Cookies,
+    Path(id): Path<String>,
+) -> Result<Json<Value>> {
+    tracing::debug!("REVOKE SESSION HANDLER");
+
+    let revoked = db::session::revoke(&state, &id, &ctx.user_id())
+        .await
+        .map_err(|_| Error::DbUpdateFailed)?;
+
+    if !revoked {
+        return Err(Error::SessionNotFound);
+    }
+
+    if ctx.session_id().is_some_and(|current| current == id) {
+        remove_token_cookie(&cookies).await;
+        remove_refresh_token_cookie(&cookies).await;
+    }
+
+    Ok(Json(json!(
+        {
+        "result": "success"
+        }
+    )))
+}
+
+/// Logs the user out of every device, including the one that made the request
+async fn revoke_all_sessions_handler(
+    State(state): State<AppState>,
+    ctx: Ctx,
+    cookies: Cookies,
+) -> Result<Json<Value>> {
+    tracing::debug!("REVOKE ALL SESSIONS HANDLER");
+
+    db::session::revoke_all_by_user_id(&state.db, &ctx.user_id())
+        .await
+        .map_err(|_| Error::DbUpdateFailed)?;
+
+    remove_token_cookie(&cookies).await;
+    remove_refresh_token_cookie(&cookies).await;
+
+    Ok(Json(json!(
+        {
+        "result": "success"
+        }
+    )))
+}
+
+#[derive(Debug, Serialize)]
+struct SessionResponse {
+    id: String,
+    device: Option<String>,
+    ip: Option<String>,
+    created_at: String,
+    last_seen_at: String,
+    current: bool,
+}
@@ -1,29 +1,51 @@
 use super::{
     error::{Error, Result},
+    job::JobResponse,
     ModelResponse,
 };
 use crate::{
+    config,
     context::Ctx,
-    db,
+    crypt, db, jobs,
     util::{
-        link::parse_yt_link,
-        yt_dlp::{YtDlp, YtDlpResult},
+        self,
+        link::parse_link,
+        source::{Provider, Source},
+        time::{now_utc, utc_time_to_str},
     },
     AppState,
 };
 use axum::{
-    extract::{Path, State},
+    extract::{
+        multipart::{Field, MultipartError},
+        DefaultBodyLimit, Multipart, Path, State,
+    },
+    http::StatusCode,
     routing::{delete, get, post},
     Json, Router,
 };
 use entity::song::Model as Song;
+use sea_orm::SqlErr;
 use serde::Deserialize;
 use serde_json::{json, Value};
+use std::path::PathBuf;
+use tokio::io::AsyncWriteExt;
+
+/// Room left in the body of an upload for the multipart boundaries and headers
+const UPLOAD_OVERHEAD_BYTES: usize = 64 * 1024;
+/// Extensions of the audio files that can be uploaded
+const UPLOAD_EXTENSIONS: [&str; 8] = ["aac", "flac", "m4a", "mp3", "oga", "ogg", "opus", "wav"];
 
 pub fn router(state: AppState) -> Router {
+    let upload_body_limit = config().upload_max_bytes as usize + UPLOAD_OVERHEAD_BYTES;
+
     Router::new()
         .route("/songs/:id", get(get_song_handler))
         .route("/songs", post(add_song_handler))
+        .route(
+            "/songs/upload",
+            post(upload_song_handler).layer(DefaultBodyLimit::max(upload_body_limit)),
+        )
         .route("/songs/:id", delete(remove_song_handler))
         .with_state(state)
 }
@@ -48,54 +70,210 @@ async fn get_song_handler(
     })))
 }
 
-/// Tries to add a new song to the database and download it.
-/// Also creates a junction table that links the song to the user that requested it, so the user
-/// can "own" the song
+/// Adds a song to the library of the user, downloading it if nobody did yet
 ///
-/// If the song already exists, no new song is created or actually downloaded, but instead the song
-/// is just linked to the user by a junction table
+/// Songs that were already downloaded, and are not unavailable, are linked to the user by a
+/// user_song junction table right away and returned with 200 OK
+///
+/// Otherwise a download job is queued and returned with 202 Accepted, the song shows up in the
+/// library once the job is done, its progress can be followed at /api/jobs/:id
 async fn add_song_handler(
     State(state): State<AppState>,
     ctx: Ctx,
     Json(payload): Json<SongPayload>,
-) -> Result<Json<Value>> {
+) -> Result<(StatusCode, Json<Value>)> {
     tracing::debug!("ADD SONG HANDLER");
 
     let SongPayload { link } = payload;
-    let song_id = parse_yt_link(&link).map_err(|e| Error::InvalidPayload(e.to_string()))?;
+    let source = parse_link(&link).map_err(|e| Error::InvalidPayload(e.to_string()))?;
 
-    let song_option = db::song::first_by_id(&state, &song_id, &ctx.user_id())
+    source
+        .check_enabled()
+        .map_err(|e| Error::InvalidPayload(e.to_string()))?;
+
+    let song_id = source.song_id();
+
+    let owned_song = db::song::first_by_id(&state, &song_id, &ctx.user_id())
         .await
-        .map_err(|_| Error::DbSelectFailed)?;
+        .map_err(|_| Error::DbSelectFailed)?
+        .filter(db::song::is_available);
+
+    if let Some(song) = owned_song {
+        return Ok((StatusCode::OK, Json(json!(ModelResponse { data: song }))));
+    }
 
-    // Exits early and creates user_song junction table for the song and user that requested it
-    if let Some(song) = song_option {
+    // unavailable songs are downloaded again, the job fixes them for every user that has them
+    let existing_song = db::song::first_by_id_any_user(&state, &song_id)
+        .await
+        .map_err(|_| Error::DbSelectFailed)?
+        .filter(db::song::is_available);
+
+    // Exits early and creates user_song junction table for the song and user that requested it,
+    // nothing is downloaded again
+    if let Some(song) = existing_song {
         db::junctions::user_song::create_new(&state, &ctx.user_id(), &song_id)
             .await
-            .map_err(|_| Error::DbInsertFailed)?;
+            .or_else(|e| match e.sql_err() {
+                // linked by a concurrent request of the same user
+                Some(SqlErr::UniqueConstraintViolation(_)) => Ok(()),
+                _ => Err(Error::DbInsertFailed),
+            })?;
 
-        return Ok(Json(json!(ModelResponse {
-            data: Song { ..song },
-        })));
+        return Ok((StatusCode::OK, Json(json!(ModelResponse { data: song }))));
     }
 
-    let process = YtDlp::default();
-    let YtDlpResult { channel, fulltitle } = process
-        .run(&song_id)
-        .await
-        .map_err(|e| Error::YtDlpError(e.to_string()))?;
+    let job_id = uuid::Uuid::new_v4().to_string();
 
-    let new_song = db::song::create_new(&state, &song_id, &fulltitle, &channel, &ctx.user_id())
+    let job = db::download_job::create_new(&state, &job_id, &ctx.user_id(), &song_id)
         .await
         .map_err(|_| Error::DbInsertFailed)?;
 
-    Ok(Json(json!(ModelResponse {
-        data: Song { ..new_song }
-    })))
+    jobs::queue_notify().notify_waiters();
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(json!(ModelResponse {
+            data: JobResponse::from(job)
+        })),
+    ))
+}
+
+/// Adds an audio file of the user to their library, for music that is not on any provider
+///
+/// Expects a multipart form with the file in a field named file, its tags become the metadata of
+/// the song, the file name is the title when it has no title tag
+///
+/// The file is transcoded to opus, the same as downloads, and the song gets a generated id, so
+/// uploading the same file twice makes two songs
+async fn upload_song_handler(
+    State(state): State<AppState>,
+    ctx: Ctx,
+    mut multipart: Multipart,
+) -> Result<Json<Value>> {
+    tracing::debug!("UPLOAD SONG HANDLER");
+
+    let mut field = loop {
+        let field = multipart
+            .next_field()
+            .await
+            .map_err(multipart_error)?
+            .ok_or(Error::InvalidPayload("The form has no file field".into()))?;
+
+        if field.name() == Some("file") {
+            break field;
+        }
+    };
+
+    let file_name = field.file_name().unwrap_or_default().to_string();
+    let (file_stem, extension) = file_name.rsplit_once('.').unwrap_or((&file_name, ""));
+
+    if !UPLOAD_EXTENSIONS.contains(&extension.to_lowercase().as_str()) {
+        return Err(Error::UnsupportedAudioFormat);
+    }
+
+    let source = Source::new(Provider::Upload, uuid::Uuid::new_v4().to_string());
+    let song_id = source.song_id();
+
+    // before the files exist, so a failure leaves nothing behind
+    let created_at = utc_time_to_str(now_utc()).map_err(crypt::error::Error::from)?;
+
+    let upload = UploadFile(
+        std::path::Path::new(&config().yt_dlp_output_path)
+            .join(format!("{}.upload", source.external_id())),
+    );
+
+    save_upload(&mut field, &upload).await?;
+
+    // ffmpeg tells whether it is actually audio, the extension is only what the user claims
+    let mut metadata = state
+        .downloader
+        .import(&upload.0, &source)
+        .await
+        .map_err(|e| match e {
+            util::error::Error::UnsupportedAudio(_) => Error::UnsupportedAudioFormat,
+            e => Error::FfmpegError(e.to_string()),
+        })?;
+
+    if metadata.fulltitle.is_empty() {
+        metadata.fulltitle = file_stem.to_string();
+    }
+
+    jobs::worker::gen_art_variants(&song_id).await;
+
+    let song = match db::song::create_new(
+        &state,
+        &song_id,
+        metadata.into(),
+        &ctx.user_id(),
+        &created_at,
+    )
+    .await
+    {
+        Ok(song) => song,
+        Err(_) => {
+            // no song points at the files, the garbage collector would never find them
+            jobs::gc::remove_media_files(&song_id, false).await;
+            return Err(Error::DbInsertFailed);
+        }
+    };
+
+    Ok(Json(json!(ModelResponse { data: song })))
+}
+
+/// Uploaded file while it is being imported, it is removed once dropped
+struct UploadFile(PathBuf);
+
+impl Drop for UploadFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Writes the file of the field to the upload as it arrives, so it is never whole in memory
+/// Fails once it gets bigger than config().upload_max_bytes
+async fn save_upload(field: &mut Field<'_>, upload: &UploadFile) -> Result<()> {
+    if let Some(dir) = upload.0.parent() {
+        tokio::fs::create_dir_all(dir)
+            .await
+            .map_err(|_| Error::IOError)?;
+    }
+
+    let mut file = tokio::fs::File::create(&upload.0)
+        .await
+        .map_err(|_| Error::IOError)?;
+    let mut size = 0;
+
+    while let Some(chunk) = field.chunk().await.map_err(multipart_error)? {
+        size += chunk.len() as u64;
+
+        if size > config().upload_max_bytes {
+            return Err(Error::UploadTooLarge);
+        }
+
+        file.write_all(&chunk).await.map_err(|_| Error::IOError)?;
+    }
+
+    if size == 0 {
+        return Err(Error::InvalidPayload("The file is empty".into()));
+    }
+
+    file.flush().await.map_err(|_| Error::IOError)?;
+
+    Ok(())
+}
+
+fn multipart_error(err: MultipartError) -> Error {
+    // the body went past the limit of the route
+    if err.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        return Error::UploadTooLarge;
+    }
+
+    Error::InvalidPayload(err.body_text())
 }
 
 /// It's a soft delete, because it only removes user_song junction table, does not actually remove
 /// song table or song file
+/// Once nobody has the song, the garbage collector deletes both after config().gc_grace_secs
 async fn remove_song_handler(
     State(state): State<AppState>,
     ctx: Ctx,
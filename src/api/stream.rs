@@ -1,5 +1,10 @@
 use super::error::Result;
-use crate::{api::error::Error, config, AppState};
+use crate::{
+    api::error::Error,
+    config,
+    util::source::{media_stem, Source},
+    AppState,
+};
 use axum::{
     body::Body,
     extract::{Path, State},
@@ -25,7 +30,16 @@ async fn stream_handler(
 ) -> Result<Response> {
     tracing::debug!("GET STREAM HANDLER");
 
-    let media_path = format!("./{}/{}.opus", &config().yt_dlp_output_path, song_id);
+    // the id ends up in a path
+    if Source::from_song_id(&song_id).is_err() {
+        return Err(Error::SongNotFound);
+    }
+
+    let media_path = format!(
+        "./{}/{}.opus",
+        &config().yt_dlp_output_path,
+        media_stem(&song_id)
+    );
 
     let serve_file = ServeFile::new(media_path)
         .oneshot(request)
@@ -0,0 +1,253 @@
+use super::{
+    error::{Error, Result},
+    me::check_passwd,
+};
+use crate::{
+    context::Ctx,
+    crypt::totp::{self, RecoveryCode},
+    db,
+    util::time::now_utc,
+    AppState,
+};
+use axum::{
+    extract::State,
+    routing::{get, post},
+    Json, Router,
+};
+use entity::totp as totp_entity;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+pub fn router(state: AppState) -> Router {
+    Router::new()
+        .route(
+            "/me/totp",
+            get(get_totp_handler)
+                .post(begin_enrollment_handler)
+                .delete(disable_totp_handler),
+        )
+        .route("/me/totp/confirm", post(confirm_enrollment_handler))
+        .route(
+            "/me/totp/recovery-codes",
+            post(regen_recovery_codes_handler),
+        )
+        .with_state(state)
+}
+
+async fn get_totp_handler(State(state): State<AppState>, ctx: Ctx) -> Result<Json<Value>> {
+    tracing::debug!("GET TOTP HANDLER");
+
+    let enabled = enabled_totp(&state, &ctx.user_id()).await?.is_some();
+    let recovery_codes_left = db::recovery_code::count_unused_by_user_id(&state, &ctx.user_id())
+        .await
+        .map_err(|_| Error::DbSelectFailed)?;
+
+    Ok(Json(json!({
+            "result": {
+                "enabled": enabled,
+                "recovery_codes_left": recovery_codes_left,
+            }
+        }
+    )))
+}
+
+/// Generates a new totp secret for the user and returns it along with the otpauth:// uri that
+/// authenticator apps read from a QR code
+///
+/// Two-factor authentication is only turned on once a code of the secret is confirmed, calling
+/// this again before that replaces the secret
+async fn begin_enrollment_handler(State(state): State<AppState>, ctx: Ctx) -> Result<Json<Value>> {
+    tracing::debug!("BEGIN TOTP ENROLLMENT HANDLER");
+
+    if ctx.session_id().is_none() {
+        return Err(Error::SessionRequired);
+    }
+
+    if enabled_totp(&state, &ctx.user_id()).await?.is_some() {
+        return Err(Error::InvalidPayload(
+            "Two-factor authentication is already enabled".to_string(),
+        ));
+    }
+
+    let user = db::user::first_by_id(&state, &ctx.user_id())
+        .await
+        .map_err(|_| Error::DbSelectFailed)?
+        .ok_or(Error::UserNotFound)?;
+
+    let secret = totp::gen_secret();
+
+    db::totp::create_pending(&state, &user.id, &secret)
+        .await
+        .map_err(|_| Error::DbInsertFailed)?;
+
+    Ok(Json(json!({
+            "result": {
+                "success": true,
+                "secret": secret,
+                "provisioning_uri": totp::provisioning_uri(&secret, &user.username),
+            }
+        }
+    )))
+}
+
+/// Receives a payload of format: { code }
+/// Turns two-factor authentication on if the code matches the secret being enrolled and returns
+/// the recovery codes, this is the only time they are shown
+async fn confirm_enrollment_handler(
+    State(state): State<AppState>,
+    ctx: Ctx,
+    Json(payload): Json<CodePayload>,
+) -> Result<Json<Value>> {
+    tracing::debug!("CONFIRM TOTP ENROLLMENT HANDLER");
+
+    if ctx.session_id().is_none() {
+        return Err(Error::SessionRequired);
+    }
+
+    let pending = db::totp::first_by_user_id(&state, &ctx.user_id())
+        .await
+        .map_err(|_| Error::DbSelectFailed)?
+        .filter(|t| !t.enabled)
+        .ok_or(Error::InvalidPayload(
+            "There is no two-factor enrollment to confirm".to_string(),
+        ))?;
+
+    verify_totp_code(&state, &pending, &payload.code).await?;
+
+    db::totp::enable(&state, &ctx.user_id())
+        .await
+        .map_err(|_| Error::DbUpdateFailed)?;
+
+    let recovery_codes = gen_recovery_codes(&state, &ctx.user_id()).await?;
+
+    Ok(Json(json!({
+            "result": {
+                "success": true,
+                "recovery_codes": recovery_codes,
+            }
+        }
+    )))
+}
+
+/// Receives a payload of format: { code }
+/// Replaces every recovery code of the user with new ones and returns them
+async fn regen_recovery_codes_handler(
+    State(state): State<AppState>,
+    ctx: Ctx,
+    Json(payload): Json<CodePayload>,
+) -> Result<Json<Value>> {
+    tracing::debug!("REGENERATE RECOVERY CODES HANDLER");
+
+    if ctx.session_id().is_none() {
+        return Err(Error::SessionRequired);
+    }
+
+    let totp = enabled_totp(&state, &ctx.user_id())
+        .await?
+        .ok_or(Error::InvalidPayload(
+            "Two-factor authentication is not enabled".to_string(),
+        ))?;
+
+    verify_totp_code(&state, &totp, &payload.code).await?;
+
+    let recovery_codes = gen_recovery_codes(&state, &ctx.user_id()).await?;
+
+    Ok(Json(json!({
+            "result": {
+                "success": true,
+                "recovery_codes": recovery_codes,
+            }
+        }
+    )))
+}
+
+/// Receives a payload of format: { pwd }
+/// Turns two-factor authentication off and deletes the recovery codes
+async fn disable_totp_handler(
+    State(state): State<AppState>,
+    ctx: Ctx,
+    Json(payload): Json<DisableTotpPayload>,
+) -> Result<Json<Value>> {
+    tracing::debug!("DISABLE TOTP HANDLER");
+
+    if ctx.session_id().is_none() {
+        return Err(Error::SessionRequired);
+    }
+
+    check_passwd(&state, &ctx.user_id(), payload.pwd).await?;
+
+    db::totp::delete(&state, &ctx.user_id())
+        .await
+        .map_err(|_| Error::DbDeleteFailed)?;
+
+    db::recovery_code::delete_all_by_user_id(&state, &ctx.user_id())
+        .await
+        .map_err(|_| Error::DbDeleteFailed)?;
+
+    Ok(Json(json!(
+        {
+        "result": "success"
+        }
+    )))
+}
+
+/// Returns the totp of the user if two-factor authentication is on
+pub(super) async fn enabled_totp(
+    state: &AppState,
+    user_id: &str,
+) -> Result<Option<totp_entity::Model>> {
+    let totp = db::totp::first_by_user_id(state, user_id)
+        .await
+        .map_err(|_| Error::DbSelectFailed)?
+        .filter(|t| t.enabled);
+
+    Ok(totp)
+}
+
+/// Checks the code against the secret and makes sure it was not used before
+pub(super) async fn verify_totp_code(
+    state: &AppState,
+    totp: &totp_entity::Model,
+    code: &str,
+) -> Result<()> {
+    let now = now_utc().unix_timestamp().max(0) as u64;
+
+    let step = totp::verify_code(&totp.secret, code, now)?.ok_or(Error::InvalidTotpCode)?;
+
+    // a code seen once could be replayed by whoever saw it, for as long as it stays valid
+    let is_new = db::totp::mark_step_used(state, &totp.user_id, step as i64)
+        .await
+        .map_err(|_| Error::DbUpdateFailed)?;
+
+    if !is_new {
+        return Err(Error::InvalidTotpCode);
+    }
+
+    Ok(())
+}
+
+async fn gen_recovery_codes(state: &AppState, user_id: &str) -> Result<Vec<String>> {
+    let recovery_codes: Vec<RecoveryCode> = (0..RecoveryCode::COUNT)
+        .map(|_| RecoveryCode::new())
+        .collect();
+    let mut code_hashes = Vec::with_capacity(recovery_codes.len());
+    for recovery_code in &recovery_codes {
+        code_hashes.push(RecoveryCode::hash(&recovery_code.0)?);
+    }
+
+    db::recovery_code::replace_all(state, user_id, &code_hashes)
+        .await
+        .map_err(|_| Error::DbInsertFailed)?;
+
+    Ok(recovery_codes.into_iter().map(|c| c.0).collect())
+}
+
+#[derive(Debug, Deserialize)]
+struct CodePayload {
+    code: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DisableTotpPayload {
+    pwd: String,
+}
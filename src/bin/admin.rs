@@ -0,0 +1,77 @@
+use anyhow::{anyhow, Result};
+use ripfy_server::{
+    config,
+    context::Role,
+    crypt::token::PasswdResetToken,
+    db,
+    util::{time::now_utc_plus_sec_str, yt_dlp::YtDlp},
+    AppState,
+};
+use std::sync::Arc;
+
+const USAGE: &str = "Usage:
+    admin reset-token <username>        issues a one-time password reset token for the user
+                                        (valid for passwd_reset_token_duration_secs)
+    admin set-role <username> <role>    sets the role of the user, either user or admin";
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    match args.first().map(String::as_str) {
+        Some("reset-token") => {
+            let username = args
+                .get(1)
+                .ok_or_else(|| anyhow!("Missing username\n\n{USAGE}"))?;
+
+            let state = AppState {
+                db: db::connect().await?,
+                downloader: Arc::new(YtDlp::default()),
+            };
+
+            let user = db::user::first_by_username(&state, username)
+                .await?
+                .ok_or_else(|| anyhow!("There is no user named {username}"))?;
+
+            let token = PasswdResetToken::new();
+            let expires_at = now_utc_plus_sec_str(config().passwd_reset_token_duration_secs)?;
+
+            db::passwd_reset_token::create_new(
+                &state,
+                &token.id,
+                &user.id,
+                &token.hashed_secret(),
+                &expires_at,
+            )
+            .await?;
+
+            println!("Reset token for {}: {token}", user.username);
+            println!("It can be used once until {expires_at} at POST /api/password-reset");
+        }
+        Some("set-role") => {
+            let (Some(username), Some(role)) = (args.get(1), args.get(2)) else {
+                return Err(anyhow!("Missing username or role\n\n{USAGE}"));
+            };
+
+            let role: Role = role
+                .parse()
+                .map_err(|_| anyhow!("Invalid role {role}\n\n{USAGE}"))?;
+
+            let state = AppState {
+                db: db::connect().await?,
+                downloader: Arc::new(YtDlp::default()),
+            };
+
+            let user = db::user::first_by_username(&state, username)
+                .await?
+                .ok_or_else(|| anyhow!("There is no user named {username}"))?;
+
+            db::user::set_role(&state, &user.id, role.as_ref()).await?;
+
+            println!("{} is now {}", user.username, role.as_ref());
+        }
+        _ => println!("{USAGE}"),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use anyhow::{anyhow, Result};
+use ripfy_server::{config, crypt::keyring::Keyring};
+use std::path::Path;
+
+const USAGE: &str = "Usage:
+    keyring list                                  lists every key and when it retires
+    keyring rotate [--grace-secs <secs>]          generates a new signing key and retires the others after the grace period
+                                                  (defaults to the access token duration)
+    keyring retire <kid> [--in-secs <secs>]       retires a key now or after the given seconds, the key the server
+                                                  signs with can only be retired once a newer key exists
+
+The server has to be restarted to sign tokens with a new key";
+
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let dir = Path::new(&config().keyring_path);
+
+    let mut keyring = Keyring::load(dir)?;
+
+    match args.first().map(String::as_str) {
+        Some("list") => {
+            let active = active_kid(&keyring);
+
+            for key in &keyring.keys {
+                let state = if key.is_retired() {
+                    "retired"
+                } else if Some(key.kid.as_str()) == active {
+                    "active"
+                } else {
+                    "accepted"
+                };
+
+                println!(
+                    "{}\t{}\tcreated {}\tretires {}",
+                    key.kid,
+                    state,
+                    key.created_at,
+                    key.retire_at.as_deref().unwrap_or("never")
+                );
+            }
+        }
+        Some("rotate") => {
+            let grace_secs =
+                flag_value(&args, "--grace-secs")?.unwrap_or(config().access_token_duration_secs);
+
+            let entry = keyring.rotate(dir, config().key_size_bits, grace_secs)?;
+            keyring.save(dir)?;
+
+            println!("Generated key {}", entry.kid);
+            println!("Older keys will be retired in {grace_secs} seconds");
+        }
+        Some("retire") => {
+            let kid = args
+                .get(1)
+                .ok_or_else(|| anyhow!("Missing kid\n\n{USAGE}"))?;
+            let in_secs = flag_value(&args, "--in-secs")?.unwrap_or(0);
+
+            // the server would reject the tokens it signs once the key retires
+            if active_kid(&keyring) == Some(kid.as_str()) && !keyring.has_successor(kid) {
+                return Err(anyhow!(
+                    "Key {kid} signs the tokens of the server and no newer key can replace it, use keyring rotate instead"
+                ));
+            }
+
+            if !keyring.retire(kid, in_secs)? {
+                return Err(anyhow!("There is no key with kid {kid}"));
+            }
+            keyring.save(dir)?;
+
+            println!("Key {kid} will be retired in {in_secs} seconds");
+        }
+        _ => println!("{USAGE}"),
+    }
+
+    Ok(())
+}
+
+/// Returns the kid of the key the server signs tokens with
+fn active_kid(keyring: &Keyring) -> Option<&str> {
+    config()
+        .active_key_id
+        .as_deref()
+        .or(keyring.newest_unscheduled().map(|k| k.kid.as_str()))
+}
+
+/// Returns the number following a flag, if the flag was passed
+fn flag_value(args: &[String], flag: &str) -> Result<Option<u64>> {
+    let Some(position) = args.iter().position(|a| a == flag) else {
+        return Ok(None);
+    };
+
+    let value = args
+        .get(position + 1)
+        .ok_or_else(|| anyhow!("Missing value for {flag}"))?
+        .parse()?;
+
+    Ok(Some(value))
+}
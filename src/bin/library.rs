@@ -0,0 +1,77 @@
+use anyhow::Result;
+use ripfy_server::{
+    db,
+    jobs::reconcile::{reconcile, Repair},
+    util::yt_dlp::YtDlp,
+    AppState,
+};
+use std::sync::Arc;
+
+const USAGE: &str = "Usage:
+    library check [--redownload] [--mark-unavailable] [--remove-stray]
+        checks that every song has a playable audio file and that every file in the output path
+        belongs to a song, nothing is changed without flags
+
+        --redownload          downloads the audio of broken songs again
+        --mark-unavailable    marks the broken songs that were not downloaded again as unavailable
+        --remove-stray        removes the files that belong to no song";
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    match args.first().map(String::as_str) {
+        Some("check") => {
+            let has_flag = |flag: &str| args.iter().skip(1).any(|a| a == flag);
+
+            let repair = Repair {
+                redownload: has_flag("--redownload"),
+                mark_unavailable: has_flag("--mark-unavailable"),
+                remove_stray: has_flag("--remove-stray"),
+            };
+
+            let state = AppState {
+                db: db::connect().await?,
+                downloader: Arc::new(YtDlp::default()),
+            };
+
+            let report = reconcile(&state, repair).await?;
+
+            for (song_id, problem) in &report.broken {
+                println!("broken\t{song_id}\t{problem}");
+            }
+            for song_id in &report.unchecked {
+                println!("unchecked\t{song_id}");
+            }
+            for song_id in &report.redownloaded {
+                println!("downloaded again\t{song_id}");
+            }
+            for song_id in &report.marked_unavailable {
+                println!("marked unavailable\t{song_id}");
+            }
+            for song_id in &report.marked_available {
+                println!("marked available\t{song_id}");
+            }
+            for path in &report.stray_files {
+                let action = if repair.remove_stray {
+                    "removed stray"
+                } else {
+                    "stray"
+                };
+
+                println!("{action}\t{}", path.display());
+            }
+
+            println!(
+                "Checked {} songs, {} broken, {} could not be checked, {} stray files",
+                report.checked,
+                report.broken.len(),
+                report.unchecked.len(),
+                report.stray_files.len()
+            );
+        }
+        _ => println!("{USAGE}"),
+    }
+
+    Ok(())
+}
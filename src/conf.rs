@@ -1,5 +1,8 @@
-use crate::crypt::gen_key::gen_private_key_if_not_exists;
-use anyhow::Result;
+use crate::{
+    crypt::{self, keyring::Keyring, token::TokenFormat},
+    util::time::parse_utc,
+};
+use anyhow::{anyhow, Result};
 use figment::{
     providers::{Format, Serialized, Toml},
     Figment,
@@ -12,7 +15,8 @@ use rsa::{
     RsaPrivateKey,
 };
 use serde::{Deserialize, Serialize};
-use std::sync::OnceLock;
+use std::{collections::HashMap, path::Path, sync::OnceLock};
+use time::OffsetDateTime;
 
 /// Creates or uses existing instance of config
 /// Uses OnceLock to:
@@ -38,12 +42,41 @@ pub fn keys() -> &'static Keys {
 #[derive(Deserialize, Serialize)]
 pub struct Config {
     pub db_location: String,
-    pub private_key_path: String,
+    pub keyring_path: String,
+    pub private_key_path: String, // key of versions without a keyring, imported into an empty one
+    pub active_key_id: Option<String>, // if not set, the newest key that is not retired is used
+    pub key_size_bits: usize,
     pub access_token_duration_secs: u64,
+    pub access_token_format: TokenFormat,
     pub refresh_token_duration_secs: u64,
+    pub passwd_reset_token_duration_secs: u64,
+    pub mfa_token_duration_secs: u64, // how long a login has to send its totp code
+    pub signup_mode: SignupMode,
+    pub users_can_invite: bool, // if false, only admins can create invite codes
+    pub login_max_failures: u32, // per username, before it gets locked out
+    pub login_max_failures_per_ip: u32,
+    pub login_lockout_secs: u64, // doubles with every failure past the limit
+    pub login_max_lockout_secs: u64,
+    pub login_failure_window_secs: u64, // failures are forgotten after this long without new ones
     pub yt_dlp_binary_path: String,
     pub yt_dlp_output_path: String,
     pub yt_dlp_timeout_milisecs: u64,
+    pub ffmpeg_binary_path: String,
+    pub ffprobe_binary_path: String,
+    pub upload_max_bytes: u64, // biggest audio file that can be uploaded
+    // links to plain audio files make the server fetch any url, NOT SSRF-safe: hosts are checked
+    // to be public, but yt-dlp follows redirects, only enable it for trusted users
+    pub direct_links_enabled: bool,
+    pub download_workers: usize, // how many songs are downloaded at the same time
+    pub playlist_import_max_entries: usize, // entries past it are left out of an import
+    pub search_max_results: usize,
+    pub search_max_concurrent: usize, // yt-dlp searches running at the same time, more are refused
+    pub search_timeout_secs: u64,     // searches are killed after this, downloads get longer
+    pub search_cache_secs: u64,       // how long the results of a query are reused
+    pub gc_interval_secs: u64,        // how often songs nobody has are deleted, 0 disables it
+    pub gc_grace_secs: u64,           // how long a song is kept after its last owner removed it
+    pub reconcile_on_startup: bool,   // checks that every song has a playable audio file
+    pub reconcile_redownload: bool,   // downloads broken songs again instead of only marking them
     pub port: u16,
 }
 
@@ -52,17 +85,55 @@ impl Default for Config {
     fn default() -> Self {
         Config {
             db_location: "ripfy.sqlite".into(),
+            keyring_path: "keyring".into(), // directory where the signing keys are stored
             private_key_path: "key.pem".into(),
-            access_token_duration_secs: 1800,    // 30 minutes
-            refresh_token_duration_secs: 604800, // 1 week
+            active_key_id: None,
+            key_size_bits: 3072,
+            access_token_duration_secs: 1800,        // 30 minutes
+            access_token_format: TokenFormat::Ripfy, // "ripfy" or "jwt"
+            refresh_token_duration_secs: 604800,     // 1 week
+            passwd_reset_token_duration_secs: 3600,  // 1 hour
+            mfa_token_duration_secs: 300,            // 5 minutes
+            signup_mode: SignupMode::Open,
+            users_can_invite: true,
+            login_max_failures: 5,
+            login_max_failures_per_ip: 20,
+            login_lockout_secs: 30,
+            login_max_lockout_secs: 3600,        // 1 hour
+            login_failure_window_secs: 900,      // 15 minutes
             yt_dlp_binary_path: "yt-dlp".into(), // default value assumes binary is on PATH
             yt_dlp_output_path: "media".into(),  // directory where media will be outputed
-            yt_dlp_timeout_milisecs: 30000,      // 30 seconds
+            yt_dlp_timeout_milisecs: 600000,     // 10 minutes
+            ffmpeg_binary_path: "ffmpeg".into(), // ffmpeg and ffprobe are on PATH wherever yt-dlp is
+            ffprobe_binary_path: "ffprobe".into(),
+            upload_max_bytes: 104857600, // 100 MiB
+            direct_links_enabled: false,
+            download_workers: 2,
+            playlist_import_max_entries: 500,
+            search_max_results: 25,
+            search_max_concurrent: 4,
+            search_timeout_secs: 20,
+            search_cache_secs: 300,  // 5 minutes
+            gc_interval_secs: 86400, // 1 day
+            gc_grace_secs: 604800,   // 1 week
+            reconcile_on_startup: true,
+            reconcile_redownload: false,
             port: 7717,
         }
     }
 }
 
+/// Who can create an account, the first account of the server can always be created and
+/// becomes an admin
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SignupMode {
+    #[default]
+    Open,
+    Invite, // requires a valid invite code
+    Closed,
+}
+
 impl Config {
     fn new() -> Result<Self> {
         let c = Figment::from(Serialized::defaults(Config::default()))
@@ -73,23 +144,115 @@ impl Config {
     }
 }
 
+/// Every key of the keyring that is not retired, tokens are signed with the active one and
+/// verified with the one whose kid they carry
 pub struct Keys {
-    pub signing_key: SigningKey<Sha512>,
-    pub verifying_key: VerifyingKey<Sha512>,
+    active_kid: String,
+    signing_key: SigningKey<Sha512>,
+    verifying_keys: HashMap<String, VerifyingKey<Sha512>>,
+    retire_times: HashMap<String, OffsetDateTime>,
 }
 
 impl Keys {
     fn new() -> Result<Self> {
-        gen_private_key_if_not_exists()?;
+        let dir = Path::new(&config().keyring_path);
+        let mut keyring = Keyring::load(dir)?;
+
+        // the key of versions without a keyring becomes its first key instead of being left behind
+        let legacy_key_path = Path::new(&config().private_key_path);
+        if keyring.keys.is_empty() && legacy_key_path.exists() {
+            let entry = keyring.import_key(dir, legacy_key_path)?;
+            keyring.save(dir)?;
 
-        let private_key: RsaPrivateKey =
-            DecodeRsaPrivateKey::read_pkcs1_pem_file(&config().private_key_path)?;
-        let signing_key = SigningKey::<Sha512>::new(private_key);
-        let verifying_key = signing_key.verifying_key();
+            tracing::info!(
+                "Imported {} into the keyring as key {}, it can be removed",
+                legacy_key_path.display(),
+                entry.kid
+            );
+        }
+
+        // every key is scheduled to retire, a new one takes over before they do
+        if keyring.newest_unscheduled().is_none() {
+            keyring.gen_key(dir, config().key_size_bits)?;
+            keyring.save(dir)?;
+        }
+
+        let active = match &config().active_key_id {
+            Some(kid) => keyring
+                .find(kid)
+                .filter(|k| k.retire_at.is_none())
+                .ok_or_else(|| {
+                    anyhow!("active key {kid} does not exist or is scheduled to retire")
+                })?,
+            None => keyring
+                .newest_unscheduled()
+                .ok_or_else(|| anyhow!("keyring has no active key"))?,
+        };
+        let active_kid = active.kid.clone();
+
+        let mut signing_key = None;
+        let mut verifying_keys = HashMap::new();
+        let mut retire_times = HashMap::new();
+
+        for entry in keyring.keys.iter().filter(|k| !k.is_retired()) {
+            let private_key: RsaPrivateKey =
+                DecodeRsaPrivateKey::read_pkcs1_pem_file(Keyring::key_path(dir, &entry.kid))?;
+            let key = SigningKey::<Sha512>::new(private_key);
+
+            verifying_keys.insert(entry.kid.clone(), key.verifying_key());
+
+            if let Some(retire_at) = &entry.retire_at {
+                retire_times.insert(entry.kid.clone(), parse_utc(retire_at)?);
+            }
+
+            if entry.kid == active_kid {
+                signing_key = Some(key);
+            }
+        }
 
         Ok(Keys {
-            signing_key,
-            verifying_key,
+            signing_key: signing_key.ok_or_else(|| anyhow!("could not load the active key"))?,
+            active_kid,
+            verifying_keys,
+            retire_times,
         })
     }
+
+    /// Returns the kid and the key new tokens are signed with
+    pub fn signing_key(&self) -> (&str, &SigningKey<Sha512>) {
+        (&self.active_kid, &self.signing_key)
+    }
+
+    /// Returns every key that is still accepted, along with its kid
+    pub fn verifying_keys(&self) -> Vec<(&str, &VerifyingKey<Sha512>)> {
+        let mut verifying_keys: Vec<(&str, &VerifyingKey<Sha512>)> = self
+            .verifying_keys
+            .iter()
+            .map(|(kid, key)| (kid.as_str(), key))
+            .filter(|(kid, _)| self.verifying_key(kid).is_ok())
+            .collect();
+
+        verifying_keys.sort_by_key(|(kid, _)| *kid);
+
+        verifying_keys
+    }
+
+    /// Returns the key that verifies tokens signed with kid
+    ///
+    /// Keys are retired on schedule, so a key that was accepted when the server started may be
+    /// rejected later
+    pub fn verifying_key(&self, kid: &str) -> Result<&VerifyingKey<Sha512>, crypt::error::Error> {
+        let is_retired = self
+            .retire_times
+            .get(kid)
+            .is_some_and(|retire_at| *retire_at <= OffsetDateTime::now_utc());
+
+        if is_retired {
+            return Err(crypt::error::Error::UnknownSigningKey);
+        }
+
+        self.verifying_keys
+            .get(kid)
+            .ok_or(crypt::error::Error::UnknownSigningKey)
+    }
 }
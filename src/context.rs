@@ -1,12 +1,31 @@
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
 #[derive(Clone, Debug)]
 pub struct Ctx {
     user_id: String,
+    session_id: Option<String>,
+    scope: Scope,
+    role: Role,
 }
 
 impl Ctx {
-    pub fn new(user_id: &str) -> Self {
+    pub fn new(user_id: &str, session_id: &str, role: Role) -> Self {
+        Self {
+            user_id: user_id.to_string(),
+            session_id: Some(session_id.to_string()),
+            scope: Scope::Full,
+            role,
+        }
+    }
+
+    /// Context of a request authenticated with an api key, which has no session
+    pub fn new_api_key(user_id: &str, scope: Scope, role: Role) -> Self {
         Self {
             user_id: user_id.to_string(),
+            session_id: None,
+            scope,
+            role,
         }
     }
 
@@ -14,4 +33,61 @@ impl Ctx {
     pub fn user_id(&self) -> String {
         self.user_id.clone()
     }
+
+    /// Getter for session_id, is None when the request was authenticated with an api key
+    pub fn session_id(&self) -> Option<String> {
+        self.session_id.clone()
+    }
+
+    /// Getter for scope
+    pub fn scope(&self) -> Scope {
+        self.scope
+    }
+
+    /// Getter for role
+    pub fn role(&self) -> Role {
+        self.role
+    }
+}
+
+/// What a context is allowed to do, sessions always have full access
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, strum_macros::AsRefStr)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum Scope {
+    ReadOnly,
+    AddSongs,
+    Full,
+}
+
+impl FromStr for Scope {
+    type Err = ();
+    fn from_str(scope: &str) -> Result<Self, Self::Err> {
+        match scope {
+            "read-only" => Ok(Self::ReadOnly),
+            "add-songs" => Ok(Self::AddSongs),
+            "full" => Ok(Self::Full),
+            _ => Err(()),
+        }
+    }
+}
+
+/// What a user is allowed to do, admins can also manage other users
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, strum_macros::AsRefStr)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum Role {
+    User,
+    Admin,
+}
+
+impl FromStr for Role {
+    type Err = ();
+    fn from_str(role: &str) -> Result<Self, Self::Err> {
+        match role {
+            "user" => Ok(Self::User),
+            "admin" => Ok(Self::Admin),
+            _ => Err(()),
+        }
+    }
 }
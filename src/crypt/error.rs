@@ -6,10 +6,22 @@ use thiserror::Error;
 pub enum Error {
     #[error("Something went wrong while decoding b64 to string!")]
     DecodingError(#[from] DecodeError),
-    #[error("Token provided in header is in the wrong format!\nExpected the following format: 'user-[user-id].[expiration].[signature]'.")]
+    #[error("Token provided in header is in the wrong format!\nExpected the following format: '[key-id].[user-id].[session-id].[expiration].[signature]'.")]
     TokenInvalidFormat,
+    #[error("Token provided in header is not a valid JWT!\nExpected the following format: '[header].[claims].[signature]'.")]
+    JwtInvalidFormat,
+    #[error("Refresh token provided is in the wrong format!\nExpected the following format: '[token-id].[secret]'.")]
+    RefreshTokenInvalidFormat,
+    #[error("Api key provided is in the wrong format!\nExpected the following format: 'ripfy_[key-id].[secret]'.")]
+    ApiKeyInvalidFormat,
+    #[error("The stored totp secret is not valid base32!")]
+    TotpInvalidSecret,
     #[error("Could not generate private key!")]
     KeyGenFailed,
+    #[error("Could not read/write the keyring!\nReason: {0}")]
+    KeyringIOError(String),
+    #[error("The key the token was signed with does not exist or was retired!")]
+    UnknownSigningKey,
     #[error("An error ocurred while trying to parse bytes of signature to Signature!")]
     SignParsingFailed,
     #[error(transparent)]
@@ -0,0 +1,167 @@
+use super::{b64, decode_signature, error::Error, sign_content};
+use crate::{config, keys, util::time::now_utc};
+use rsa::{
+    pkcs1v15::{SigningKey, VerifyingKey},
+    sha2::Sha512,
+    signature::Verifier,
+    traits::PublicKeyParts,
+    RsaPublicKey,
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, str::FromStr};
+
+/// The only algorithm accepted, RSASSA-PKCS1-v1_5 with SHA-512, same as the ripfy token format
+pub const JWT_ALG: &str = "RS512";
+
+/// Access token encoded as a standard signed JWT (RFC 7519), so other services can verify it
+/// with any JWT library and the keys published on the JWKS endpoint
+#[derive(Debug)]
+pub struct Jwt {
+    pub header: JwtHeader,
+    pub claims: JwtClaims,
+    pub signature: String,  // base64_url_safe
+    signed_content: String, // the encoded header and claims exactly as they were signed
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JwtHeader {
+    pub alg: String,
+    pub typ: Option<String>,
+    pub kid: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JwtClaims {
+    pub sub: String, // user id
+    pub sid: String, // session id
+    pub exp: i64,
+    pub iat: i64,
+    pub jti: String,
+}
+
+impl FromStr for Jwt {
+    type Err = Error;
+    fn from_str(token_str: &str) -> Result<Self, Self::Err> {
+        let splits: Vec<&str> = token_str.split('.').collect();
+        if splits.len() != 3 {
+            return Err(Error::JwtInvalidFormat);
+        }
+
+        let (header_b64u, claims_b64u, signature_b64u) = (splits[0], splits[1], splits[2]);
+
+        let header = serde_json::from_slice(&b64::decode(header_b64u)?)
+            .map_err(|_| Error::JwtInvalidFormat)?;
+        let claims = serde_json::from_slice(&b64::decode(claims_b64u)?)
+            .map_err(|_| Error::JwtInvalidFormat)?;
+
+        Ok(Self {
+            header,
+            claims,
+            signature: signature_b64u.to_string(),
+            signed_content: format!("{header_b64u}.{claims_b64u}"),
+        })
+    }
+}
+
+impl Display for Jwt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", &self.signed_content, &self.signature)
+    }
+}
+
+impl Jwt {
+    pub fn validate(&self, key: &VerifyingKey<Sha512>) -> Result<(), Error> {
+        // never trust the alg of the header, anything but RS512 (such as "none") is rejected
+        if self.header.alg != JWT_ALG {
+            return Err(Error::InvalidTokenSignature);
+        }
+
+        let signature = decode_signature(self.signature.as_str())?;
+
+        // validates signature
+        key.verify(self.signed_content.as_bytes(), &signature)
+            .map_err(|_| Error::InvalidTokenSignature)?;
+
+        // checks expiration
+        if self.claims.exp <= now_utc().unix_timestamp() {
+            return Err(Error::ExpiredTokenError);
+        }
+
+        Ok(())
+    }
+
+    pub fn new_access_token(user: &str, session_id: &str) -> Result<Self, Error> {
+        let duration = &config().access_token_duration_secs;
+        let (key_id, key) = keys().signing_key();
+
+        let token = Self::new(user, session_id, duration, key_id, key)?;
+
+        Ok(token)
+    }
+
+    fn new(
+        identifier: &str,
+        session_id: &str,
+        duration_secs: &u64,
+        key_id: &str,
+        key: &SigningKey<Sha512>,
+    ) -> Result<Self, Error> {
+        let iat = now_utc().unix_timestamp();
+        let duration_secs = i64::try_from(*duration_secs).map_err(|_| Error::JwtInvalidFormat)?;
+
+        let header = JwtHeader {
+            alg: JWT_ALG.to_string(),
+            typ: Some("JWT".to_string()),
+            kid: key_id.to_string(),
+        };
+        let claims = JwtClaims {
+            sub: identifier.to_string(),
+            sid: session_id.to_string(),
+            exp: iat + duration_secs,
+            iat,
+            jti: uuid::Uuid::new_v4().to_string(),
+        };
+
+        let signed_content = format!(
+            "{}.{}",
+            b64::encode(serde_json::to_vec(&header).map_err(|_| Error::JwtInvalidFormat)?),
+            b64::encode(serde_json::to_vec(&claims).map_err(|_| Error::JwtInvalidFormat)?)
+        );
+
+        let signature = sign_content(signed_content.clone(), key);
+
+        Ok(Self {
+            header,
+            claims,
+            signature,
+            signed_content,
+        })
+    }
+}
+
+/// Public part of a signing key as a JSON Web Key (RFC 7517), published on the JWKS endpoint
+#[derive(Debug, Serialize)]
+pub struct Jwk {
+    kty: &'static str,
+    #[serde(rename = "use")]
+    key_use: &'static str,
+    alg: &'static str,
+    kid: String,
+    n: String, // modulus, base64_url_safe big-endian
+    e: String, // exponent, base64_url_safe big-endian
+}
+
+impl Jwk {
+    pub fn new(kid: &str, key: &VerifyingKey<Sha512>) -> Self {
+        let public_key: &RsaPublicKey = key.as_ref();
+
+        Self {
+            kty: "RSA",
+            key_use: "sig",
+            alg: JWT_ALG,
+            kid: kid.to_string(),
+            n: b64::encode(public_key.n().to_bytes_be()),
+            e: b64::encode(public_key.e().to_bytes_be()),
+        }
+    }
+}
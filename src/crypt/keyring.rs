@@ -0,0 +1,181 @@
+use super::{b64, error::Error, gen_key::gen_private_key};
+use crate::util::time::{now_utc, now_utc_plus_sec_str, parse_utc, utc_time_to_str};
+use rand::RngCore;
+use rsa::{pkcs1::DecodeRsaPrivateKey, RsaPrivateKey};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+const MANIFEST_FILE: &str = "keyring.json";
+
+/// Keeps track of every signing key in the keyring directory
+///
+/// Each key is stored as `<kid>.pem` next to a keyring.json file holding when it was created
+/// and when it stops being accepted. Keys are ordered from oldest to newest
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Keyring {
+    pub keys: Vec<KeyEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyEntry {
+    pub kid: String,
+    pub created_at: String,
+    pub retire_at: Option<String>, // tokens signed by the key are rejected after this moment
+}
+
+impl KeyEntry {
+    /// A key is retired once its retire_at moment has passed
+    pub fn is_retired(&self) -> bool {
+        self.retire_at
+            .as_deref()
+            .is_some_and(|retire_at| parse_utc(retire_at).map_or(true, |t| t <= now_utc()))
+    }
+}
+
+impl Keyring {
+    /// Reads the keyring of the directory, a directory without a keyring.json has an empty one
+    pub fn load(dir: &Path) -> Result<Self, Error> {
+        let manifest_path = dir.join(MANIFEST_FILE);
+
+        if !manifest_path.exists() {
+            return Ok(Self::default());
+        }
+
+        let manifest =
+            fs::read_to_string(manifest_path).map_err(|e| Error::KeyringIOError(e.to_string()))?;
+
+        serde_json::from_str(&manifest).map_err(|e| Error::KeyringIOError(e.to_string()))
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), Error> {
+        let manifest =
+            serde_json::to_string_pretty(self).map_err(|e| Error::KeyringIOError(e.to_string()))?;
+
+        fs::write(dir.join(MANIFEST_FILE), manifest)
+            .map_err(|e| Error::KeyringIOError(e.to_string()))
+    }
+
+    pub fn key_path(dir: &Path, kid: &str) -> PathBuf {
+        dir.join(format!("{kid}.pem"))
+    }
+
+    /// Returns the newest key that is not retired
+    pub fn newest_active(&self) -> Option<&KeyEntry> {
+        self.keys.iter().rev().find(|k| !k.is_retired())
+    }
+
+    /// Returns the newest key that is not scheduled to retire, which signs new tokens unless
+    /// config().active_key_id pins another
+    ///
+    /// A key that is scheduled to retire never signs, the server would reject its own tokens once
+    /// the key retires
+    pub fn newest_unscheduled(&self) -> Option<&KeyEntry> {
+        self.keys.iter().rev().find(|k| k.retire_at.is_none())
+    }
+
+    /// Whether a key newer than kid, and not scheduled to retire, can sign tokens in its place
+    pub fn has_successor(&self, kid: &str) -> bool {
+        self.keys
+            .iter()
+            .skip_while(|k| k.kid != kid)
+            .skip(1)
+            .any(|k| k.retire_at.is_none())
+    }
+
+    pub fn find(&self, kid: &str) -> Option<&KeyEntry> {
+        self.keys.iter().find(|k| k.kid == kid)
+    }
+
+    /// Generates a new key, writes it to the directory and adds it to the keyring
+    ///
+    /// The keyring itself is NOT saved, call save after it
+    pub fn gen_key(&mut self, dir: &Path, bits: usize) -> Result<KeyEntry, Error> {
+        fs::create_dir_all(dir).map_err(|e| Error::KeyringIOError(e.to_string()))?;
+
+        let entry = Self::new_entry()?;
+
+        gen_private_key(&Self::key_path(dir, &entry.kid), bits)?;
+        self.keys.push(entry.clone());
+
+        Ok(entry)
+    }
+
+    /// Copies an existing PKCS#1 PEM key into the directory and adds it to the keyring, used for
+    /// the single key of versions without a keyring
+    ///
+    /// The keyring itself is NOT saved, call save after it
+    pub fn import_key(&mut self, dir: &Path, key_path: &Path) -> Result<KeyEntry, Error> {
+        // a file that is not a key would make every later startup fail
+        RsaPrivateKey::read_pkcs1_pem_file(key_path)
+            .map_err(|e| Error::KeyringIOError(e.to_string()))?;
+
+        fs::create_dir_all(dir).map_err(|e| Error::KeyringIOError(e.to_string()))?;
+
+        let entry = Self::new_entry()?;
+
+        fs::copy(key_path, Self::key_path(dir, &entry.kid))
+            .map_err(|e| Error::KeyringIOError(e.to_string()))?;
+        self.keys.push(entry.clone());
+
+        Ok(entry)
+    }
+
+    fn new_entry() -> Result<KeyEntry, Error> {
+        let mut rng = rand::thread_rng();
+        let mut kid = [0u8; 8];
+        rng.fill_bytes(&mut kid);
+
+        Ok(KeyEntry {
+            kid: b64::encode(kid),
+            created_at: utc_time_to_str(now_utc())?,
+            retire_at: None,
+        })
+    }
+
+    /// Schedules the retirement of a key in_secs from now
+    ///
+    /// A key that is already scheduled to retire earlier keeps its schedule
+    /// Returns Ok(false) if there is no key with that kid
+    pub fn retire(&mut self, kid: &str, in_secs: u64) -> Result<bool, Error> {
+        let retire_at = now_utc_plus_sec_str(in_secs)?;
+        let retire_time = parse_utc(&retire_at)?;
+
+        let Some(entry) = self.keys.iter_mut().find(|k| k.kid == kid) else {
+            return Ok(false);
+        };
+
+        let retires_earlier = entry
+            .retire_at
+            .as_deref()
+            .and_then(|current| parse_utc(current).ok())
+            .is_some_and(|current| current <= retire_time);
+
+        if !retires_earlier {
+            entry.retire_at = Some(retire_at);
+        }
+
+        Ok(true)
+    }
+
+    /// Generates a new key and schedules every key that is still accepted to retire in
+    /// grace_secs, so tokens signed before the rotation keep working until they expire
+    pub fn rotate(&mut self, dir: &Path, bits: usize, grace_secs: u64) -> Result<KeyEntry, Error> {
+        let old_kids: Vec<String> = self
+            .keys
+            .iter()
+            .filter(|k| !k.is_retired())
+            .map(|k| k.kid.clone())
+            .collect();
+
+        let entry = self.gen_key(dir, bits)?;
+
+        for kid in old_kids {
+            self.retire(&kid, grace_secs)?;
+        }
+
+        Ok(entry)
+    }
+}
@@ -1,8 +1,11 @@
 pub mod b64;
 pub mod error;
 pub mod gen_key;
+pub mod jwt;
+pub mod keyring;
 pub mod passwd;
 pub mod token;
+pub mod totp;
 
 use self::error::Error;
 use rsa::{
@@ -1,12 +1,19 @@
 use super::{
     b64, decode_signature,
+    gen_key::gen_private_key,
+    jwt::{Jwk, Jwt},
+    keyring::Keyring,
     passwd::{gen_salt, passwd_encrypt, verify_encrypted_passwd},
     sign_content,
-    token::Token,
+    token::{verify_access_token, RefreshToken, Token},
+    totp::{self, RecoveryCode},
 };
 use crate::{keys, util::time::now_utc_plus_sec_str};
 use anyhow::Result;
-use rsa::signature::Verifier;
+use rsa::{
+    sha2::{Digest, Sha512},
+    signature::Verifier,
+};
 use std::time::Duration;
 
 #[test]
@@ -40,16 +47,17 @@ fn signature() -> Result<()> {
     let content = "Really cool message";
     let content_alt = "Reall cool message";
 
-    let signature_string = sign_content(content.into(), &keys().signing_key);
+    let (kid, signing_key) = keys().signing_key();
+    let signature_string = sign_content(content.into(), signing_key);
     let signature = decode_signature(signature_string.as_str())?;
 
     assert!(keys()
-        .verifying_key
+        .verifying_key(kid)?
         .verify(content.as_bytes(), &signature)
         .is_ok());
 
     assert!(keys()
-        .verifying_key
+        .verifying_key(kid)?
         .verify(content_alt.as_bytes(), &signature)
         .is_err());
 
@@ -58,25 +66,269 @@ fn signature() -> Result<()> {
 
 #[test]
 fn token() -> Result<()> {
-    let access_token = Token::new_access_token("good guy")?;
-    access_token.validate(&keys().verifying_key)?;
+    let (kid, signing_key) = keys().signing_key();
+    let verifying_key = keys().verifying_key(kid)?;
+
+    let access_token = Token::new_access_token("good guy", "session")?;
+    assert_eq!(access_token.key_id, kid);
+    access_token.validate(verifying_key)?;
+
+    let parsed_token: Token = access_token.to_string().parse()?;
+    assert_eq!(parsed_token.session_id, "session");
+    assert_eq!(parsed_token.key_id, kid);
+    parsed_token.validate(verifying_key)?;
 
-    let mut bad_token = Token::new_access_token("bad guy")?;
+    let mut bad_token = Token::new_access_token("bad guy", "session")?;
     bad_token.identifier = "really good guy".into();
-    assert!(bad_token.validate(&keys().verifying_key).is_err());
+    assert!(bad_token.validate(verifying_key).is_err());
+
+    let mut hijacked_token = Token::new_access_token("good guy", "revoked session")?;
+    hijacked_token.session_id = "active session".into();
+    assert!(hijacked_token.validate(verifying_key).is_err());
+
+    let mut swapped_kid_token = Token::new_access_token("good guy", "session")?;
+    swapped_kid_token.key_id = "another key".into();
+    assert!(swapped_kid_token.validate(verifying_key).is_err());
+    assert!(keys().verifying_key("another key").is_err());
 
     let name = "really late guy".to_string();
+    let session = "session".to_string();
     let exp = now_utc_plus_sec_str(2)?;
     std::thread::sleep(Duration::from_secs(2));
     let expired_token = Token {
+        key_id: kid.to_string(),
         identifier: name.clone(),
+        session_id: session.clone(),
         expiration: exp.clone(),
         signature: sign_content(
-            format!("{}.{}", b64::encode(name), b64::encode(exp)),
-            &keys().signing_key,
+            format!(
+                "{}.{}.{}.{}",
+                b64::encode(kid),
+                b64::encode(name),
+                b64::encode(session),
+                b64::encode(exp)
+            ),
+            signing_key,
         ),
     };
-    assert!(expired_token.validate(&keys().verifying_key).is_err());
+    assert!(expired_token.validate(verifying_key).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn jwt() -> Result<()> {
+    let (kid, _) = keys().signing_key();
+    let verifying_key = keys().verifying_key(kid)?;
+
+    let token = Jwt::new_access_token("good guy", "session")?;
+    assert_eq!(token.header.alg, "RS512");
+    assert_eq!(token.header.kid, kid);
+    token.validate(verifying_key)?;
+
+    let parsed: Jwt = token.to_string().parse()?;
+    assert_eq!(parsed.claims.sub, "good guy");
+    assert_eq!(parsed.claims.sid, "session");
+    assert!(parsed.claims.exp > parsed.claims.iat);
+    parsed.validate(verifying_key)?;
+
+    // both formats are accepted no matter which one is configured
+    let claims = verify_access_token(&token.to_string())?;
+    assert_eq!(claims.identifier, "good guy");
+    let claims = verify_access_token(&Token::new_access_token("good guy", "session")?.to_string())?;
+    assert_eq!(claims.session_id, "session");
+
+    // the claims are signed, swapping them breaks the signature
+    let token_str = token.to_string();
+    let other_str = Jwt::new_access_token("bad guy", "session")?.to_string();
+    let token_parts: Vec<&str> = token_str.split('.').collect();
+    let other_parts: Vec<&str> = other_str.split('.').collect();
+
+    let forged = format!("{}.{}.{}", token_parts[0], other_parts[1], token_parts[2]);
+    assert!(verify_access_token(&forged).is_err());
+
+    // never trust the alg of the header
+    let unsigned_header = b64::encode(format!(r#"{{"alg":"none","kid":"{kid}"}}"#));
+    let unsigned = format!("{}.{}.", unsigned_header, token_parts[1]);
+    assert!(verify_access_token(&unsigned).is_err());
+
+    let jwk = serde_json::to_value(Jwk::new(kid, verifying_key))?;
+    assert_eq!(jwk["kid"], kid);
+    assert_eq!(jwk["use"], "sig");
+    assert_eq!(jwk["e"], "AQAB");
+
+    Ok(())
+}
+
+#[test]
+fn refresh_token() -> Result<()> {
+    let refresh_token = RefreshToken::new();
+    let parsed: RefreshToken = refresh_token.to_string().parse()?;
+
+    assert_eq!(parsed.id, refresh_token.id);
+    assert_eq!(parsed.hashed_secret(), refresh_token.hashed_secret());
+    assert_ne!(
+        RefreshToken::new().hashed_secret(),
+        refresh_token.hashed_secret()
+    );
+
+    assert!("no-secret".parse::<RefreshToken>().is_err());
+    assert!(".secret".parse::<RefreshToken>().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn totp() -> Result<()> {
+    // test vectors of RFC 6238, appendix B, truncated to 6 digits
+    let secret = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    assert_eq!(totp::code_at(secret, totp::time_step(59))?, "287082");
+    assert_eq!(
+        totp::code_at(secret, totp::time_step(1111111109))?,
+        "081804"
+    );
+    assert_eq!(
+        totp::code_at(secret, totp::time_step(1234567890))?,
+        "005924"
+    );
+    assert_eq!(
+        totp::code_at(secret, totp::time_step(2000000000))?,
+        "279037"
+    );
+
+    // codes of the neighbour steps are accepted, older ones are not
+    let now = 1234567890;
+    let previous = totp::code_at(secret, totp::time_step(now) - 1)?;
+    let too_old = totp::code_at(secret, totp::time_step(now) - 2)?;
+
+    assert_eq!(
+        totp::verify_code(secret, "005924", now)?,
+        Some(totp::time_step(now))
+    );
+    assert_eq!(
+        totp::verify_code(secret, &previous, now)?,
+        Some(totp::time_step(now) - 1)
+    );
+    assert_eq!(totp::verify_code(secret, &too_old, now)?, None);
+    assert_eq!(totp::verify_code(secret, "00592", now)?, None);
+
+    let secret = totp::gen_secret();
+    assert_eq!(secret.len(), 32);
+    assert!(totp::provisioning_uri(&secret, "demo user")
+        .starts_with("otpauth://totp/Ripfy:demo%20user?secret="));
+
+    let recovery_code = RecoveryCode::new();
+    let code_hash = RecoveryCode::hash(&recovery_code.0)?;
+    assert!(RecoveryCode::verify(&recovery_code.0, &code_hash));
+    assert!(RecoveryCode::verify(
+        &recovery_code.0.replace('-', "").to_uppercase(),
+        &code_hash
+    ));
+    assert!(!RecoveryCode::verify(&RecoveryCode::new().0, &code_hash));
+
+    // salted, the same code never has the same hash twice
+    assert_ne!(code_hash, RecoveryCode::hash(&recovery_code.0)?);
+
+    // codes hashed before argon2 was used
+    let legacy_hash = b64::encode(Sha512::digest(recovery_code.0.replace('-', "").as_bytes()));
+    assert!(RecoveryCode::verify(&recovery_code.0, &legacy_hash));
+    assert!(!RecoveryCode::verify(&RecoveryCode::new().0, &legacy_hash));
+
+    Ok(())
+}
+
+#[test]
+fn keyring_rotation() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("ripfy-keyring-{}", uuid::Uuid::new_v4()));
+
+    let mut keyring = Keyring::load(&dir)?;
+    assert!(keyring.newest_active().is_none());
+
+    let first = keyring.gen_key(&dir, 1024)?;
+    let second = keyring.rotate(&dir, 1024, 60)?;
+    keyring.save(&dir)?;
+
+    // the old key is still accepted during the grace period, but new tokens use the new one
+    let mut keyring = Keyring::load(&dir)?;
+    assert_eq!(keyring.keys.len(), 2);
+    assert!(keyring
+        .find(&first.kid)
+        .is_some_and(|k| k.retire_at.is_some()));
+    assert!(!keyring.find(&first.kid).is_some_and(|k| k.is_retired()));
+    assert_eq!(keyring.newest_active().map(|k| &k.kid), Some(&second.kid));
+    assert!(Keyring::key_path(&dir, &second.kid).exists());
+
+    // retiring now overrides the grace period, retiring later does not
+    assert!(keyring.retire(&first.kid, 0)?);
+    assert!(keyring.retire(&first.kid, 3600)?);
+    assert!(keyring.find(&first.kid).is_some_and(|k| k.is_retired()));
+    assert!(!keyring.retire("missing", 0)?);
+
+    keyring.retire(&second.kid, 0)?;
+    assert!(keyring.newest_active().is_none());
+
+    std::fs::remove_dir_all(dir)?;
+
+    Ok(())
+}
+
+#[test]
+fn keyring_import_key() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("ripfy-keyring-{}", uuid::Uuid::new_v4()));
+    let legacy_key_path = std::env::temp_dir().join(format!("ripfy-{}.pem", uuid::Uuid::new_v4()));
+
+    gen_private_key(&legacy_key_path, 1024)?;
+
+    let mut keyring = Keyring::load(&dir)?;
+    let entry = keyring.import_key(&dir, &legacy_key_path)?;
+    keyring.save(&dir)?;
+
+    let keyring = Keyring::load(&dir)?;
+    assert_eq!(keyring.newest_active().map(|k| &k.kid), Some(&entry.kid));
+    assert_eq!(
+        std::fs::read(Keyring::key_path(&dir, &entry.kid))?,
+        std::fs::read(&legacy_key_path)?
+    );
+
+    // files that are not keys are not imported
+    let mut keyring = Keyring::load(&dir)?;
+    std::fs::write(&legacy_key_path, "not a key")?;
+    assert!(keyring.import_key(&dir, &legacy_key_path).is_err());
+    assert_eq!(keyring.keys.len(), 1);
+
+    std::fs::remove_dir_all(dir)?;
+    std::fs::remove_file(legacy_key_path)?;
+
+    Ok(())
+}
+
+#[test]
+fn keyring_signing_key() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("ripfy-keyring-{}", uuid::Uuid::new_v4()));
+
+    let mut keyring = Keyring::load(&dir)?;
+    let first = keyring.gen_key(&dir, 1024)?;
+    assert!(!keyring.has_successor(&first.kid));
+
+    // a key scheduled to retire is still accepted, but never signs
+    keyring.retire(&first.kid, 3600)?;
+    assert!(!keyring.find(&first.kid).is_some_and(|k| k.is_retired()));
+    assert!(keyring.newest_unscheduled().is_none());
+
+    let second = keyring.rotate(&dir, 1024, 60)?;
+    assert!(keyring.has_successor(&first.kid));
+    assert!(!keyring.has_successor(&second.kid));
+    assert_eq!(
+        keyring.newest_unscheduled().map(|k| &k.kid),
+        Some(&second.kid)
+    );
+
+    keyring.retire(&second.kid, 60)?;
+    assert!(!keyring.has_successor(&first.kid));
+    assert!(keyring.newest_unscheduled().is_none());
+
+    std::fs::remove_dir_all(dir)?;
 
     Ok(())
 }
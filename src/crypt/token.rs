@@ -1,18 +1,22 @@
-use super::{b64, decode_signature, error::Error, sign_content};
+use super::{b64, decode_signature, error::Error, jwt::Jwt, sign_content};
 use crate::{
     config, keys,
     util::time::{now_utc, now_utc_plus_sec_str, parse_utc},
 };
+use rand::RngCore;
 use rsa::{
     pkcs1v15::{SigningKey, VerifyingKey},
-    sha2::Sha512,
+    sha2::{Digest, Sha512},
     signature::Verifier,
 };
+use serde::{Deserialize, Serialize};
 use std::{fmt::Display, str::FromStr};
 
 #[derive(Debug)]
 pub struct Token {
+    pub key_id: String,     // kid of the keyring key the token was signed with
     pub identifier: String, // can be anything, an identifier number, an UUID, an unique username, etc
+    pub session_id: String, // the server-side session the token was issued for, so it can be revoked
     pub expiration: String,
     pub signature: String, // base64_url_safe
 }
@@ -21,14 +25,17 @@ impl FromStr for Token {
     type Err = Error;
     fn from_str(token_str: &str) -> Result<Self, Self::Err> {
         let splits: Vec<&str> = token_str.split('.').collect();
-        if splits.len() != 3 {
+        if splits.len() != 5 {
             return Err(Error::TokenInvalidFormat);
         }
 
-        let (identifier_b64u, expiration_b64u, signature_b64u) = (splits[0], splits[1], splits[2]);
+        let (key_id_b64u, identifier_b64u, session_id_b64u, expiration_b64u, signature_b64u) =
+            (splits[0], splits[1], splits[2], splits[3], splits[4]);
 
         Ok(Self {
+            key_id: b64::decode_to_string(key_id_b64u)?,
             identifier: b64::decode_to_string(identifier_b64u)?,
+            session_id: b64::decode_to_string(session_id_b64u)?,
             expiration: b64::decode_to_string(expiration_b64u)?,
             signature: signature_b64u.to_string(),
         })
@@ -39,8 +46,10 @@ impl Display for Token {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "{}.{}.{}",
+            "{}.{}.{}.{}.{}",
+            b64::encode(&self.key_id),
             b64::encode(&self.identifier),
+            b64::encode(&self.session_id),
             b64::encode(&self.expiration),
             &self.signature
         )
@@ -50,8 +59,10 @@ impl Display for Token {
 impl Token {
     pub fn validate(&self, key: &VerifyingKey<Sha512>) -> Result<(), Error> {
         let content = format!(
-            "{}.{}",
+            "{}.{}.{}.{}",
+            b64::encode(&self.key_id),
             b64::encode(&self.identifier),
+            b64::encode(&self.session_id),
             b64::encode(&self.expiration)
         );
         let signature = decode_signature(self.signature.as_str())?;
@@ -71,27 +82,239 @@ impl Token {
         Ok(())
     }
 
-    pub fn new_access_token(user: &str) -> Result<Self, Error> {
+    pub fn new_access_token(user: &str, session_id: &str) -> Result<Self, Error> {
         let duration = &config().access_token_duration_secs;
-        let key = &keys().signing_key;
+        let (key_id, key) = keys().signing_key();
 
-        let token = Self::new(user, duration, key)?;
+        let token = Self::new(user, session_id, duration, key_id, key)?;
 
         Ok(token)
     }
 
-    fn new(identifier: &str, duration_secs: &u64, key: &SigningKey<Sha512>) -> Result<Self, Error> {
+    fn new(
+        identifier: &str,
+        session_id: &str,
+        duration_secs: &u64,
+        key_id: &str,
+        key: &SigningKey<Sha512>,
+    ) -> Result<Self, Error> {
+        let key_id = key_id.to_string();
         let identifier = identifier.to_string();
+        let session_id = session_id.to_string();
         let expiration = now_utc_plus_sec_str(*duration_secs)?;
 
-        let content = format!("{}.{}", b64::encode(&identifier), b64::encode(&expiration));
+        let content = format!(
+            "{}.{}.{}.{}",
+            b64::encode(&key_id),
+            b64::encode(&identifier),
+            b64::encode(&session_id),
+            b64::encode(&expiration)
+        );
 
         let signature = sign_content(content, key);
 
         Ok(Self {
+            key_id,
             identifier,
+            session_id,
             expiration,
             signature,
         })
     }
 }
+
+/// Encoding of the access tokens issued by the server
+///
+/// Tokens of every format are accepted, so changing it does not log anyone out
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenFormat {
+    #[default]
+    Ripfy,
+    Jwt,
+}
+
+/// What an access token says about the request, after its signature and expiration were checked
+#[derive(Debug)]
+pub struct AccessClaims {
+    pub identifier: String,
+    pub session_id: String,
+}
+
+/// Issues an access token in the format set on the config
+pub fn issue_access_token(user: &str, session_id: &str) -> Result<String, Error> {
+    let token = match config().access_token_format {
+        TokenFormat::Ripfy => Token::new_access_token(user, session_id)?.to_string(),
+        TokenFormat::Jwt => Jwt::new_access_token(user, session_id)?.to_string(),
+    };
+
+    Ok(token)
+}
+
+/// Parses an access token of any format and validates it against the key it was signed with
+pub fn verify_access_token(token_str: &str) -> Result<AccessClaims, Error> {
+    // a JWT has 3 parts while a ripfy token has 5
+    if token_str.split('.').count() == 3 {
+        let token: Jwt = token_str.parse()?;
+        token.validate(keys().verifying_key(&token.header.kid)?)?;
+
+        return Ok(AccessClaims {
+            identifier: token.claims.sub,
+            session_id: token.claims.sid,
+        });
+    }
+
+    let token: Token = token_str.parse()?;
+    token.validate(keys().verifying_key(&token.key_id)?)?;
+
+    Ok(AccessClaims {
+        identifier: token.identifier,
+        session_id: token.session_id,
+    })
+}
+
+/// Opaque long-lived token exchanged for new access tokens
+///
+/// Has the format `id.secret`, where the id is used to find the token in the database and the
+/// secret is checked against the hash stored there
+#[derive(Debug)]
+pub struct RefreshToken {
+    pub id: String,
+    pub secret: String, // base64_url_safe
+}
+
+/// One-time token an admin issues so a user can set a new password without knowing the old one
+///
+/// Shares the `id.secret` format and the hashing of refresh tokens
+pub type PasswdResetToken = RefreshToken;
+
+/// Short-lived token a login with the correct password gets when the user has two-factor
+/// authentication on, it is traded for the real tokens along with a valid code
+pub type MfaToken = RefreshToken;
+
+impl FromStr for RefreshToken {
+    type Err = Error;
+    fn from_str(token_str: &str) -> Result<Self, Self::Err> {
+        let (id, secret) = token_str
+            .split_once('.')
+            .ok_or(Error::RefreshTokenInvalidFormat)?;
+
+        if id.is_empty() || secret.is_empty() {
+            return Err(Error::RefreshTokenInvalidFormat);
+        }
+
+        Ok(Self {
+            id: id.to_string(),
+            secret: secret.to_string(),
+        })
+    }
+}
+
+impl Display for RefreshToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", &self.id, &self.secret)
+    }
+}
+
+impl RefreshToken {
+    pub fn new() -> Self {
+        let mut rng = rand::thread_rng();
+        let mut secret = [0u8; 32];
+        rng.fill_bytes(&mut secret);
+
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            secret: b64::encode(secret),
+        }
+    }
+
+    /// Returns a base64url encoded sha512 hash of the secret, which is what gets stored
+    /// The secret is random and long enough that a slow hash like argon2 is not needed
+    pub fn hashed_secret(&self) -> String {
+        b64::encode(Sha512::digest(self.secret.as_bytes()))
+    }
+}
+
+impl Default for RefreshToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Long-lived key used by scripts and headless clients in place of a login
+///
+/// Has the format `ripfy_id.secret`, the prefix lets it be told apart from access tokens when it
+/// is sent as a Bearer token
+#[derive(Debug)]
+pub struct ApiKey {
+    pub id: String,
+    pub secret: String, // base64_url_safe
+}
+
+impl ApiKey {
+    pub const PREFIX: &'static str = "ripfy_";
+
+    pub fn new() -> Self {
+        let mut rng = rand::thread_rng();
+        let mut secret = [0u8; 32];
+        rng.fill_bytes(&mut secret);
+
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            secret: b64::encode(secret),
+        }
+    }
+}
+
+impl Default for ApiKey {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FromStr for ApiKey {
+    type Err = Error;
+    fn from_str(key_str: &str) -> Result<Self, Self::Err> {
+        let (id, secret) = key_str
+            .strip_prefix(Self::PREFIX)
+            .and_then(|key| key.split_once('.'))
+            .ok_or(Error::ApiKeyInvalidFormat)?;
+
+        if id.is_empty() || secret.is_empty() {
+            return Err(Error::ApiKeyInvalidFormat);
+        }
+
+        Ok(Self {
+            id: id.to_string(),
+            secret: secret.to_string(),
+        })
+    }
+}
+
+impl Display for ApiKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}.{}", Self::PREFIX, &self.id, &self.secret)
+    }
+}
+
+/// Code that lets someone sign up when the server is invite-only
+///
+/// Codes are stored as is, they are short to be easy to share and only grant a signup
+#[derive(Debug)]
+pub struct InviteCode(pub String);
+
+impl InviteCode {
+    pub fn new() -> Self {
+        let mut rng = rand::thread_rng();
+        let mut code = [0u8; 12];
+        rng.fill_bytes(&mut code);
+
+        Self(b64::encode(code))
+    }
+}
+
+impl Default for InviteCode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,160 @@
+use super::{
+    b64,
+    error::Error,
+    passwd::{gen_salt, passwd_encrypt, verify_encrypted_passwd},
+};
+use argon2::password_hash;
+use base32::Alphabet;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use rsa::sha2::{Digest, Sha512};
+use sha1::Sha1;
+
+/// RFC 6238 parameters, the ones every authenticator app supports
+const PERIOD_SECS: u64 = 30;
+const DIGITS: u32 = 6;
+const SECRET_LEN: usize = 20;
+const ISSUER: &str = "Ripfy";
+
+/// Codes of the previous and the next time step are accepted as well, so a clock that is a bit
+/// off does not lock the user out
+const ALLOWED_SKEW_STEPS: u64 = 1;
+
+const BASE32: Alphabet = Alphabet::RFC4648 { padding: false };
+
+/// Generates a random base32 encoded secret, the format authenticator apps expect
+pub fn gen_secret() -> String {
+    let mut rng = rand::thread_rng();
+    let mut secret = [0u8; SECRET_LEN];
+    rng.fill_bytes(&mut secret);
+
+    base32::encode(BASE32, &secret)
+}
+
+/// Returns the otpauth:// URI authenticator apps read from a QR code to enroll the secret
+pub fn provisioning_uri(secret: &str, username: &str) -> String {
+    format!(
+        "otpauth://totp/{ISSUER}:{}?secret={secret}&issuer={ISSUER}&algorithm=SHA1&digits={DIGITS}&period={PERIOD_SECS}",
+        percent_encode(username)
+    )
+}
+
+/// Returns the time step the unix time falls in
+pub fn time_step(unix_time: u64) -> u64 {
+    unix_time / PERIOD_SECS
+}
+
+/// Returns the code of the secret for the time step
+pub fn code_at(secret: &str, step: u64) -> Result<String, Error> {
+    let key = base32::decode(BASE32, secret).ok_or(Error::TotpInvalidSecret)?;
+
+    let mut mac = Hmac::<Sha1>::new_from_slice(&key).map_err(|_| Error::TotpInvalidSecret)?;
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    // dynamic truncation, RFC 4226 section 5.3
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+
+    Ok(format!(
+        "{:0width$}",
+        binary % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    ))
+}
+
+/// Checks the code against the secret at the unix time
+///
+/// Returns the time step the code belongs to, so callers can refuse a code that was already used
+pub fn verify_code(secret: &str, code: &str, unix_time: u64) -> Result<Option<u64>, Error> {
+    let code = code.trim();
+    let current_step = time_step(unix_time);
+
+    for step in current_step.saturating_sub(ALLOWED_SKEW_STEPS)..=current_step + ALLOWED_SKEW_STEPS
+    {
+        if constant_time_eq(code_at(secret, step)?.as_bytes(), code.as_bytes()) {
+            return Ok(Some(step));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Single-use code that replaces a totp code when the authenticator is lost
+///
+/// Has the format `xxxxx-xxxxx` so it is easy to write down, only its hash is stored
+pub struct RecoveryCode(pub String);
+
+impl RecoveryCode {
+    pub const COUNT: usize = 10;
+
+    pub fn new() -> Self {
+        let mut rng = rand::thread_rng();
+        let mut code = [0u8; 7];
+        rng.fill_bytes(&mut code);
+
+        let code = base32::encode(BASE32, &code).to_lowercase();
+
+        Self(format!("{}-{}", &code[..5], &code[5..10]))
+    }
+
+    /// Returns the hash of the code the way it is stored
+    ///
+    /// The codes are short enough to be brute-forced from a plain hash, so they are salted and
+    /// hashed with argon2 like passwords
+    pub fn hash(code: &str) -> Result<String, password_hash::Error> {
+        passwd_encrypt(Self::normalize(code), gen_salt())
+    }
+
+    /// Whether the code is the one of the stored hash
+    ///
+    /// Codes generated before they were hashed with argon2 have a plain sha512 hash, they keep
+    /// working until the user generates new codes
+    pub fn verify(code: &str, code_hash: &str) -> bool {
+        let normalized = Self::normalize(code);
+
+        if code_hash.starts_with('$') {
+            return verify_encrypted_passwd(normalized, code_hash).unwrap_or(false);
+        }
+
+        let legacy_hash = b64::encode(Sha512::digest(normalized.as_bytes()));
+
+        constant_time_eq(legacy_hash.as_bytes(), code_hash.as_bytes())
+    }
+
+    /// Dashes, spaces and case are ignored so codes can be typed back however they were written
+    /// down
+    fn normalize(code: &str) -> String {
+        code.chars()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .map(|c| c.to_ascii_lowercase())
+            .collect()
+    }
+}
+
+impl Default for RecoveryCode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
@@ -0,0 +1,85 @@
+use crate::AppState;
+use entity::api_key;
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ActiveValue, ColumnTrait, DbErr, EntityTrait, QueryFilter,
+    QueryOrder,
+};
+
+pub async fn first_by_id(state: &AppState, id: &str) -> Result<Option<api_key::Model>, DbErr> {
+    let db = &state.db;
+
+    let api_key = api_key::Entity::find_by_id(id).one(db).await?;
+
+    Ok(api_key)
+}
+
+pub async fn all_by_user_id(state: &AppState, user_id: &str) -> Result<Vec<api_key::Model>, DbErr> {
+    let db = &state.db;
+
+    let api_keys = api_key::Entity::find()
+        .filter(api_key::Column::UserId.eq(user_id))
+        .order_by_asc(api_key::Column::CreatedAt)
+        .all(db)
+        .await?;
+
+    Ok(api_keys)
+}
+
+/// Creates a new api key entity on the database and Returns it
+///
+/// Only the argon2 hash of the key secret is stored, the raw key is only shown once on creation
+///
+/// Returns sea_orm::DbErr if the INSERT operation fails
+pub async fn create_new(
+    state: &AppState,
+    id: &str,
+    user_id: &str,
+    name: &str,
+    key_hash: &str,
+    scope: &str,
+    expires_at: Option<String>,
+) -> Result<api_key::Model, DbErr> {
+    let db = &state.db;
+
+    let new_api_key = api_key::ActiveModel {
+        id: ActiveValue::Set(id.to_string()),
+        user_id: ActiveValue::Set(user_id.to_string()),
+        name: ActiveValue::Set(name.to_string()),
+        key_hash: ActiveValue::Set(key_hash.to_string()),
+        scope: ActiveValue::Set(scope.to_string()),
+        expires_at: ActiveValue::Set(expires_at),
+        last_used_at: ActiveValue::Set(None),
+        ..Default::default()
+    };
+
+    let new_api_key = new_api_key.insert(db).await?;
+
+    Ok(new_api_key)
+}
+
+pub async fn touch(state: &AppState, id: &str, now: &str) -> Result<(), DbErr> {
+    let db = &state.db;
+
+    api_key::Entity::update_many()
+        .col_expr(api_key::Column::LastUsedAt, Expr::value(now))
+        .filter(api_key::Column::Id.eq(id))
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+/// Deletes an api key owned by the user
+///
+/// Returns Ok(false) if the user does not have an api key with that id
+pub async fn delete(state: &AppState, id: &str, user_id: &str) -> Result<bool, DbErr> {
+    let db = &state.db;
+
+    let result = api_key::Entity::delete_many()
+        .filter(api_key::Column::Id.eq(id))
+        .filter(api_key::Column::UserId.eq(user_id))
+        .exec(db)
+        .await?;
+
+    Ok(result.rows_affected == 1)
+}
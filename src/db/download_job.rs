@@ -0,0 +1,196 @@
+use crate::{jobs::JobState, AppState};
+use entity::download_job;
+use sea_orm::{
+    sea_query::{Expr, Query, SelectStatement},
+    ActiveModelTrait, ActiveValue, ColumnTrait, DbErr, EntityTrait, QueryFilter, QueryOrder,
+};
+
+/// Finds a download job of the user and Returns it
+///
+/// WILL NOT return a job requested by another user
+pub async fn first_by_id(
+    state: &AppState,
+    id: &str,
+    user_id: &str,
+) -> Result<Option<download_job::Model>, DbErr> {
+    let db = &state.db;
+
+    let job = download_job::Entity::find_by_id(id)
+        .filter(download_job::Column::UserId.eq(user_id))
+        .one(db)
+        .await?;
+
+    Ok(job)
+}
+
+/// Returns every download job of the user, newest first
+pub async fn all_by_user_id(
+    state: &AppState,
+    user_id: &str,
+) -> Result<Vec<download_job::Model>, DbErr> {
+    let db = &state.db;
+
+    let jobs = download_job::Entity::find()
+        .filter(download_job::Column::UserId.eq(user_id))
+        .order_by_desc(download_job::Column::CreatedAt)
+        .all(db)
+        .await?;
+
+    Ok(jobs)
+}
+
+/// Creates a new queued download job and Returns it
+///
+/// Returns sea_orm::DbErr if the INSERT operation fails
+pub async fn create_new(
+    state: &AppState,
+    id: &str,
+    user_id: &str,
+    song_id: &str,
+) -> Result<download_job::Model, DbErr> {
+    let db = &state.db;
+
+    let new_job = download_job::ActiveModel {
+        id: ActiveValue::Set(id.to_string()),
+        user_id: ActiveValue::Set(user_id.to_string()),
+        song_id: ActiveValue::Set(song_id.to_string()),
+        state: ActiveValue::Set(JobState::Queued.as_ref().to_string()),
+        ..Default::default()
+    };
+
+    let new_job = new_job.insert(db).await?;
+
+    Ok(new_job)
+}
+
+/// Creates a new queued download job that adds the song to the playlist at position once it is
+/// downloaded and Returns it
+///
+/// Returns sea_orm::DbErr if the INSERT operation fails
+pub async fn create_new_for_playlist(
+    state: &AppState,
+    id: &str,
+    user_id: &str,
+    song_id: &str,
+    playlist_id: &str,
+    position: i32,
+) -> Result<download_job::Model, DbErr> {
+    let db = &state.db;
+
+    let new_job = download_job::ActiveModel {
+        id: ActiveValue::Set(id.to_string()),
+        user_id: ActiveValue::Set(user_id.to_string()),
+        song_id: ActiveValue::Set(song_id.to_string()),
+        state: ActiveValue::Set(JobState::Queued.as_ref().to_string()),
+        playlist_id: ActiveValue::Set(Some(playlist_id.to_string())),
+        playlist_position: ActiveValue::Set(Some(position)),
+        ..Default::default()
+    };
+
+    let new_job = new_job.insert(db).await?;
+
+    Ok(new_job)
+}
+
+/// Takes the oldest queued job and marks it as running
+///
+/// The job is only taken if it is still queued when it gets updated, so two workers never run
+/// the same job
+///
+/// Returns Ok(None) if there are no queued jobs
+pub async fn claim_next(
+    state: &AppState,
+    started_at: &str,
+) -> Result<Option<download_job::Model>, DbErr> {
+    let db = &state.db;
+
+    loop {
+        let Some(job) = download_job::Entity::find()
+            .filter(download_job::Column::State.eq(JobState::Queued.as_ref()))
+            .order_by_asc(download_job::Column::CreatedAt)
+            .one(db)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        let result = download_job::Entity::update_many()
+            .col_expr(
+                download_job::Column::State,
+                Expr::value(JobState::Running.as_ref()),
+            )
+            .col_expr(download_job::Column::StartedAt, Expr::value(started_at))
+            .filter(download_job::Column::Id.eq(&job.id))
+            .filter(download_job::Column::State.eq(JobState::Queued.as_ref()))
+            .exec(db)
+            .await?;
+
+        // another worker took it first, tries the next one
+        if result.rows_affected == 1 {
+            return Ok(Some(download_job::Model {
+                state: JobState::Running.as_ref().to_string(),
+                started_at: Some(started_at.to_string()),
+                ..job
+            }));
+        }
+    }
+}
+
+/// Marks a job as done or failed, along with the reason it failed
+pub async fn finish(
+    state: &AppState,
+    id: &str,
+    job_state: JobState,
+    error: Option<String>,
+    finished_at: &str,
+) -> Result<(), DbErr> {
+    let db = &state.db;
+
+    download_job::Entity::update_many()
+        .col_expr(download_job::Column::State, Expr::value(job_state.as_ref()))
+        .col_expr(download_job::Column::Error, Expr::value(error))
+        .col_expr(download_job::Column::FinishedAt, Expr::value(finished_at))
+        .filter(download_job::Column::Id.eq(id))
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+/// Puts jobs that were running back on the queue
+///
+/// Only called on startup, a job can only be running then if the server stopped in the middle of
+/// it
+///
+/// Returns how many jobs were put back
+pub async fn requeue_running(state: &AppState) -> Result<u64, DbErr> {
+    let db = &state.db;
+
+    let result = download_job::Entity::update_many()
+        .col_expr(
+            download_job::Column::State,
+            Expr::value(JobState::Queued.as_ref()),
+        )
+        .col_expr(
+            download_job::Column::StartedAt,
+            Expr::value(Option::<String>::None),
+        )
+        .filter(download_job::Column::State.eq(JobState::Running.as_ref()))
+        .exec(db)
+        .await?;
+
+    Ok(result.rows_affected)
+}
+
+/// Ids of the songs with a job that is queued or running, a worker may store or link them any
+/// moment
+pub(super) fn active_song_ids() -> SelectStatement {
+    Query::select()
+        .column(download_job::Column::SongId)
+        .from(download_job::Entity)
+        .and_where(
+            download_job::Column::State
+                .is_in([JobState::Queued.as_ref(), JobState::Running.as_ref()]),
+        )
+        .to_owned()
+}
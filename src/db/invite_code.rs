@@ -0,0 +1,110 @@
+use crate::{
+    util::time::{now_utc, parse_utc},
+    AppState,
+};
+use entity::invite_code;
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DbErr,
+    EntityTrait, QueryFilter, QueryOrder,
+};
+
+pub async fn first_by_code(
+    state: &AppState,
+    code: &str,
+) -> Result<Option<invite_code::Model>, DbErr> {
+    let db = &state.db;
+
+    let invite_code = invite_code::Entity::find_by_id(code).one(db).await?;
+
+    Ok(invite_code)
+}
+
+pub async fn all_by_creator(
+    state: &AppState,
+    user_id: &str,
+) -> Result<Vec<invite_code::Model>, DbErr> {
+    let db = &state.db;
+
+    let invite_codes = invite_code::Entity::find()
+        .filter(invite_code::Column::CreatedBy.eq(user_id))
+        .order_by_asc(invite_code::Column::CreatedAt)
+        .all(db)
+        .await?;
+
+    Ok(invite_codes)
+}
+
+/// Creates a new invite code entity on the database and Returns it
+///
+/// Returns sea_orm::DbErr if the INSERT operation fails
+pub async fn create_new(
+    state: &AppState,
+    code: &str,
+    created_by: &str,
+    max_uses: i32,
+    expires_at: Option<String>,
+) -> Result<invite_code::Model, DbErr> {
+    let db = &state.db;
+
+    let new_invite_code = invite_code::ActiveModel {
+        code: ActiveValue::Set(code.to_string()),
+        created_by: ActiveValue::Set(created_by.to_string()),
+        max_uses: ActiveValue::Set(max_uses),
+        uses: ActiveValue::Set(0),
+        expires_at: ActiveValue::Set(expires_at),
+        ..Default::default()
+    };
+
+    let new_invite_code = new_invite_code.insert(db).await?;
+
+    Ok(new_invite_code)
+}
+
+/// Uses the invite code once, unless it expired
+///
+/// The check and the update happen in the same query, so a code can never be used more times
+/// than its max_uses, even by concurrent signups
+/// Takes a connection so it can run in the transaction of the signup
+///
+/// Returns Ok(false) if the code does not exist, expired or has no uses left
+pub async fn consume(db: &impl ConnectionTrait, code: &str) -> Result<bool, DbErr> {
+    let Some(invite_code) = invite_code::Entity::find_by_id(code).one(db).await? else {
+        return Ok(false);
+    };
+
+    if let Some(expires_at) = &invite_code.expires_at {
+        let is_expired = parse_utc(expires_at).map_or(true, |t| t < now_utc());
+        if is_expired {
+            return Ok(false);
+        }
+    }
+
+    let result = invite_code::Entity::update_many()
+        .col_expr(
+            invite_code::Column::Uses,
+            Expr::col(invite_code::Column::Uses).add(1),
+        )
+        .filter(invite_code::Column::Code.eq(code))
+        .filter(Expr::col(invite_code::Column::Uses).lt(Expr::col(invite_code::Column::MaxUses)))
+        .exec(db)
+        .await?;
+
+    Ok(result.rows_affected == 1)
+}
+
+/// Deletes an invite code, only the user that created it can delete it unless created_by is None
+///
+/// Returns Ok(false) if there is no code to delete
+pub async fn delete(state: &AppState, code: &str, created_by: Option<&str>) -> Result<bool, DbErr> {
+    let db = &state.db;
+
+    let mut delete = invite_code::Entity::delete_many().filter(invite_code::Column::Code.eq(code));
+
+    if let Some(created_by) = created_by {
+        delete = delete.filter(invite_code::Column::CreatedBy.eq(created_by));
+    }
+
+    let result = delete.exec(db).await?;
+
+    Ok(result.rows_affected == 1)
+}
@@ -1,14 +1,38 @@
 use entity::playlist_song;
-use sea_orm::{ActiveModelTrait, ActiveValue, DbErr, EntityTrait};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, DbErr, EntityTrait, QueryFilter, QueryOrder,
+};
 
 use crate::AppState;
 
+/// Adds the song to the end of the playlist
 pub async fn create_new(state: &AppState, playlist_id: &str, song_id: &str) -> Result<(), DbErr> {
     let db = &state.db;
 
+    let last = playlist_song::Entity::find()
+        .filter(playlist_song::Column::PlaylistId.eq(playlist_id))
+        .order_by_desc(playlist_song::Column::Position)
+        .one(db)
+        .await?;
+
+    let position = last.map(|s| s.position + 1).unwrap_or_default();
+
+    create_new_at(state, playlist_id, song_id, position).await
+}
+
+/// Adds the song to the playlist at position, songs of a playlist are sorted by it
+pub async fn create_new_at(
+    state: &AppState,
+    playlist_id: &str,
+    song_id: &str,
+    position: i32,
+) -> Result<(), DbErr> {
+    let db = &state.db;
+
     let new_playlist_song = playlist_song::ActiveModel {
         playlist_id: ActiveValue::Set(playlist_id.into()),
         song_id: ActiveValue::Set(song_id.into()),
+        position: ActiveValue::Set(position),
         ..Default::default()
     };
 
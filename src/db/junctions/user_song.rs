@@ -1,12 +1,13 @@
 use crate::AppState;
-use entity::{playlist, playlist_song, user_song};
+use entity::{playlist, playlist_song, song, user_song};
 use sea_orm::{
-    sea_query::Query, ActiveModelTrait, ActiveValue, ColumnTrait, Condition, DbErr, EntityTrait,
-    QueryFilter,
+    sea_query::{Expr, Query},
+    ActiveModelTrait, ActiveValue, ColumnTrait, Condition, DbErr, EntityTrait, QueryFilter,
 };
 
 /// Creates a new UserSong junction table that associates an user with a song
-/// Returns Ok(()) when successful and sea_orm::DbErr when INSERT fails
+/// The song is not orphaned anymore, so the garbage collector leaves it alone
+/// Returns Ok(()) when successful and sea_orm::DbErr when INSERT or UPDATE fails
 pub async fn create_new(state: &AppState, user_id: &str, song_id: &str) -> Result<(), DbErr> {
     let db = &state.db;
 
@@ -18,9 +19,37 @@ pub async fn create_new(state: &AppState, user_id: &str, song_id: &str) -> Resul
 
     new_user_song.insert(db).await?;
 
+    song::Entity::update_many()
+        .col_expr(
+            song::Column::OrphanedAt,
+            Expr::value(Option::<String>::None),
+        )
+        .filter(song::Column::Id.eq(song_id))
+        .filter(song::Column::OrphanedAt.is_not_null())
+        .exec(db)
+        .await?;
+
     Ok(())
 }
 
+/// Returns the ids of every song in the library of the user
+pub async fn all_song_ids_by_user_id(
+    state: &AppState,
+    user_id: &str,
+) -> Result<Vec<String>, DbErr> {
+    let db = &state.db;
+
+    let song_ids = user_song::Entity::find()
+        .filter(user_song::Column::UserId.eq(user_id))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|us| us.song_id)
+        .collect();
+
+    Ok(song_ids)
+}
+
 pub async fn delete(state: &AppState, user_id: &str, song_id: &str) -> Result<(), DbErr> {
     let db = &state.db;
 
@@ -0,0 +1,82 @@
+use crate::AppState;
+use entity::login_failure;
+use sea_orm::{
+    sea_query::{Expr, OnConflict},
+    ActiveModelTrait, ActiveValue, ColumnTrait, DbErr, EntityTrait, QueryFilter,
+};
+
+pub async fn first_by_key(
+    state: &AppState,
+    key: &str,
+) -> Result<Option<login_failure::Model>, DbErr> {
+    let db = &state.db;
+
+    let login_failure = login_failure::Entity::find_by_id(key).one(db).await?;
+
+    Ok(login_failure)
+}
+
+/// Counts one more failed login for the key and Returns the updated entity
+///
+/// The count is incremented by the database, so concurrent failures are never lost
+pub async fn increment(
+    state: &AppState,
+    key: &str,
+    failed_at: &str,
+) -> Result<login_failure::Model, DbErr> {
+    let db = &state.db;
+
+    let new_login_failure = login_failure::ActiveModel {
+        key: ActiveValue::Set(key.to_string()),
+        failures: ActiveValue::Set(1),
+        last_failure_at: ActiveValue::Set(failed_at.to_string()),
+        locked_until: ActiveValue::Set(None),
+    };
+
+    login_failure::Entity::insert(new_login_failure)
+        .on_conflict(
+            OnConflict::column(login_failure::Column::Key)
+                .value(
+                    login_failure::Column::Failures,
+                    Expr::col(login_failure::Column::Failures).add(1),
+                )
+                .update_column(login_failure::Column::LastFailureAt)
+                .to_owned(),
+        )
+        .exec(db)
+        .await?;
+
+    first_by_key(state, key)
+        .await?
+        .ok_or(DbErr::RecordNotFound(key.to_string()))
+}
+
+pub async fn set_locked_until(
+    state: &AppState,
+    key: &str,
+    locked_until: &str,
+) -> Result<(), DbErr> {
+    let db = &state.db;
+
+    login_failure::ActiveModel {
+        key: ActiveValue::Unchanged(key.to_string()),
+        locked_until: ActiveValue::Set(Some(locked_until.to_string())),
+        ..Default::default()
+    }
+    .update(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Forgets every failed login of the key
+pub async fn delete(state: &AppState, key: &str) -> Result<(), DbErr> {
+    let db = &state.db;
+
+    login_failure::Entity::delete_many()
+        .filter(login_failure::Column::Key.eq(key))
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
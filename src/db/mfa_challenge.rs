@@ -0,0 +1,101 @@
+use crate::AppState;
+use entity::{mfa_challenge, recovery_code};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ActiveValue, ColumnTrait, DbErr, EntityTrait, QueryFilter,
+    TransactionTrait,
+};
+
+pub async fn first_by_id(
+    state: &AppState,
+    id: &str,
+) -> Result<Option<mfa_challenge::Model>, DbErr> {
+    let db = &state.db;
+
+    let challenge = mfa_challenge::Entity::find_by_id(id).one(db).await?;
+
+    Ok(challenge)
+}
+
+/// Stores a new mfa challenge, started by a login with the correct password, and Returns it
+///
+/// Only the hash of the token secret is stored
+///
+/// Returns sea_orm::DbErr if the INSERT operation fails
+pub async fn create_new(
+    state: &AppState,
+    id: &str,
+    user_id: &str,
+    token_hash: &str,
+    expires_at: &str,
+) -> Result<mfa_challenge::Model, DbErr> {
+    let db = &state.db;
+
+    let new_challenge = mfa_challenge::ActiveModel {
+        id: ActiveValue::Set(id.to_string()),
+        user_id: ActiveValue::Set(user_id.to_string()),
+        token_hash: ActiveValue::Set(token_hash.to_string()),
+        used: ActiveValue::Set(false),
+        expires_at: ActiveValue::Set(expires_at.to_string()),
+    };
+
+    let new_challenge = new_challenge.insert(db).await?;
+
+    Ok(new_challenge)
+}
+
+/// Marks a challenge as used, so its token can not start another session
+///
+/// Returns Ok(false) if the challenge was already used
+pub async fn mark_used(state: &AppState, id: &str) -> Result<bool, DbErr> {
+    let db = &state.db;
+
+    let result = mfa_challenge::Entity::update_many()
+        .col_expr(mfa_challenge::Column::Used, Expr::value(true))
+        .filter(mfa_challenge::Column::Id.eq(id))
+        .filter(mfa_challenge::Column::Used.eq(false))
+        .exec(db)
+        .await?;
+
+    Ok(result.rows_affected == 1)
+}
+
+/// Marks a challenge as used and then uses the recovery code, in a single transaction, so a
+/// recovery code is never spent on a challenge that was already used
+///
+/// Nothing is changed if either of them was already used
+///
+/// Returns Ok(None) if the challenge was already used, Ok(Some(false)) if the recovery code was
+pub async fn mark_used_with_recovery_code(
+    state: &AppState,
+    id: &str,
+    recovery_code_id: &str,
+) -> Result<Option<bool>, DbErr> {
+    let txn = state.db.begin().await?;
+
+    let result = mfa_challenge::Entity::update_many()
+        .col_expr(mfa_challenge::Column::Used, Expr::value(true))
+        .filter(mfa_challenge::Column::Id.eq(id))
+        .filter(mfa_challenge::Column::Used.eq(false))
+        .exec(&txn)
+        .await?;
+
+    if result.rows_affected != 1 {
+        return Ok(None);
+    }
+
+    let result = recovery_code::Entity::update_many()
+        .col_expr(recovery_code::Column::Used, Expr::value(true))
+        .filter(recovery_code::Column::Id.eq(recovery_code_id))
+        .filter(recovery_code::Column::Used.eq(false))
+        .exec(&txn)
+        .await?;
+
+    // dropping the transaction rolls back the challenge, so the user can try again
+    if result.rows_affected != 1 {
+        return Ok(Some(false));
+    }
+
+    txn.commit().await?;
+
+    Ok(Some(true))
+}
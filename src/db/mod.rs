@@ -1,6 +1,16 @@
+pub mod api_key;
+pub mod download_job;
+pub mod invite_code;
 pub mod junctions;
+pub mod login_failure;
+pub mod mfa_challenge;
+pub mod passwd_reset_token;
 pub mod playlist;
+pub mod recovery_code;
+pub mod refresh_token;
+pub mod session;
 pub mod song;
+pub mod totp;
 pub mod user;
 
 use crate::config;
@@ -0,0 +1,87 @@
+use super::{session, user};
+use crate::AppState;
+use entity::passwd_reset_token;
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DbErr,
+    EntityTrait, QueryFilter, TransactionTrait,
+};
+
+pub async fn first_by_id(
+    state: &AppState,
+    id: &str,
+) -> Result<Option<passwd_reset_token::Model>, DbErr> {
+    let db = &state.db;
+
+    let token = passwd_reset_token::Entity::find_by_id(id).one(db).await?;
+
+    Ok(token)
+}
+
+/// Stores a new password reset token and Returns it
+///
+/// Only the hash of the token secret is stored, the raw token is only shown to the admin that
+/// issued it
+///
+/// Returns sea_orm::DbErr if the INSERT operation fails
+pub async fn create_new(
+    state: &AppState,
+    id: &str,
+    user_id: &str,
+    token_hash: &str,
+    expires_at: &str,
+) -> Result<passwd_reset_token::Model, DbErr> {
+    let db = &state.db;
+
+    let new_token = passwd_reset_token::ActiveModel {
+        id: ActiveValue::Set(id.to_string()),
+        user_id: ActiveValue::Set(user_id.to_string()),
+        token_hash: ActiveValue::Set(token_hash.to_string()),
+        used: ActiveValue::Set(false),
+        expires_at: ActiveValue::Set(expires_at.to_string()),
+        ..Default::default()
+    };
+
+    let new_token = new_token.insert(db).await?;
+
+    Ok(new_token)
+}
+
+/// Marks a token as used, so it can not be used again
+///
+/// The check and the update happen in the same query, so when two requests try to use the same
+/// token at once only one of them gets Ok(true)
+///
+/// Returns Ok(false) if the token was already used
+pub async fn mark_used(db: &impl ConnectionTrait, id: &str) -> Result<bool, DbErr> {
+    let result = passwd_reset_token::Entity::update_many()
+        .col_expr(passwd_reset_token::Column::Used, Expr::value(true))
+        .filter(passwd_reset_token::Column::Id.eq(id))
+        .filter(passwd_reset_token::Column::Used.eq(false))
+        .exec(db)
+        .await?;
+
+    Ok(result.rows_affected == 1)
+}
+
+/// Uses the token to replace the password of its user and revokes every session of the user, in
+/// a single transaction, so the token is not spent when the password could not be replaced
+///
+/// Returns Ok(false) if the token was already used
+pub async fn reset_passwd(
+    state: &AppState,
+    token: &passwd_reset_token::Model,
+    passwd: &str,
+) -> Result<bool, DbErr> {
+    let txn = state.db.begin().await?;
+
+    if !mark_used(&txn, &token.id).await? {
+        return Ok(false);
+    }
+
+    user::update_passwd(&txn, &token.user_id, passwd).await?;
+    session::revoke_all_by_user_id(&txn, &token.user_id).await?;
+
+    txn.commit().await?;
+
+    Ok(true)
+}
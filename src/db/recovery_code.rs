@@ -0,0 +1,73 @@
+use crate::AppState;
+use entity::recovery_code;
+use sea_orm::{
+    ActiveValue, ColumnTrait, DbErr, EntityTrait, PaginatorTrait, QueryFilter, TransactionTrait,
+};
+
+/// Replaces every recovery code of the user with the new ones in a single transaction
+///
+/// Only the hashes of the codes are stored
+pub async fn replace_all(
+    state: &AppState,
+    user_id: &str,
+    code_hashes: &[String],
+) -> Result<(), DbErr> {
+    let txn = state.db.begin().await?;
+
+    recovery_code::Entity::delete_many()
+        .filter(recovery_code::Column::UserId.eq(user_id))
+        .exec(&txn)
+        .await?;
+
+    let new_codes = code_hashes
+        .iter()
+        .map(|code_hash| recovery_code::ActiveModel {
+            id: ActiveValue::Set(uuid::Uuid::new_v4().to_string()),
+            user_id: ActiveValue::Set(user_id.to_string()),
+            code_hash: ActiveValue::Set(code_hash.to_string()),
+            used: ActiveValue::Set(false),
+        });
+
+    recovery_code::Entity::insert_many(new_codes)
+        .exec_without_returning(&txn)
+        .await?;
+
+    txn.commit().await?;
+
+    Ok(())
+}
+
+/// Returns the recovery codes of the user that were not used yet
+pub async fn all_unused_by_user_id(
+    state: &AppState,
+    user_id: &str,
+) -> Result<Vec<recovery_code::Model>, DbErr> {
+    let db = &state.db;
+
+    recovery_code::Entity::find()
+        .filter(recovery_code::Column::UserId.eq(user_id))
+        .filter(recovery_code::Column::Used.eq(false))
+        .all(db)
+        .await
+}
+
+pub async fn count_unused_by_user_id(state: &AppState, user_id: &str) -> Result<u64, DbErr> {
+    let db = &state.db;
+
+    recovery_code::Entity::find()
+        .filter(recovery_code::Column::UserId.eq(user_id))
+        .filter(recovery_code::Column::Used.eq(false))
+        .count(db)
+        .await
+}
+
+pub async fn delete_all_by_user_id(state: &AppState, user_id: &str) -> Result<(), DbErr> {
+    let db = &state.db;
+
+    recovery_code::Entity::delete_many()
+        .filter(recovery_code::Column::UserId.eq(user_id))
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,108 @@
+use crate::AppState;
+use entity::refresh_token;
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DbErr,
+    EntityTrait, QueryFilter,
+};
+
+pub async fn first_by_id(
+    state: &AppState,
+    id: &str,
+) -> Result<Option<refresh_token::Model>, DbErr> {
+    let db = &state.db;
+
+    let refresh_token = refresh_token::Entity::find_by_id(id).one(db).await?;
+
+    Ok(refresh_token)
+}
+
+/// Stores a new refresh token and Returns it
+///
+/// Only the hash of the token secret is stored, the raw secret is only known by the client
+///
+/// Returns sea_orm::DbErr if the INSERT operation fails
+pub async fn create_new(
+    state: &AppState,
+    id: &str,
+    user_id: &str,
+    family_id: &str,
+    token_hash: &str,
+    expires_at: &str,
+) -> Result<refresh_token::Model, DbErr> {
+    let db = &state.db;
+
+    let new_refresh_token = refresh_token::ActiveModel {
+        id: ActiveValue::Set(id.to_string()),
+        user_id: ActiveValue::Set(user_id.to_string()),
+        family_id: ActiveValue::Set(family_id.to_string()),
+        token_hash: ActiveValue::Set(token_hash.to_string()),
+        revoked: ActiveValue::Set(false),
+        expires_at: ActiveValue::Set(expires_at.to_string()),
+        ..Default::default()
+    };
+
+    let new_refresh_token = new_refresh_token.insert(db).await?;
+
+    Ok(new_refresh_token)
+}
+
+/// Revokes a single refresh token
+///
+/// The check and the update happen in the same query, so when two requests try to use the same
+/// token at once only one of them gets Ok(true)
+///
+/// Returns Ok(false) if the token was already revoked
+pub async fn revoke(state: &AppState, id: &str) -> Result<bool, DbErr> {
+    let db = &state.db;
+
+    let result = refresh_token::Entity::update_many()
+        .col_expr(refresh_token::Column::Revoked, Expr::value(true))
+        .filter(refresh_token::Column::Id.eq(id))
+        .filter(refresh_token::Column::Revoked.eq(false))
+        .exec(db)
+        .await?;
+
+    Ok(result.rows_affected == 1)
+}
+
+/// Revokes every refresh token that descends from the same login
+///
+/// The family id of a refresh token is the id of the session it was issued for
+pub async fn revoke_family(state: &AppState, family_id: &str) -> Result<(), DbErr> {
+    let db = &state.db;
+
+    refresh_token::Entity::update_many()
+        .col_expr(refresh_token::Column::Revoked, Expr::value(true))
+        .filter(refresh_token::Column::FamilyId.eq(family_id))
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn revoke_all_by_user_id(db: &impl ConnectionTrait, user_id: &str) -> Result<(), DbErr> {
+    refresh_token::Entity::update_many()
+        .col_expr(refresh_token::Column::Revoked, Expr::value(true))
+        .filter(refresh_token::Column::UserId.eq(user_id))
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn revoke_all_other_families_by_user_id(
+    state: &AppState,
+    user_id: &str,
+    family_id: &str,
+) -> Result<(), DbErr> {
+    let db = &state.db;
+
+    refresh_token::Entity::update_many()
+        .col_expr(refresh_token::Column::Revoked, Expr::value(true))
+        .filter(refresh_token::Column::UserId.eq(user_id))
+        .filter(refresh_token::Column::FamilyId.ne(family_id))
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,183 @@
+use super::refresh_token;
+use crate::{
+    util::time::{now_utc, parse_utc},
+    AppState,
+};
+use entity::session;
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DbErr,
+    EntityTrait, QueryFilter, QueryOrder,
+};
+
+/// Finds a session that was not revoked and has not expired
+///
+/// Requires the AppState, SessionId and the UserId of the User that owns the session
+pub async fn first_active_by_id(
+    state: &AppState,
+    session_id: &str,
+    user_id: &str,
+) -> Result<Option<session::Model>, DbErr> {
+    let db = &state.db;
+
+    let session = session::Entity::find_by_id(session_id)
+        .filter(session::Column::UserId.eq(user_id))
+        .filter(session::Column::Revoked.eq(false))
+        .one(db)
+        .await?
+        .filter(is_unexpired);
+
+    Ok(session)
+}
+
+/// Returns every session of the user that was not revoked and has not expired, most recently
+/// seen first
+pub async fn all_active_by_user_id(
+    state: &AppState,
+    user_id: &str,
+) -> Result<Vec<session::Model>, DbErr> {
+    let db = &state.db;
+
+    let sessions = session::Entity::find()
+        .filter(session::Column::UserId.eq(user_id))
+        .filter(session::Column::Revoked.eq(false))
+        .order_by_desc(session::Column::LastSeenAt)
+        .all(db)
+        .await?
+        .into_iter()
+        .filter(is_unexpired)
+        .collect();
+
+    Ok(sessions)
+}
+
+/// The expiration is stored as a RFC3339 string, so it is compared here instead of in the query
+/// A session whose expiration can not be parsed counts as expired
+fn is_unexpired(session: &session::Model) -> bool {
+    parse_utc(&session.expires_at).is_ok_and(|expires_at| expires_at > now_utc())
+}
+
+/// Creates a new session entity on the database and Returns it
+///
+/// Returns sea_orm::DbErr if the INSERT operation fails
+pub async fn create_new(
+    state: &AppState,
+    user_id: &str,
+    device: Option<String>,
+    ip: Option<String>,
+    now: &str,
+    expires_at: &str,
+) -> Result<session::Model, DbErr> {
+    let db = &state.db;
+
+    let new_session = session::ActiveModel {
+        id: ActiveValue::Set(uuid::Uuid::new_v4().to_string()),
+        user_id: ActiveValue::Set(user_id.to_string()),
+        device: ActiveValue::Set(device),
+        ip: ActiveValue::Set(ip),
+        revoked: ActiveValue::Set(false),
+        last_seen_at: ActiveValue::Set(now.to_string()),
+        expires_at: ActiveValue::Set(expires_at.to_string()),
+        ..Default::default()
+    };
+
+    let new_session = new_session.insert(db).await?;
+
+    Ok(new_session)
+}
+
+/// Updates when and from where the session was last used
+pub async fn touch(
+    state: &AppState,
+    session_id: &str,
+    ip: Option<String>,
+    now: &str,
+) -> Result<(), DbErr> {
+    let db = &state.db;
+
+    let mut update =
+        session::Entity::update_many().col_expr(session::Column::LastSeenAt, Expr::value(now));
+
+    if let Some(ip) = ip {
+        update = update.col_expr(session::Column::Ip, Expr::value(ip));
+    }
+
+    update
+        .filter(session::Column::Id.eq(session_id))
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+/// Pushes the expiration of the session, used when its refresh token is rotated
+pub async fn extend(state: &AppState, session_id: &str, expires_at: &str) -> Result<(), DbErr> {
+    let db = &state.db;
+
+    session::Entity::update_many()
+        .col_expr(session::Column::ExpiresAt, Expr::value(expires_at))
+        .filter(session::Column::Id.eq(session_id))
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+/// Revokes a session and every refresh token issued for it
+///
+/// Returns Ok(false) if the user does not have an active session with that id
+pub async fn revoke(state: &AppState, session_id: &str, user_id: &str) -> Result<bool, DbErr> {
+    let db = &state.db;
+
+    let result = session::Entity::update_many()
+        .col_expr(session::Column::Revoked, Expr::value(true))
+        .filter(session::Column::Id.eq(session_id))
+        .filter(session::Column::UserId.eq(user_id))
+        .filter(session::Column::Revoked.eq(false))
+        .exec(db)
+        .await?;
+
+    let revoked = result.rows_affected == 1;
+
+    // the refresh token family of a session shares its id
+    if revoked {
+        refresh_token::revoke_family(state, session_id).await?;
+    }
+
+    Ok(revoked)
+}
+
+/// Revokes every session of the user and every refresh token issued for them
+pub async fn revoke_all_by_user_id(db: &impl ConnectionTrait, user_id: &str) -> Result<(), DbErr> {
+    session::Entity::update_many()
+        .col_expr(session::Column::Revoked, Expr::value(true))
+        .filter(session::Column::UserId.eq(user_id))
+        .exec(db)
+        .await?;
+
+    refresh_token::revoke_all_by_user_id(db, user_id).await?;
+
+    Ok(())
+}
+
+/// Revokes every session of the user but the given one, along with their refresh tokens
+///
+/// Used when the password changes, so the device that changed it stays logged in
+pub async fn revoke_all_other_by_user_id(
+    state: &AppState,
+    user_id: &str,
+    session_id: &str,
+) -> Result<(), DbErr> {
+    let db = &state.db;
+
+    session::Entity::update_many()
+        .col_expr(session::Column::Revoked, Expr::value(true))
+        .filter(session::Column::UserId.eq(user_id))
+        .filter(session::Column::Id.ne(session_id))
+        .exec(db)
+        .await?;
+
+    // the refresh token family of a session shares its id
+    refresh_token::revoke_all_other_families_by_user_id(state, user_id, session_id).await?;
+
+    Ok(())
+}
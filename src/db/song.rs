@@ -1,10 +1,27 @@
 use super::junctions;
-use crate::AppState;
+use crate::{util::yt_dlp::YtDlpResult, AppState};
 use entity::{playlist_song, song, user_song};
 use sea_orm::{
-    ActiveModelTrait, ActiveValue, ColumnTrait, DbErr, EntityTrait, JoinType, QueryFilter,
-    QuerySelect, RelationTrait,
+    sea_query::{Expr, Query},
+    ActiveModelTrait, ActiveValue, ColumnTrait, Condition, DbErr, EntityTrait, JoinType,
+    QueryFilter, QueryOrder, QuerySelect, RelationTrait,
 };
+use serde::{Deserialize, Serialize};
+
+/// Whether a song can be played, it is unavailable when its audio file is missing or broken and
+/// could not be downloaded again
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, strum_macros::AsRefStr)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum SongStatus {
+    Available,
+    Unavailable,
+}
+
+/// Whether the song can be played, unavailable songs are downloaded again when someone adds them
+pub fn is_available(song: &song::Model) -> bool {
+    song.status != SongStatus::Unavailable.as_ref()
+}
 
 /// Finds a song entity that is related by user_song to an user entity and Returns it
 ///
@@ -33,6 +50,47 @@ pub async fn first_by_id(
     Ok(song)
 }
 
+/// Finds a song entity no matter which users have it and Returns it
+///
+/// Used to tell if a song was already downloaded, NEVER return its result to a user that does not
+/// have the song
+pub async fn first_by_id_any_user(
+    state: &AppState,
+    song_id: &str,
+) -> Result<Option<song::Model>, DbErr> {
+    let db = &state.db;
+
+    let song = song::Entity::find_by_id(song_id).one(db).await?;
+
+    Ok(song)
+}
+
+/// Returns every song, no matter which users have it
+pub async fn all(state: &AppState) -> Result<Vec<song::Model>, DbErr> {
+    let db = &state.db;
+
+    let songs = song::Entity::find()
+        .order_by_asc(song::Column::Id)
+        .all(db)
+        .await?;
+
+    Ok(songs)
+}
+
+pub async fn set_status(state: &AppState, song_id: &str, status: SongStatus) -> Result<(), DbErr> {
+    let db = &state.db;
+
+    song::ActiveModel {
+        id: ActiveValue::Unchanged(song_id.to_string()),
+        status: ActiveValue::Set(status.as_ref().to_string()),
+        ..Default::default()
+    }
+    .update(db)
+    .await?;
+
+    Ok(())
+}
+
 pub async fn all_from_playlist(
     state: &AppState,
     playlist_id: &str,
@@ -42,32 +100,74 @@ pub async fn all_from_playlist(
     let songs = song::Entity::find()
         .join(JoinType::LeftJoin, song::Relation::PlaylistSong.def())
         .filter(playlist_song::Column::PlaylistId.eq(playlist_id))
+        .order_by_asc(playlist_song::Column::Position)
+        .order_by_asc(playlist_song::Column::AddedAt)
         .all(db)
         .await?;
 
     Ok(songs)
 }
 
+/// What is known about a song when it is added, everything but title and channel is optional
+#[derive(Debug, Default, Clone)]
+pub struct SongMetadata {
+    pub title: String,
+    pub channel: String,
+    pub duration_secs: Option<i32>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub track: Option<String>,
+    pub release_year: Option<i32>,
+    pub upload_date: Option<String>, // YYYY-MM-DD
+    pub thumbnail_url: Option<String>,
+}
+
+impl From<YtDlpResult> for SongMetadata {
+    fn from(result: YtDlpResult) -> Self {
+        Self {
+            duration_secs: result.duration.map(|d| d.round() as i32),
+            upload_date: result.upload_date_iso(),
+            title: result.fulltitle,
+            channel: result.channel,
+            artist: result.artist,
+            album: result.album,
+            track: result.track,
+            release_year: result.release_year,
+            thumbnail_url: result.thumbnail,
+        }
+    }
+}
+
 /// Creates a new song entity on the database and Returns it
 /// Also creates a user_song junction entity
 ///
-/// Requires the AppState, SongLinkId, SongTitle, SongChannel and the UserId of the User that made
-/// the request
+/// Requires the AppState, SongLinkId, the SongMetadata, the UserId of the User that made the
+/// request and when it was added
 ///
 /// Returns sea_orm::DbErr if any INSERT operation fails
 pub async fn create_new(
     state: &AppState,
     link_id: &str,
-    title: &str,
-    channel: &str,
+    metadata: SongMetadata,
     user_id: &str,
+    created_at: &str,
 ) -> Result<song::Model, DbErr> {
     let db = &state.db;
 
     let new_song = song::ActiveModel {
         id: ActiveValue::Set(link_id.to_string()),
-        title: ActiveValue::Set(title.to_string()),
-        channel: ActiveValue::Set(channel.to_string()),
+        title: ActiveValue::Set(metadata.title),
+        channel: ActiveValue::Set(metadata.channel),
+        duration_secs: ActiveValue::Set(metadata.duration_secs),
+        artist: ActiveValue::Set(metadata.artist),
+        album: ActiveValue::Set(metadata.album),
+        track: ActiveValue::Set(metadata.track),
+        release_year: ActiveValue::Set(metadata.release_year),
+        upload_date: ActiveValue::Set(metadata.upload_date),
+        thumbnail_url: ActiveValue::Set(metadata.thumbnail_url),
+        created_at: ActiveValue::Set(created_at.to_string()),
+        orphaned_at: ActiveValue::Set(None),
+        status: ActiveValue::Set(SongStatus::Available.as_ref().to_string()),
     };
 
     let new_song = new_song.insert(db).await?;
@@ -76,3 +176,84 @@ pub async fn create_new(
 
     Ok(new_song)
 }
+
+/// Songs that are in no library, no playlist and no download job that is queued or running
+///
+/// A song with such a job is about to get an owner, deleting it would leave the job linking a song
+/// whose files are gone
+fn is_orphaned() -> Condition {
+    Condition::all()
+        .add(
+            song::Column::Id.not_in_subquery(
+                Query::select()
+                    .column(user_song::Column::SongId)
+                    .from(user_song::Entity)
+                    .to_owned(),
+            ),
+        )
+        .add(
+            song::Column::Id.not_in_subquery(
+                Query::select()
+                    .column(playlist_song::Column::SongId)
+                    .from(playlist_song::Entity)
+                    .to_owned(),
+            ),
+        )
+        .add(song::Column::Id.not_in_subquery(super::download_job::active_song_ids()))
+}
+
+/// Sets orphaned_at of the songs that lost their last owner since the last time it ran, and
+/// clears it for the ones that got an owner back
+///
+/// Returns sea_orm::DbErr if any UPDATE operation fails
+pub async fn mark_orphaned(state: &AppState, orphaned_at: &str) -> Result<(), DbErr> {
+    let db = &state.db;
+
+    song::Entity::update_many()
+        .col_expr(song::Column::OrphanedAt, Expr::value(orphaned_at))
+        .filter(is_orphaned())
+        .filter(song::Column::OrphanedAt.is_null())
+        .exec(db)
+        .await?;
+
+    song::Entity::update_many()
+        .col_expr(
+            song::Column::OrphanedAt,
+            Expr::value(Option::<String>::None),
+        )
+        .filter(is_orphaned().not())
+        .filter(song::Column::OrphanedAt.is_not_null())
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+/// Returns every song that is in no library, no playlist and no active job, orphaned_at is None for the ones
+/// mark_orphaned did not see yet
+pub async fn all_orphaned(state: &AppState) -> Result<Vec<song::Model>, DbErr> {
+    let db = &state.db;
+
+    let songs = song::Entity::find()
+        .filter(is_orphaned())
+        .order_by_asc(song::Column::OrphanedAt)
+        .all(db)
+        .await?;
+
+    Ok(songs)
+}
+
+/// Deletes the song, unless someone added it again since it was found orphaned
+///
+/// Returns whether it was deleted
+pub async fn delete_orphaned(state: &AppState, song_id: &str) -> Result<bool, DbErr> {
+    let db = &state.db;
+
+    let result = song::Entity::delete_many()
+        .filter(song::Column::Id.eq(song_id))
+        .filter(is_orphaned())
+        .exec(db)
+        .await?;
+
+    Ok(result.rows_affected > 0)
+}
@@ -0,0 +1,87 @@
+use crate::AppState;
+use entity::totp;
+use sea_orm::{
+    sea_query::{Expr, OnConflict},
+    ActiveValue, ColumnTrait, DbErr, EntityTrait, QueryFilter,
+};
+
+pub async fn first_by_user_id(
+    state: &AppState,
+    user_id: &str,
+) -> Result<Option<totp::Model>, DbErr> {
+    let db = &state.db;
+
+    let totp = totp::Entity::find_by_id(user_id).one(db).await?;
+
+    Ok(totp)
+}
+
+/// Stores a new secret for the user that stays disabled until it is confirmed
+///
+/// Replaces a secret that was never confirmed, so enrollment can be started over
+pub async fn create_pending(state: &AppState, user_id: &str, secret: &str) -> Result<(), DbErr> {
+    let db = &state.db;
+
+    let new_totp = totp::ActiveModel {
+        user_id: ActiveValue::Set(user_id.to_string()),
+        secret: ActiveValue::Set(secret.to_string()),
+        enabled: ActiveValue::Set(false),
+        last_used_step: ActiveValue::Set(None),
+        ..Default::default()
+    };
+
+    totp::Entity::insert(new_totp)
+        .on_conflict(
+            OnConflict::column(totp::Column::UserId)
+                .update_columns([totp::Column::Secret, totp::Column::LastUsedStep])
+                .action_and_where(Expr::col(totp::Column::Enabled).eq(false))
+                .to_owned(),
+        )
+        .exec_without_returning(db)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn enable(state: &AppState, user_id: &str) -> Result<(), DbErr> {
+    let db = &state.db;
+
+    totp::Entity::update_many()
+        .col_expr(totp::Column::Enabled, Expr::value(true))
+        .filter(totp::Column::UserId.eq(user_id))
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+/// Records that the code of a time step was used, so it can not be used again
+///
+/// The check and the update happen in the same query, so when two requests send the same code at
+/// once only one of them gets Ok(true)
+///
+/// Returns Ok(false) if a code of this step or a later one was already used
+pub async fn mark_step_used(state: &AppState, user_id: &str, step: i64) -> Result<bool, DbErr> {
+    let db = &state.db;
+
+    let result = totp::Entity::update_many()
+        .col_expr(totp::Column::LastUsedStep, Expr::value(step))
+        .filter(totp::Column::UserId.eq(user_id))
+        .filter(
+            totp::Column::LastUsedStep
+                .is_null()
+                .or(totp::Column::LastUsedStep.lt(step)),
+        )
+        .exec(db)
+        .await?;
+
+    Ok(result.rows_affected == 1)
+}
+
+pub async fn delete(state: &AppState, user_id: &str) -> Result<(), DbErr> {
+    let db = &state.db;
+
+    totp::Entity::delete_by_id(user_id).exec(db).await?;
+
+    Ok(())
+}
@@ -1,8 +1,50 @@
-use entity::user;
-use sea_orm::{ActiveValue, ColumnTrait, DbErr, EntityTrait, QueryFilter};
+use entity::{
+    api_key, download_job, invite_code, mfa_challenge, passwd_reset_token, playlist, playlist_song,
+    recovery_code, refresh_token, session, totp, user, user_song,
+};
+use sea_orm::{
+    sea_query::{Expr, Query},
+    ActiveValue, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, PaginatorTrait, QueryFilter,
+    QueryOrder, TransactionTrait,
+};
 
 use crate::AppState;
 
+pub async fn first_by_id(state: &AppState, user_id: &str) -> Result<Option<user::Model>, DbErr> {
+    let db = &state.db;
+
+    let user = user::Entity::find_by_id(user_id).one(db).await?;
+
+    Ok(user)
+}
+
+/// Returns every user, ordered by username
+pub async fn all(state: &AppState) -> Result<Vec<user::Model>, DbErr> {
+    let db = &state.db;
+
+    let users = user::Entity::find()
+        .order_by_asc(user::Column::NormalizedUsername)
+        .all(db)
+        .await?;
+
+    Ok(users)
+}
+
+pub async fn count(state: &AppState) -> Result<u64, DbErr> {
+    let db = &state.db;
+
+    let count = user::Entity::find().count(db).await?;
+
+    Ok(count)
+}
+
+/// Usernames are unique regardless of case, so they are compared by this form
+/// The migrations have their own copy of it, the two must match
+pub fn normalize_username(username: &str) -> String {
+    username.trim().to_lowercase()
+}
+
+/// Finds the user whose username matches exactly, ignoring case
 pub async fn first_by_username(
     state: &AppState,
     username: &str,
@@ -10,23 +52,229 @@ pub async fn first_by_username(
     let db = &state.db;
 
     let user = user::Entity::find()
-        .filter(user::Column::Username.contains(username))
+        .filter(user::Column::NormalizedUsername.eq(normalize_username(username)))
         .one(db)
         .await?;
 
     Ok(user)
 }
 
+/// Creates a new user entity on the database
+///
+/// Returns sea_orm::DbErr if the INSERT operation fails, which includes a unique constraint
+/// violation when another user already has the same normalized username
 pub async fn create_new_user(state: &AppState, username: &str, passwd: &str) -> Result<(), DbErr> {
+    create_new_user_with_role(state, username, passwd, "user").await
+}
+
+/// Same as create_new_user, but with the given role instead of the default one
+pub async fn create_new_user_with_role(
+    state: &AppState,
+    username: &str,
+    passwd: &str,
+    role: &str,
+) -> Result<(), DbErr> {
     let db = &state.db;
 
     let new_user = user::ActiveModel {
         id: ActiveValue::Set(uuid::Uuid::new_v4().to_string()),
         username: ActiveValue::Set(username.to_string()),
         passwd: ActiveValue::Set(passwd.to_string()),
+        normalized_username: ActiveValue::Set(normalize_username(username)),
+        role: ActiveValue::Set(role.to_string()),
+        ..Default::default()
     };
 
     user::Entity::insert(new_user).exec(db).await?;
 
     Ok(())
 }
+
+/// Who can sign up once the server has a user
+pub enum Admission<'a> {
+    Open,
+    Closed,
+    Invite(Option<&'a str>), // the invite code that came with the signup, if any
+}
+
+/// Why a signup was not admitted
+#[derive(Debug, PartialEq, Eq)]
+pub enum Refusal {
+    SignupClosed,
+    InviteCodeRequired,
+    InvalidInviteCode,
+}
+
+/// Creates the user that signed up, the first user of the server is always admitted and created
+/// with the role admin, the rest only if admission allows it
+///
+/// The user count, the use of the invite code and the INSERT happen in a single transaction, so
+/// two concurrent signups can not both become the first user, and an invite code is not used up
+/// by a signup whose INSERT fails
+///
+/// Returns the role of the new user, or why they were not admitted
+pub async fn create_signed_up_user(
+    state: &AppState,
+    username: &str,
+    passwd: &str,
+    admission: Admission<'_>,
+) -> Result<Result<&'static str, Refusal>, DbErr> {
+    let txn = state.db.begin().await?;
+
+    let is_first_user = user::Entity::find().count(&txn).await? == 0;
+
+    if !is_first_user {
+        let code = match admission {
+            Admission::Open => None,
+            Admission::Closed => return Ok(Err(Refusal::SignupClosed)),
+            Admission::Invite(None) => return Ok(Err(Refusal::InviteCodeRequired)),
+            Admission::Invite(Some(code)) => Some(code),
+        };
+
+        if let Some(code) = code {
+            if !super::invite_code::consume(&txn, code).await? {
+                return Ok(Err(Refusal::InvalidInviteCode));
+            }
+        }
+    }
+
+    let role = match is_first_user {
+        true => "admin",
+        false => "user",
+    };
+
+    let new_user = user::ActiveModel {
+        id: ActiveValue::Set(uuid::Uuid::new_v4().to_string()),
+        username: ActiveValue::Set(username.to_string()),
+        passwd: ActiveValue::Set(passwd.to_string()),
+        normalized_username: ActiveValue::Set(normalize_username(username)),
+        role: ActiveValue::Set(role.to_string()),
+        ..Default::default()
+    };
+
+    user::Entity::insert(new_user).exec(&txn).await?;
+
+    txn.commit().await?;
+
+    Ok(Ok(role))
+}
+
+/// Replaces the password hash of the user
+pub async fn update_passwd(
+    db: &impl ConnectionTrait,
+    user_id: &str,
+    passwd: &str,
+) -> Result<(), DbErr> {
+    user::Entity::update_many()
+        .col_expr(user::Column::Passwd, Expr::value(passwd))
+        .filter(user::Column::Id.eq(user_id))
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+/// Returns Ok(false) if there is no user with that id
+pub async fn set_disabled(state: &AppState, user_id: &str, disabled: bool) -> Result<bool, DbErr> {
+    let db = &state.db;
+
+    let result = user::Entity::update_many()
+        .col_expr(user::Column::Disabled, Expr::value(disabled))
+        .filter(user::Column::Id.eq(user_id))
+        .exec(db)
+        .await?;
+
+    Ok(result.rows_affected == 1)
+}
+
+/// Returns Ok(false) if there is no user with that id
+pub async fn set_role(state: &AppState, user_id: &str, role: &str) -> Result<bool, DbErr> {
+    let db = &state.db;
+
+    let result = user::Entity::update_many()
+        .col_expr(user::Column::Role, Expr::value(role))
+        .filter(user::Column::Id.eq(user_id))
+        .exec(db)
+        .await?;
+
+    Ok(result.rows_affected == 1)
+}
+
+/// Deletes the user and everything that belongs to them in a single transaction, if any DELETE
+/// fails nothing is deleted
+///
+/// Songs are NOT deleted, only the user_song junctions, since other users may have them too
+pub async fn delete_with_data(state: &AppState, user_id: &str) -> Result<(), DbErr> {
+    let txn = state.db.begin().await?;
+
+    playlist_song::Entity::delete_many()
+        .filter(
+            playlist_song::Column::PlaylistId.in_subquery(
+                Query::select()
+                    .column(playlist::Column::Id)
+                    .from(playlist::Entity)
+                    .and_where(playlist::Column::UserId.eq(user_id))
+                    .to_owned(),
+            ),
+        )
+        .exec(&txn)
+        .await?;
+
+    playlist::Entity::delete_many()
+        .filter(playlist::Column::UserId.eq(user_id))
+        .exec(&txn)
+        .await?;
+
+    user_song::Entity::delete_many()
+        .filter(user_song::Column::UserId.eq(user_id))
+        .exec(&txn)
+        .await?;
+
+    refresh_token::Entity::delete_many()
+        .filter(refresh_token::Column::UserId.eq(user_id))
+        .exec(&txn)
+        .await?;
+
+    session::Entity::delete_many()
+        .filter(session::Column::UserId.eq(user_id))
+        .exec(&txn)
+        .await?;
+
+    api_key::Entity::delete_many()
+        .filter(api_key::Column::UserId.eq(user_id))
+        .exec(&txn)
+        .await?;
+
+    download_job::Entity::delete_many()
+        .filter(download_job::Column::UserId.eq(user_id))
+        .exec(&txn)
+        .await?;
+
+    passwd_reset_token::Entity::delete_many()
+        .filter(passwd_reset_token::Column::UserId.eq(user_id))
+        .exec(&txn)
+        .await?;
+
+    invite_code::Entity::delete_many()
+        .filter(invite_code::Column::CreatedBy.eq(user_id))
+        .exec(&txn)
+        .await?;
+
+    mfa_challenge::Entity::delete_many()
+        .filter(mfa_challenge::Column::UserId.eq(user_id))
+        .exec(&txn)
+        .await?;
+
+    recovery_code::Entity::delete_many()
+        .filter(recovery_code::Column::UserId.eq(user_id))
+        .exec(&txn)
+        .await?;
+
+    totp::Entity::delete_by_id(user_id).exec(&txn).await?;
+
+    user::Entity::delete_by_id(user_id).exec(&txn).await?;
+
+    txn.commit().await?;
+
+    Ok(())
+}
@@ -0,0 +1,353 @@
+use super::Downloader;
+use crate::{
+    config,
+    util::{
+        error::Error,
+        source::{Provider, Source},
+        yt_dlp::{Phase, Progress, YtDlpPlaylist, YtDlpPlaylistEntry, YtDlpResult, YtDlpThumbnail},
+    },
+};
+use async_trait::async_trait;
+use image::{ImageFormat, Rgb, RgbImage};
+use std::{
+    collections::{HashMap, HashSet},
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+/// Length of the audio of every fake song
+const DURATION_SECS: u32 = 1;
+/// Samples the decoder skips at the start, what libopus encoders use
+const PRE_SKIP: u16 = 312;
+/// 20ms of silence, the smallest valid opus packet that decodes to silence
+const SILENT_PACKET: [u8; 3] = [0xf8, 0xff, 0xfe];
+const SAMPLES_PER_PACKET: u64 = 960; // 20ms at 48kHz
+const OGG_SERIAL: u32 = 0x7269_7066;
+
+/// Downloader that never touches the network, every download, listing and search gives the same
+/// result for the same input
+///
+/// Any valid source downloads into a second of silence with a cover and canned metadata, youtube
+/// videos added with with_video get their title and channel and are the only ones search finds
+///
+/// Uploads are imported the same way, as long as they start like an audio file does, they never
+/// have tags
+#[derive(Debug, Clone)]
+pub struct FakeDownloader {
+    output_path: PathBuf,
+    videos: Vec<FakeVideo>,
+    playlists: HashMap<String, (String, Vec<String>)>,
+    unavailable: HashSet<String>,
+}
+
+#[derive(Debug, Clone)]
+struct FakeVideo {
+    id: String,
+    title: String,
+    channel: String,
+}
+
+impl Default for FakeDownloader {
+    fn default() -> Self {
+        Self {
+            output_path: PathBuf::from(&config().yt_dlp_output_path),
+            videos: vec![],
+            playlists: HashMap::new(),
+            unavailable: HashSet::new(),
+        }
+    }
+}
+
+impl FakeDownloader {
+    pub fn with_video(mut self, id: &str, title: &str, channel: &str) -> Self {
+        self.videos.push(FakeVideo {
+            id: id.into(),
+            title: title.into(),
+            channel: channel.into(),
+        });
+        self
+    }
+
+    /// Playlists that were not added do not exist
+    pub fn with_playlist(mut self, list_id: &str, title: &str, video_ids: &[&str]) -> Self {
+        let video_ids = video_ids.iter().map(|id| id.to_string()).collect();
+        self.playlists
+            .insert(list_id.into(), (title.into(), video_ids));
+        self
+    }
+
+    /// Downloading the song with that external id fails the same way yt-dlp does for a private or
+    /// deleted video
+    pub fn with_unavailable(mut self, id: &str) -> Self {
+        self.unavailable.insert(id.into());
+        self
+    }
+
+    fn video(&self, id: &str) -> FakeVideo {
+        self.videos
+            .iter()
+            .find(|v| v.id == id)
+            .cloned()
+            .unwrap_or_else(|| FakeVideo {
+                id: id.into(),
+                title: format!("Fake song {id}"),
+                channel: "Fake channel".into(),
+            })
+    }
+
+    /// Writes the audio and the cover of the song to the output path
+    async fn write_song(&self, source: &Source) -> Result<(), Error> {
+        let io_error = |e: std::io::Error| Error::YtDlpIOError(e.to_string());
+        let media_stem = source.media_stem();
+
+        tokio::fs::create_dir_all(&self.output_path)
+            .await
+            .map_err(io_error)?;
+        tokio::fs::write(
+            self.output_path.join(format!("{media_stem}.opus")),
+            silent_opus(),
+        )
+        .await
+        .map_err(io_error)?;
+        tokio::fs::write(
+            self.output_path.join(format!("{media_stem}.jpg")),
+            cover(source.external_id())?,
+        )
+        .await
+        .map_err(io_error)?;
+
+        Ok(())
+    }
+
+    fn entry(&self, id: &str) -> YtDlpPlaylistEntry {
+        let video = self.video(id);
+
+        YtDlpPlaylistEntry {
+            title: Some(if self.unavailable.contains(id) {
+                "[Private video]".into()
+            } else {
+                video.title
+            }),
+            channel: Some(video.channel),
+            duration: Some(DURATION_SECS.into()),
+            thumbnails: vec![YtDlpThumbnail {
+                url: thumbnail_url(id),
+            }],
+            id: Some(video.id),
+        }
+    }
+}
+
+#[async_trait]
+impl Downloader for FakeDownloader {
+    async fn download(
+        &self,
+        source: &Source,
+        on_progress: &mut (dyn FnMut(Progress) + Send),
+    ) -> Result<YtDlpResult, Error> {
+        // uploads have nothing to download from
+        source.url()?;
+
+        let id = source.external_id();
+        // youtube video ids are always 11 characters long
+        let is_valid_id = source.provider() != Provider::YouTube || id.len() == 11;
+
+        if !is_valid_id || self.unavailable.contains(id) {
+            return Err(Error::YtDlpExitCode(
+                1,
+                format!("ERROR: [youtube] {id}: Video unavailable"),
+            ));
+        }
+
+        for percent in [0.0, 50.0, 100.0] {
+            on_progress(Progress {
+                phase: Phase::Downloading,
+                percent: Some(percent),
+                speed_bytes_per_sec: None,
+                eta_secs: None,
+            });
+        }
+
+        on_progress(Progress {
+            phase: Phase::Extracting,
+            percent: None,
+            speed_bytes_per_sec: None,
+            eta_secs: None,
+        });
+
+        self.write_song(source).await?;
+
+        let video = self.video(id);
+
+        Ok(YtDlpResult {
+            channel: video.channel,
+            fulltitle: video.title,
+            duration: Some(DURATION_SECS.into()),
+            upload_date: Some("20240101".into()),
+            thumbnail: Some(thumbnail_url(id)),
+            ..Default::default()
+        })
+    }
+
+    async fn import(&self, input: &Path, source: &Source) -> Result<YtDlpResult, Error> {
+        self.check_audio(input).await?;
+
+        self.write_song(source).await?;
+
+        Ok(YtDlpResult {
+            duration: Some(DURATION_SECS.into()),
+            ..Default::default()
+        })
+    }
+
+    async fn check_audio(&self, path: &Path) -> Result<(), Error> {
+        let file = tokio::fs::read(path)
+            .await
+            .map_err(|e| Error::FfmpegIOError(e.to_string()))?;
+
+        if !is_audio(&file) {
+            return Err(Error::UnsupportedAudio(
+                "Invalid data found when processing input".into(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    async fn list_playlist(&self, list_id: &str) -> Result<YtDlpPlaylist, Error> {
+        let (title, video_ids) = self.playlists.get(list_id).ok_or_else(|| {
+            Error::YtDlpExitCode(
+                1,
+                format!("ERROR: [youtube:tab] {list_id}: The playlist does not exist."),
+            )
+        })?;
+
+        Ok(YtDlpPlaylist {
+            title: Some(title.clone()),
+            entries: video_ids.iter().map(|id| Some(self.entry(id))).collect(),
+        })
+    }
+
+    async fn search(&self, query: &str, limit: usize) -> Result<Vec<YtDlpPlaylistEntry>, Error> {
+        let words: Vec<String> = query.split_whitespace().map(|w| w.to_lowercase()).collect();
+
+        let results = self
+            .videos
+            .iter()
+            .filter(|video| {
+                let haystack = format!("{} {}", video.title, video.channel).to_lowercase();
+                words.iter().all(|word| haystack.contains(word))
+            })
+            .take(limit)
+            .map(|video| self.entry(&video.id))
+            .collect();
+
+        Ok(results)
+    }
+}
+
+/// Whether the file starts with the magic bytes of mp3, flac, ogg, wav or mp4 audio
+fn is_audio(file: &[u8]) -> bool {
+    let mpeg_frame = file.len() > 1 && file[0] == 0xff && file[1] & 0xe0 == 0xe0;
+
+    file.starts_with(b"ID3")
+        || mpeg_frame
+        || file.starts_with(b"fLaC")
+        || file.starts_with(b"OggS")
+        || (file.starts_with(b"RIFF") && file.get(8..12) == Some(b"WAVE"))
+        || file.get(4..8) == Some(b"ftyp")
+}
+
+fn thumbnail_url(id: &str) -> String {
+    format!("https://i.ytimg.com/vi/{id}/maxresdefault.jpg")
+}
+
+/// A 16:9 jpg with a color picked from the id, so covers of different songs differ
+fn cover(id: &str) -> Result<Vec<u8>, Error> {
+    let [r, g, b] = [0, 1, 2].map(|i| id.as_bytes().get(i).copied().unwrap_or_default());
+    let image = RgbImage::from_pixel(64, 36, Rgb([r, g, b]));
+
+    let mut jpg = Cursor::new(vec![]);
+    image
+        .write_to(&mut jpg, ImageFormat::Jpeg)
+        .map_err(|e| Error::ArtError(e.to_string()))?;
+
+    Ok(jpg.into_inner())
+}
+
+/// Ogg Opus file with DURATION_SECS of mono silence
+pub(super) fn silent_opus() -> Vec<u8> {
+    let mut opus_head = b"OpusHead".to_vec();
+    opus_head.push(1); // version
+    opus_head.push(1); // channels
+    opus_head.extend_from_slice(&PRE_SKIP.to_le_bytes());
+    opus_head.extend_from_slice(&48000u32.to_le_bytes()); // sample rate of the input
+    opus_head.extend_from_slice(&0i16.to_le_bytes()); // output gain
+    opus_head.push(0); // channel mapping family
+
+    let vendor = b"ripfy";
+    let mut opus_tags = b"OpusTags".to_vec();
+    opus_tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    opus_tags.extend_from_slice(vendor);
+    opus_tags.extend_from_slice(&0u32.to_le_bytes()); // no comments
+
+    let packet_count = DURATION_SECS as u64 * 48000 / SAMPLES_PER_PACKET;
+    let packets: Vec<&[u8]> = (0..packet_count).map(|_| &SILENT_PACKET[..]).collect();
+    let granule = PRE_SKIP as u64 + packet_count * SAMPLES_PER_PACKET;
+
+    let mut file = vec![];
+    ogg_page(&mut file, 0x02, 0, 0, &[&opus_head]); // beginning of stream
+    ogg_page(&mut file, 0x00, 0, 1, &[&opus_tags]);
+    ogg_page(&mut file, 0x04, granule, 2, &packets); // end of stream
+
+    file
+}
+
+/// Appends an ogg page with the packets to out, every packet must fit in the page
+fn ogg_page(out: &mut Vec<u8>, header_type: u8, granule: u64, sequence: u32, packets: &[&[u8]]) {
+    let mut segments = vec![];
+
+    for packet in packets {
+        segments.extend(std::iter::repeat_n(255, packet.len() / 255));
+        segments.push((packet.len() % 255) as u8);
+    }
+
+    let start = out.len();
+
+    out.extend_from_slice(b"OggS");
+    out.push(0); // version
+    out.push(header_type);
+    out.extend_from_slice(&granule.to_le_bytes());
+    out.extend_from_slice(&OGG_SERIAL.to_le_bytes());
+    out.extend_from_slice(&sequence.to_le_bytes());
+    out.extend_from_slice(&[0; 4]); // checksum, filled below
+    out.push(segments.len() as u8);
+    out.extend_from_slice(&segments);
+
+    for packet in packets {
+        out.extend_from_slice(packet);
+    }
+
+    let checksum = ogg_crc(&out[start..]);
+    out[start + 22..start + 26].copy_from_slice(&checksum.to_le_bytes());
+}
+
+/// CRC-32 as ogg uses it, polynomial 0x04c11db7 with no reflection, no initial value and no final
+/// xor
+pub(super) fn ogg_crc(data: &[u8]) -> u32 {
+    let mut crc = 0u32;
+
+    for &byte in data {
+        crc ^= (byte as u32) << 24;
+
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04c1_1db7
+            } else {
+                crc << 1
+            };
+        }
+    }
+
+    crc
+}
@@ -0,0 +1,71 @@
+pub mod fake;
+
+use crate::util::{
+    error::Error,
+    ffmpeg::Ffmpeg,
+    source::Source,
+    yt_dlp::{Progress, YtDlp, YtDlpPlaylist, YtDlpPlaylistEntry, YtDlpResult},
+};
+use async_trait::async_trait;
+use std::path::Path;
+
+/// Where songs, playlists and search results come from
+///
+/// The server uses yt-dlp and ffmpeg, tests use a FakeDownloader so they run without network or
+/// either binary
+#[async_trait]
+pub trait Downloader: Send + Sync {
+    /// Downloads the audio of a song as stem.opus and its cover as stem.jpg to the output path,
+    /// stem being the media stem of the source
+    /// Calls on_progress every time the download makes progress
+    async fn download(
+        &self,
+        source: &Source,
+        on_progress: &mut (dyn FnMut(Progress) + Send),
+    ) -> Result<YtDlpResult, Error>;
+
+    /// Turns an uploaded audio file into a song, the same files download outputs are written from
+    /// it, the file itself is left as is
+    /// Returns the tags of the file, fulltitle is empty when it has no title tag
+    async fn import(&self, input: &Path, source: &Source) -> Result<YtDlpResult, Error>;
+
+    /// Checks that a media file is audio that can be played, without changing it
+    /// Returns Error::UnsupportedAudio with the reason when it is not
+    async fn check_audio(&self, path: &Path) -> Result<(), Error>;
+
+    /// Lists the videos of a youtube playlist without downloading anything
+    async fn list_playlist(&self, list_id: &str) -> Result<YtDlpPlaylist, Error>;
+
+    /// Searches youtube for videos without downloading anything, best match first
+    async fn search(&self, query: &str, limit: usize) -> Result<Vec<YtDlpPlaylistEntry>, Error>;
+}
+
+#[async_trait]
+impl Downloader for YtDlp {
+    async fn download(
+        &self,
+        source: &Source,
+        on_progress: &mut (dyn FnMut(Progress) + Send),
+    ) -> Result<YtDlpResult, Error> {
+        self.run_with_progress(source, on_progress).await
+    }
+
+    async fn import(&self, input: &Path, source: &Source) -> Result<YtDlpResult, Error> {
+        Ffmpeg::default().import(input, source).await
+    }
+
+    async fn check_audio(&self, path: &Path) -> Result<(), Error> {
+        Ffmpeg::default().check_audio(path).await
+    }
+
+    async fn list_playlist(&self, list_id: &str) -> Result<YtDlpPlaylist, Error> {
+        YtDlp::list_playlist(self, list_id).await
+    }
+
+    async fn search(&self, query: &str, limit: usize) -> Result<Vec<YtDlpPlaylistEntry>, Error> {
+        YtDlp::search(self, query, limit).await
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,103 @@
+use super::{
+    fake::{ogg_crc, silent_opus, FakeDownloader},
+    Downloader,
+};
+use crate::util::source::Source;
+use anyhow::Result;
+
+#[test]
+fn ogg_checksum() {
+    // check value of crc-32 with polynomial 0x04c11db7, no reflection, no initial value and no
+    // final xor
+    assert_eq!(ogg_crc(b"123456789"), 0x89a1_897f);
+}
+
+#[test]
+fn silent_opus_pages() -> Result<()> {
+    let opus = silent_opus();
+
+    let mut pages = vec![];
+    let mut rest = opus.as_slice();
+
+    while !rest.is_empty() {
+        assert_eq!(&rest[..4], b"OggS");
+
+        let segment_count = rest[26] as usize;
+        let body_len: usize = rest[27..27 + segment_count]
+            .iter()
+            .map(|&s| s as usize)
+            .sum();
+        let page_len = 27 + segment_count + body_len;
+
+        // the checksum is computed with its own field zeroed
+        let mut page = rest[..page_len].to_vec();
+        let checksum = u32::from_le_bytes(page[22..26].try_into()?);
+        page[22..26].fill(0);
+        assert_eq!(ogg_crc(&page), checksum);
+
+        pages.push(rest[..page_len].to_vec());
+        rest = &rest[page_len..];
+    }
+
+    assert_eq!(pages.len(), 3);
+    assert_eq!(pages[0][5], 0x02); // beginning of stream
+    assert_eq!(&pages[0][28..36], b"OpusHead");
+    assert_eq!(&pages[1][28..36], b"OpusTags");
+    assert_eq!(pages[2][5], 0x04); // end of stream
+
+    // a second of 48kHz audio plus the pre-skip
+    let granule = u64::from_le_bytes(pages[2][6..14].try_into()?);
+    assert_eq!(granule, 312 + 48000);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn fake_listing_and_search() -> Result<()> {
+    let downloader = FakeDownloader::default()
+        .with_video("fJ9rUzIMcZQ", "Queen – Bohemian Rhapsody", "Queen Official")
+        .with_video("2ZBtPf7FOoM", "Queen - Killer Queen", "Queen Official")
+        .with_video("Nnjh-zp6pP4", "AC/DC - Highway to Hell", "AC/DC")
+        .with_playlist(
+            "PLqueen",
+            "Queen Classics",
+            &["fJ9rUzIMcZQ", "privatevid0", "2ZBtPf7FOoM"],
+        )
+        .with_unavailable("privatevid0");
+
+    let ids = |entries: Vec<crate::util::yt_dlp::YtDlpPlaylistEntry>| -> Vec<String> {
+        entries.into_iter().filter_map(|e| e.id).collect()
+    };
+
+    let results = downloader.search("queen", 10).await?;
+    assert_eq!(ids(results), ["fJ9rUzIMcZQ", "2ZBtPf7FOoM"]);
+
+    let results = downloader.search("QUEEN bohemian", 10).await?;
+    assert_eq!(ids(results), ["fJ9rUzIMcZQ"]);
+
+    let results = downloader.search("queen", 1).await?;
+    assert_eq!(ids(results), ["fJ9rUzIMcZQ"]);
+
+    let playlist = downloader.list_playlist("PLqueen").await?;
+    assert_eq!(playlist.title.as_deref(), Some("Queen Classics"));
+
+    let entries: Vec<_> = playlist.entries.into_iter().flatten().collect();
+    assert_eq!(entries.len(), 3);
+    assert!(entries[0].is_available());
+    assert!(!entries[1].is_available());
+
+    assert!(downloader.list_playlist("PLmissing").await.is_err());
+
+    // fails before writing anything
+    let mut on_progress = |_| {};
+    assert!(downloader
+        .download(&Source::youtube("privatevid0"), &mut on_progress)
+        .await
+        .is_err());
+    assert!(downloader
+        .download(&Source::youtube("not-an-id"), &mut on_progress)
+        .await
+        .is_err());
+
+    Ok(())
+}
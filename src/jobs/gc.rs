@@ -0,0 +1,117 @@
+use crate::{
+    config, db,
+    util::{
+        art::{art_path, ART_SIZES},
+        source::audio_path,
+        time::{now_utc, parse_utc, utc_time_to_str},
+    },
+    AppState,
+};
+use anyhow::Result;
+use serde::Serialize;
+use std::time::Duration;
+
+/// What a collection deleted, or would have deleted on a dry run
+#[derive(Debug, Default, Serialize)]
+pub struct GcReport {
+    pub dry_run: bool,
+    pub song_ids: Vec<String>,
+    pub reclaimed_bytes: u64,
+}
+
+/// Collects garbage every config().gc_interval_secs, forever
+pub async fn run(state: AppState) {
+    tracing::debug!("GARBAGE COLLECTOR STARTED");
+
+    let mut interval = tokio::time::interval(Duration::from_secs(config().gc_interval_secs));
+
+    loop {
+        interval.tick().await;
+
+        match collect(&state, false).await {
+            Ok(report) if !report.song_ids.is_empty() => tracing::info!(
+                "Garbage collected {} songs, reclaimed {} bytes",
+                report.song_ids.len(),
+                report.reclaimed_bytes
+            ),
+            Ok(_) => {}
+            Err(err) => tracing::error!("COULD NOT COLLECT GARBAGE - CAUSE: {err:?}"),
+        }
+    }
+}
+
+/// Deletes the songs that have been in no library, no playlist and no queued or running download
+/// job for longer than config().gc_grace_secs, along with their media files
+///
+/// The grace period starts when a collection first finds the song orphaned, a dry run reports what
+/// would be deleted without touching anything
+pub async fn collect(state: &AppState, dry_run: bool) -> Result<GcReport> {
+    collect_older_than(state, dry_run, Duration::from_secs(config().gc_grace_secs)).await
+}
+
+/// Same as collect, but the songs are kept for grace instead of config().gc_grace_secs
+pub(super) async fn collect_older_than(
+    state: &AppState,
+    dry_run: bool,
+    grace: Duration,
+) -> Result<GcReport> {
+    let now = now_utc();
+    let now_str = utc_time_to_str(now)?;
+
+    if !dry_run {
+        db::song::mark_orphaned(state, &now_str).await?;
+    }
+
+    let mut report = GcReport {
+        dry_run,
+        ..Default::default()
+    };
+
+    for song in db::song::all_orphaned(state).await? {
+        // a dry run does not mark the songs, the real run would mark them now
+        let orphaned_at = match &song.orphaned_at {
+            Some(orphaned_at) => parse_utc(orphaned_at)?,
+            None => now,
+        };
+
+        if orphaned_at + grace > now {
+            continue;
+        }
+
+        if !dry_run && !db::song::delete_orphaned(state, &song.id).await? {
+            continue;
+        }
+
+        report.reclaimed_bytes += remove_media_files(&song.id, dry_run).await;
+        report.song_ids.push(song.id);
+    }
+
+    Ok(report)
+}
+
+/// Removes the audio and covers of the song, unless it is a dry run
+/// Returns how many bytes they took
+pub async fn remove_media_files(song_id: &str, dry_run: bool) -> u64 {
+    let paths = [audio_path(song_id), art_path(song_id, None)]
+        .into_iter()
+        .chain(ART_SIZES.map(|size| art_path(song_id, Some(size))));
+
+    let mut removed_bytes = 0;
+
+    for path in paths {
+        let Ok(metadata) = tokio::fs::metadata(&path).await else {
+            continue;
+        };
+
+        if !dry_run {
+            if let Err(err) = tokio::fs::remove_file(&path).await {
+                tracing::warn!("COULD NOT REMOVE {} - CAUSE: {err}", path.display());
+                continue;
+            }
+        }
+
+        removed_bytes += metadata.len();
+    }
+
+    removed_bytes
+}
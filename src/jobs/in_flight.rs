@@ -0,0 +1,92 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Mutex, OnceLock},
+};
+use tokio::sync::watch;
+
+type DownloadResult = Result<(), String>;
+
+/// Downloads that are running right now, by video id, along with the channel their result is
+/// sent to once they end
+fn in_flight() -> &'static Mutex<HashMap<String, watch::Sender<Option<DownloadResult>>>> {
+    static IN_FLIGHT: OnceLock<Mutex<HashMap<String, watch::Sender<Option<DownloadResult>>>>> =
+        OnceLock::new();
+
+    IN_FLIGHT.get_or_init(Default::default)
+}
+
+/// Whether it runs the download or waits for one that is already running
+enum Role {
+    Leader(Registration),
+    Follower(watch::Receiver<Option<DownloadResult>>),
+}
+
+/// Removes the download from the registry when it ends, even if the leader panics or is
+/// cancelled, so the next job for the video does not wait forever
+struct Registration {
+    song_id: String,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        in_flight()
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&self.song_id);
+    }
+}
+
+/// Runs download for the video unless a download of it is already running, in which case it waits
+/// for that one and Returns its result instead
+///
+/// Every caller gets the same result, so a video requested by several users at the same time is
+/// only downloaded once, download should store the song so the callers only have to link it
+pub async fn download_once<F>(song_id: &str, download: F) -> DownloadResult
+where
+    F: Future<Output = DownloadResult>,
+{
+    let role = {
+        let mut in_flight = in_flight().lock().unwrap_or_else(|e| e.into_inner());
+
+        match in_flight.get(song_id) {
+            Some(sender) => Role::Follower(sender.subscribe()),
+            None => {
+                let (sender, _) = watch::channel(None);
+                in_flight.insert(song_id.to_string(), sender);
+
+                Role::Leader(Registration {
+                    song_id: song_id.to_string(),
+                })
+            }
+        }
+    };
+
+    match role {
+        Role::Leader(registration) => {
+            let result = download.await;
+
+            if let Some(sender) = in_flight()
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .get(&registration.song_id)
+            {
+                sender.send_replace(Some(result.clone()));
+            }
+
+            result
+        }
+        Role::Follower(mut receiver) => {
+            tracing::debug!("WAITING FOR THE RUNNING DOWNLOAD OF SONG {song_id}");
+
+            receiver
+                .wait_for(Option::is_some)
+                .await
+                .ok()
+                .and_then(|result| result.clone())
+                .unwrap_or_else(|| {
+                    Err("The download this job was waiting for was interrupted".into())
+                })
+        }
+    }
+}
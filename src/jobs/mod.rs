@@ -0,0 +1,94 @@
+pub mod gc;
+pub mod in_flight;
+pub mod reconcile;
+pub mod worker;
+
+use crate::{config, db, util::yt_dlp::Progress, AppState};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+use tokio::sync::{broadcast, Notify};
+
+/// How many events are kept for clients that fall behind, older ones are dropped for them
+const JOB_EVENTS_CAPACITY: usize = 256;
+
+/// State of a download job, jobs are queued, run by a worker and end up done or failed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, strum_macros::AsRefStr)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum JobState {
+    Queued,
+    Running,
+    Done,
+    Failed,
+}
+
+/// Used to wake the workers up when a job is queued
+///
+/// Workers also look for jobs every few seconds, so a job queued by something that does not
+/// notify them only waits a bit longer
+pub fn queue_notify() -> &'static Notify {
+    static QUEUE_NOTIFY: OnceLock<Notify> = OnceLock::new();
+
+    QUEUE_NOTIFY.get_or_init(Notify::new)
+}
+
+/// Sent every time a job changes state or makes progress, clients receive the ones of their
+/// user at /api/jobs/events
+#[derive(Clone, Debug, Serialize)]
+pub struct JobEvent {
+    #[serde(skip)]
+    pub user_id: String,
+    pub job_id: String,
+    pub song_id: String,
+    pub state: JobState,
+    pub error: Option<String>,
+    #[serde(flatten)]
+    pub progress: Option<Progress>,
+}
+
+/// Channel the workers send the job events to
+pub fn job_events() -> &'static broadcast::Sender<JobEvent> {
+    static JOB_EVENTS: OnceLock<broadcast::Sender<JobEvent>> = OnceLock::new();
+
+    JOB_EVENTS.get_or_init(|| broadcast::channel(JOB_EVENTS_CAPACITY).0)
+}
+
+/// Puts the jobs a previous run of the server did not finish back on the queue and spawns
+/// config().download_workers workers to run them, along with every job queued later
+pub async fn start_workers(state: &AppState) -> Result<()> {
+    let requeued = db::download_job::requeue_running(state).await?;
+
+    if requeued > 0 {
+        tracing::info!("Requeued {requeued} download jobs interrupted by a restart");
+    }
+
+    for worker_id in 0..config().download_workers.max(1) {
+        tokio::spawn(worker::run(state.clone(), worker_id));
+    }
+
+    Ok(())
+}
+
+/// Spawns the garbage collector, which deletes the songs nobody has anymore every
+/// config().gc_interval_secs, unless it is 0
+pub fn start_gc(state: &AppState) {
+    if config().gc_interval_secs == 0 {
+        return;
+    }
+
+    tokio::spawn(gc::run(state.clone()));
+}
+
+/// Spawns a check of the library, which marks the songs whose audio file is missing or broken as
+/// unavailable, unless config().reconcile_on_startup is not set
+pub fn start_reconcile(state: &AppState) {
+    if !config().reconcile_on_startup {
+        return;
+    }
+
+    tokio::spawn(reconcile::run(state.clone()));
+}
+
+#[cfg(test)]
+mod tests;
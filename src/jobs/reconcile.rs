@@ -0,0 +1,236 @@
+use super::{in_flight, worker::gen_art_variants};
+use crate::{
+    config,
+    db::{self, song::SongStatus},
+    util::{
+        error::Error,
+        source::{audio_path, media_stem, Source},
+    },
+    AppState,
+};
+use anyhow::Result;
+use std::{collections::HashSet, fmt, io::ErrorKind, path::PathBuf, time::Duration};
+
+/// Files younger than this are never stray, downloads and uploads that are running have no song yet
+const STRAY_MIN_AGE: Duration = Duration::from_secs(3600);
+
+/// What reconcile is allowed to change, with everything off it only reports
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Repair {
+    pub redownload: bool,       // downloads the audio of broken songs again
+    pub mark_unavailable: bool, // for the broken songs that were not downloaded again
+    pub remove_stray: bool,     // removes the files that belong to no song
+}
+
+/// What is wrong with the audio file of a song
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Problem {
+    Missing,
+    Empty,
+    Corrupt(String), // what ffprobe said about it
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Missing => write!(f, "audio file is missing"),
+            Problem::Empty => write!(f, "audio file is empty"),
+            Problem::Corrupt(reason) => write!(f, "audio file is corrupt: {}", reason.trim()),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ReconcileReport {
+    pub checked: usize,
+    pub broken: Vec<(String, Problem)>, // song id and what is wrong with it
+    pub redownloaded: Vec<String>,
+    pub marked_unavailable: Vec<String>,
+    pub marked_available: Vec<String>, // unavailable songs whose audio is fine again
+    pub unchecked: Vec<String>,        // songs whose audio could not be checked, left as they are
+    pub stray_files: Vec<PathBuf>,
+}
+
+/// Checks the library once and logs what was found
+///
+/// Broken songs are marked unavailable and only downloaded again if
+/// config().reconcile_redownload is set, stray files are only reported
+pub async fn run(state: AppState) {
+    let repair = Repair {
+        redownload: config().reconcile_redownload,
+        mark_unavailable: true,
+        remove_stray: false,
+    };
+
+    match reconcile(&state, repair).await {
+        Ok(report) => tracing::info!(
+            "Checked {} songs, {} broken, {} could not be checked, {} downloaded again, {} stray files",
+            report.checked,
+            report.broken.len(),
+            report.unchecked.len(),
+            report.redownloaded.len(),
+            report.stray_files.len()
+        ),
+        Err(err) => tracing::error!("COULD NOT CHECK THE LIBRARY - CAUSE: {err:?}"),
+    }
+}
+
+/// Compares the songs in the database with the files in the output path
+///
+/// Finds songs whose audio file is missing, empty or not audio ffprobe can read, and files that
+/// belong to no song, then repairs what repair allows
+/// Unavailable songs whose audio file is fine are always marked available again
+pub async fn reconcile(state: &AppState, repair: Repair) -> Result<ReconcileReport> {
+    let mut report = ReconcileReport::default();
+    let mut media_stems = HashSet::new();
+
+    for song in db::song::all(state).await? {
+        media_stems.insert(media_stem(&song.id).to_string());
+        report.checked += 1;
+
+        let is_unavailable = song.status == SongStatus::Unavailable.as_ref();
+
+        // e.g. ffprobe is not installed, the rest of the songs and the stray files are still checked
+        let problem = match check_song(state, &song.id).await {
+            Ok(problem) => problem,
+            Err(err) => {
+                tracing::warn!("COULD NOT CHECK SONG {} - CAUSE: {err}", song.id);
+                report.unchecked.push(song.id);
+                continue;
+            }
+        };
+
+        let Some(problem) = problem else {
+            if is_unavailable {
+                db::song::set_status(state, &song.id, SongStatus::Available).await?;
+                report.marked_available.push(song.id);
+            }
+
+            continue;
+        };
+
+        tracing::warn!("SONG {} IS BROKEN - {problem}", song.id);
+        report.broken.push((song.id.clone(), problem));
+
+        if repair.redownload {
+            match redownload(state, &song.id).await {
+                Ok(()) => {
+                    if is_unavailable {
+                        db::song::set_status(state, &song.id, SongStatus::Available).await?;
+                    }
+
+                    report.redownloaded.push(song.id);
+                    continue;
+                }
+                Err(err) => {
+                    tracing::warn!("COULD NOT DOWNLOAD SONG {} AGAIN - CAUSE: {err}", song.id)
+                }
+            }
+        }
+
+        if repair.mark_unavailable && !is_unavailable {
+            db::song::set_status(state, &song.id, SongStatus::Unavailable).await?;
+            report.marked_unavailable.push(song.id);
+        }
+    }
+
+    report.stray_files = stray_files(&media_stems).await?;
+
+    if repair.remove_stray {
+        for path in &report.stray_files {
+            if let Err(err) = tokio::fs::remove_file(path).await {
+                tracing::warn!("COULD NOT REMOVE {} - CAUSE: {err}", path.display());
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// Returns what is wrong with the audio file of the song, if anything
+///
+/// Fails when the file could not be checked at all, e.g. when ffprobe is not installed
+async fn check_song(state: &AppState, song_id: &str) -> Result<Option<Problem>, Error> {
+    let path = audio_path(song_id);
+
+    let Ok(metadata) = tokio::fs::metadata(&path).await else {
+        return Ok(Some(Problem::Missing));
+    };
+
+    if metadata.len() == 0 {
+        return Ok(Some(Problem::Empty));
+    }
+
+    match state.downloader.check_audio(&path).await {
+        Ok(()) => Ok(None),
+        Err(Error::UnsupportedAudio(reason)) => Ok(Some(Problem::Corrupt(reason))),
+        Err(err) => Err(err),
+    }
+}
+
+/// Replaces the files of the song with freshly downloaded ones, uploads can not be downloaded
+async fn redownload(state: &AppState, song_id: &str) -> Result<(), String> {
+    let source = Source::from_song_id(song_id).map_err(|e| e.to_string())?;
+    source.url().map_err(|e| e.to_string())?;
+    source.check_enabled().map_err(|e| e.to_string())?;
+
+    // a worker may be downloading the song for a new job right now
+    in_flight::download_once(song_id, async {
+        // yt-dlp skips songs whose file is there already, broken or not
+        let _ = tokio::fs::remove_file(audio_path(song_id)).await;
+
+        state
+            .downloader
+            .download(&source, &mut |_| {})
+            .await
+            .map_err(|e| e.to_string())?;
+
+        gen_art_variants(song_id).await;
+
+        Ok(())
+    })
+    .await
+}
+
+/// Returns the files in the output path whose name does not start with the media stem of a song
+async fn stray_files(media_stems: &HashSet<String>) -> Result<Vec<PathBuf>> {
+    let mut entries = match tokio::fs::read_dir(&config().yt_dlp_output_path).await {
+        Ok(entries) => entries,
+        // nothing was downloaded yet
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut stray_files = vec![];
+
+    while let Some(entry) = entries.next_entry().await? {
+        let metadata = entry.metadata().await?;
+
+        if !metadata.is_file() {
+            continue;
+        }
+
+        // media stems have no dots, covers are stem.jpg and stem.size.jpg
+        let file_name = entry.file_name();
+        let file_name = file_name.to_string_lossy();
+        let stem = file_name.split('.').next().unwrap_or_default();
+
+        if media_stems.contains(stem) {
+            continue;
+        }
+
+        let is_recent = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_none_or(|age| age < STRAY_MIN_AGE);
+
+        if !is_recent {
+            stray_files.push(entry.path());
+        }
+    }
+
+    stray_files.sort();
+
+    Ok(stray_files)
+}
@@ -0,0 +1,246 @@
+use super::{
+    gc::{collect, collect_older_than, remove_media_files},
+    in_flight::download_once,
+    reconcile::{reconcile, Problem, Repair},
+};
+use crate::{
+    config,
+    db::{self, junctions, song::SongStatus},
+    downloader::fake::FakeDownloader,
+    util::{
+        art::art_path,
+        source::{audio_path, Provider, Source},
+    },
+    AppState,
+};
+use anyhow::Result;
+use migration::{Migrator, MigratorTrait};
+use sea_orm::Database;
+use std::{
+    fs::{File, FileTimes},
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+use tokio::sync::Barrier;
+
+#[tokio::test]
+async fn concurrent_downloads_run_once() {
+    static RUNS: AtomicUsize = AtomicUsize::new(0);
+    let barrier = Barrier::new(2);
+
+    let download = || async {
+        RUNS.fetch_add(1, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        Err("yt-dlp failed".to_string())
+    };
+
+    let leader = async {
+        barrier.wait().await;
+        download_once("dQw4w9WgXcQ", download()).await
+    };
+    let follower = async {
+        barrier.wait().await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        download_once("dQw4w9WgXcQ", download()).await
+    };
+
+    let (leader, follower) = tokio::join!(leader, follower);
+
+    // both get the result of the only download that ran
+    assert_eq!(RUNS.load(Ordering::SeqCst), 1);
+    assert_eq!(leader, Err("yt-dlp failed".to_string()));
+    assert_eq!(follower, leader);
+
+    // the download is forgotten once it ends
+    download_once("dQw4w9WgXcQ", download()).await.unwrap_err();
+    assert_eq!(RUNS.load(Ordering::SeqCst), 2);
+
+    // other songs never wait on it
+    let (a, b) = tokio::join!(
+        download_once("9bZkp7q19f0", async { Ok(()) }),
+        download_once("kJQP7kiw5Fk", async { Ok(()) })
+    );
+    assert_eq!((a, b), (Ok(()), Ok(())));
+}
+
+#[tokio::test]
+async fn reconcile_library() -> Result<()> {
+    let db = Database::connect("sqlite::memory:").await?;
+    Migrator::up(&db, None).await?;
+
+    let state = AppState {
+        db,
+        downloader: Arc::new(FakeDownloader::default()),
+    };
+
+    db::user::create_new_user(&state, "reconcile", "passwd").await?;
+    let user = db::user::first_by_username(&state, "reconcile")
+        .await?
+        .unwrap();
+
+    let output = Path::new(&config().yt_dlp_output_path);
+    let missing = Source::youtube("rcMissing01");
+    let empty = Source::youtube("rcEmpty0001");
+    let corrupt = Source::youtube("rcCorrupt01");
+    let healthy = Source::youtube("rcHealthy01");
+    let upload = Source::new(Provider::Upload, uuid::Uuid::new_v4().to_string());
+
+    for source in [&missing, &empty, &corrupt, &healthy, &upload] {
+        let result = match source.provider() {
+            Provider::Upload => Default::default(),
+            _ => state.downloader.download(source, &mut |_| {}).await?,
+        };
+
+        db::song::create_new(&state, &source.song_id(), result.into(), &user.id, "").await?;
+    }
+
+    let audio_path = |source: &Source| output.join(format!("{}.opus", source.media_stem()));
+
+    std::fs::remove_file(audio_path(&missing))?;
+    std::fs::write(audio_path(&empty), "")?;
+    std::fs::write(audio_path(&corrupt), "<!DOCTYPE html>")?;
+
+    // files that were just written may belong to a download that is running
+    let stray = output.join("rcStray0001.opus");
+    let recent = output.join("rcRecent001.opus");
+    std::fs::write(&stray, "stray")?;
+    std::fs::write(&recent, "recent")?;
+    File::options()
+        .write(true)
+        .open(&stray)?
+        .set_times(FileTimes::new().set_modified(SystemTime::now() - Duration::from_secs(7200)))?;
+
+    // only reports without repairs
+    let report = reconcile(&state, Repair::default()).await?;
+    let broken: Vec<_> = report
+        .broken
+        .iter()
+        .map(|(id, p)| (id.as_str(), p))
+        .collect();
+
+    assert_eq!(report.checked, 5);
+    assert!(broken.contains(&(missing.song_id().as_str(), &Problem::Missing)));
+    assert!(broken.contains(&(empty.song_id().as_str(), &Problem::Empty)));
+    assert!(broken
+        .iter()
+        .any(|(id, p)| *id == corrupt.song_id() && matches!(p, Problem::Corrupt(_))));
+    assert!(broken.contains(&(upload.song_id().as_str(), &Problem::Missing)));
+    assert!(!broken.iter().any(|(id, _)| *id == healthy.song_id()));
+    assert!(report.marked_unavailable.is_empty());
+    assert!(report.stray_files.contains(&stray));
+    assert!(!report.stray_files.contains(&recent));
+    assert!(stray.exists());
+
+    // uploads can not be downloaded again
+    let repair = Repair {
+        redownload: true,
+        mark_unavailable: true,
+        remove_stray: false,
+    };
+    let report = reconcile(&state, repair).await?;
+
+    let mut redownloaded = report.redownloaded.clone();
+    redownloaded.sort();
+    assert_eq!(
+        redownloaded,
+        [corrupt.song_id(), empty.song_id(), missing.song_id()]
+    );
+    assert_eq!(report.marked_unavailable, [upload.song_id()]);
+
+    let song = db::song::first_by_id_any_user(&state, &upload.song_id())
+        .await?
+        .unwrap();
+    assert_eq!(song.status, SongStatus::Unavailable.as_ref());
+
+    // it is available again once its file is back
+    std::fs::copy(audio_path(&healthy), audio_path(&upload))?;
+
+    let report = reconcile(&state, Repair::default()).await?;
+    assert!(report.broken.is_empty());
+    assert_eq!(report.marked_available, [upload.song_id()]);
+
+    for path in [stray, recent, audio_path(&upload)] {
+        std::fs::remove_file(path)?;
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn collect_orphaned_songs() -> Result<()> {
+    let db = Database::connect("sqlite::memory:").await?;
+    Migrator::up(&db, None).await?;
+
+    let state = AppState {
+        db,
+        downloader: Arc::new(FakeDownloader::default()),
+    };
+
+    db::user::create_new_user(&state, "collect", "passwd").await?;
+    let user = db::user::first_by_username(&state, "collect")
+        .await?
+        .unwrap();
+
+    let orphan = Source::youtube("gcOrphan001");
+    let queued = Source::youtube("gcQueued001");
+    let running = Source::youtube("gcRunning01");
+
+    for source in [&orphan, &queued, &running] {
+        let result = state.downloader.download(source, &mut |_| {}).await?;
+
+        db::song::create_new(&state, &source.song_id(), result.into(), &user.id, "").await?;
+        junctions::user_song::delete(&state, &user.id, &source.song_id()).await?;
+    }
+
+    // the song of a job that is downloading it again or about to add it to a library
+    db::download_job::create_new(&state, "gc-running", &user.id, &running.song_id()).await?;
+    db::download_job::claim_next(&state, "").await?;
+    db::download_job::create_new(&state, "gc-queued", &user.id, &queued.song_id()).await?;
+
+    let orphan_files = [
+        audio_path(&orphan.song_id()),
+        art_path(&orphan.song_id(), None),
+    ];
+    let orphan_bytes: u64 = orphan_files
+        .iter()
+        .map(|path| path.metadata().map(|m| m.len()))
+        .sum::<Result<_, _>>()?;
+
+    // kept during config().gc_grace_secs
+    let report = collect(&state, false).await?;
+    assert!(report.song_ids.is_empty());
+    assert!(db::song::first_by_id_any_user(&state, &orphan.song_id())
+        .await?
+        .is_some());
+
+    // a dry run only reports it
+    let report = collect_older_than(&state, true, Duration::ZERO).await?;
+    assert_eq!(report.song_ids, [orphan.song_id()]);
+    assert_eq!(report.reclaimed_bytes, orphan_bytes);
+    assert!(orphan_files.iter().all(|path| path.exists()));
+
+    // deleted after it, along with its files
+    let report = collect_older_than(&state, false, Duration::ZERO).await?;
+    assert_eq!(report.song_ids, [orphan.song_id()]);
+    assert_eq!(report.reclaimed_bytes, orphan_bytes);
+    assert!(orphan_files.iter().all(|path| !path.exists()));
+    assert!(db::song::first_by_id_any_user(&state, &orphan.song_id())
+        .await?
+        .is_none());
+
+    for source in [&queued, &running] {
+        let song = db::song::first_by_id_any_user(&state, &source.song_id()).await?;
+        assert!(song.is_some(), "{}", source.song_id());
+        assert!(audio_path(&source.song_id()).exists());
+    }
+
+    for source in [&queued, &running] {
+        remove_media_files(&source.song_id(), false).await;
+    }
+
+    Ok(())
+}
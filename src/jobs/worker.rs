@@ -0,0 +1,226 @@
+use super::{in_flight, job_events, queue_notify, JobEvent, JobState};
+use crate::{
+    db::{self, song::SongStatus},
+    util::{
+        art,
+        error::Error,
+        source::{audio_path, Source},
+        time::{now_utc, utc_time_to_str},
+        yt_dlp::{Phase, Progress},
+    },
+    AppState,
+};
+use entity::download_job;
+use sea_orm::SqlErr;
+use std::{pin::pin, time::Duration};
+use tokio::time::timeout;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Runs queued jobs one at a time, forever
+pub async fn run(state: AppState, worker_id: usize) {
+    tracing::debug!("DOWNLOAD WORKER {worker_id} STARTED");
+
+    loop {
+        // registered before looking at the queue, so a job queued in between still wakes it up
+        let mut notified = pin!(queue_notify().notified());
+        notified.as_mut().enable();
+
+        let started_at = utc_time_to_str(now_utc()).unwrap_or_default();
+
+        match db::download_job::claim_next(&state, &started_at).await {
+            Ok(Some(job)) => {
+                run_job(&state, job).await;
+                continue;
+            }
+            Ok(None) => {}
+            Err(err) => tracing::error!(
+                "DOWNLOAD WORKER {worker_id} - COULD NOT READ THE QUEUE - CAUSE: {err:?}"
+            ),
+        }
+
+        let _ = timeout(POLL_INTERVAL, notified).await;
+    }
+}
+
+/// Downloads the song of the job and adds it to the library of the user that requested it, then
+/// stores how the job ended
+async fn run_job(state: &AppState, job: download_job::Model) {
+    tracing::debug!("RUNNING DOWNLOAD JOB {} - SONG {}", job.id, job.song_id);
+
+    send_event(&job, JobState::Running, None, None);
+
+    let (job_state, error) = match download(state, &job).await {
+        Ok(()) => (JobState::Done, None),
+        Err(err) => (JobState::Failed, Some(err)),
+    };
+
+    let finished_at = utc_time_to_str(now_utc()).unwrap_or_default();
+    let finish =
+        db::download_job::finish(state, &job.id, job_state, error.clone(), &finished_at).await;
+
+    if let Err(err) = finish {
+        tracing::error!("COULD NOT FINISH DOWNLOAD JOB {} - CAUSE: {err:?}", job.id);
+    }
+
+    send_event(&job, job_state, error, None);
+}
+
+fn send_event(
+    job: &download_job::Model,
+    state: JobState,
+    error: Option<String>,
+    progress: Option<Progress>,
+) {
+    // fails only when nobody is listening
+    let _ = job_events().send(JobEvent {
+        user_id: job.user_id.clone(),
+        job_id: job.id.clone(),
+        song_id: job.song_id.clone(),
+        state,
+        error,
+        progress,
+    });
+}
+
+/// Returns the reason the job failed, which is the stderr of yt-dlp when it ran and failed
+async fn download(state: &AppState, job: &download_job::Model) -> Result<(), String> {
+    // jobs of other users for the same song share the download instead of running yt-dlp again
+    in_flight::download_once(&job.song_id, store_song(state, job)).await?;
+
+    link_song(state, &job.user_id, &job.song_id).await?;
+
+    // queued by a playlist import
+    if let (Some(playlist_id), Some(position)) = (&job.playlist_id, job.playlist_position) {
+        add_to_playlist(state, job, playlist_id, position).await?;
+    }
+
+    Ok(())
+}
+
+/// Downloads the song of the job and stores it, unless it is stored already
+async fn store_song(state: &AppState, job: &download_job::Model) -> Result<(), String> {
+    // the song may have been downloaded for someone else while the job was queued
+    let existing_song = db::song::first_by_id_any_user(state, &job.song_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if existing_song.as_ref().is_some_and(db::song::is_available) {
+        return Ok(());
+    }
+
+    let source = Source::from_song_id(&job.song_id).map_err(|e| e.to_string())?;
+
+    // queued before direct links were disabled
+    source.check_enabled().map_err(|e| e.to_string())?;
+
+    // marked unavailable by a library check, yt-dlp skips songs whose file is there, broken or not
+    if existing_song.is_some() {
+        let _ = tokio::fs::remove_file(audio_path(&job.song_id)).await;
+    }
+
+    // yt-dlp reports progress many times per second, only changes of a whole percent or of phase
+    // are sent
+    let mut last_reported: Option<(Phase, Option<u64>)> = None;
+    let mut on_progress = |progress: Progress| {
+        let reported = (progress.phase, progress.percent.map(|p| p as u64));
+
+        if last_reported != Some(reported) {
+            last_reported = Some(reported);
+            send_event(job, JobState::Running, None, Some(progress));
+        }
+    };
+
+    let result = state
+        .downloader
+        .download(&source, &mut on_progress)
+        .await
+        .map_err(|e| match e {
+            Error::YtDlpExitCode(_, stderr) => stderr,
+            e => e.to_string(),
+        })?;
+
+    gen_art_variants(&job.song_id).await;
+
+    if existing_song.is_some() {
+        return db::song::set_status(state, &job.song_id, SongStatus::Available)
+            .await
+            .map_err(|e| e.to_string());
+    }
+
+    let created_at = utc_time_to_str(now_utc()).map_err(|e| e.to_string())?;
+
+    match db::song::create_new(
+        state,
+        &job.song_id,
+        result.into(),
+        &job.user_id,
+        &created_at,
+    )
+    .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => match e.sql_err() {
+            // stored in between by something that does not go through the registry
+            Some(SqlErr::UniqueConstraintViolation(_)) => Ok(()),
+            _ => Err(e.to_string()),
+        },
+    }
+}
+
+/// Adds a song that is already downloaded to the library of the user
+async fn link_song(state: &AppState, user_id: &str, song_id: &str) -> Result<(), String> {
+    match db::junctions::user_song::create_new(state, user_id, song_id).await {
+        Ok(()) => Ok(()),
+        Err(e) => match e.sql_err() {
+            // the user already has it
+            Some(SqlErr::UniqueConstraintViolation(_)) => Ok(()),
+            _ => Err(e.to_string()),
+        },
+    }
+}
+
+/// Adds the song of the job to the playlist at position, unless the playlist was deleted since the
+/// job was queued
+async fn add_to_playlist(
+    state: &AppState,
+    job: &download_job::Model,
+    playlist_id: &str,
+    position: i32,
+) -> Result<(), String> {
+    let playlist = db::playlist::first_by_id(state, playlist_id, &job.user_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if playlist.is_none() {
+        return Ok(());
+    }
+
+    match db::junctions::playlist_song::create_new_at(state, playlist_id, &job.song_id, position)
+        .await
+    {
+        Ok(()) => Ok(()),
+        Err(e) => match e.sql_err() {
+            // the playlist already has it
+            Some(SqlErr::UniqueConstraintViolation(_)) => Ok(()),
+            _ => Err(e.to_string()),
+        },
+    }
+}
+
+/// Generates the resized covers of a song that was just downloaded or uploaded
+///
+/// A song without a cover is still playable, so failing only gets logged and /art serves a
+/// placeholder for it
+pub async fn gen_art_variants(song_id: &str) {
+    let id = song_id.to_string();
+
+    let result = tokio::task::spawn_blocking(move || art::gen_variants(&id))
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|result| result.map_err(|e| e.to_string()));
+
+    if let Err(err) = result {
+        tracing::warn!("COULD NOT GENERATE COVER ART OF SONG {song_id} - CAUSE: {err}");
+    }
+}
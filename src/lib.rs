@@ -3,6 +3,8 @@ pub mod conf;
 pub mod context;
 pub mod crypt;
 pub mod db;
+pub mod downloader;
+pub mod jobs;
 pub mod util;
 
 pub use conf::config;
@@ -10,12 +12,15 @@ pub use conf::keys;
 
 use axum::middleware;
 use axum::Router;
+use downloader::Downloader;
 use sea_orm::DatabaseConnection;
+use std::sync::Arc;
 use tower_cookies::CookieManagerLayer;
 
 #[derive(Clone)]
 pub struct AppState {
     pub db: DatabaseConnection,
+    pub downloader: Arc<dyn Downloader>, // yt-dlp, unless a test swaps it for a fake
 }
 
 // The difference between layers and route_layers used here is that route_layers apply only when
@@ -23,13 +28,29 @@ pub struct AppState {
 pub fn build_app(state: AppState) -> Router {
     let routes_rest = Router::new()
         .merge(api::song::router(state.clone()))
+        .merge(api::job::router(state.clone()))
         .merge(api::playlist::router(state.clone()))
+        .merge(api::search::router(state.clone()))
+        .merge(api::session::router(state.clone()))
+        .merge(api::api_key::router(state.clone()))
+        .merge(api::invite::router(state.clone()))
+        .merge(api::me::router(state.clone()))
+        .merge(api::totp::router(state.clone()))
+        .merge(
+            api::admin::router(state.clone())
+                .route_layer(middleware::from_fn(api::mw::ctx::ctx_require_admin)),
+        )
         .route_layer(middleware::from_fn(api::mw::ctx::ctx_require_auth));
 
     Router::new()
         .nest("/api", api::auth::router(state.clone()))
         .nest("/api", routes_rest)
         .merge(api::stream::router(state.clone()))
-        .layer(middleware::from_fn(api::mw::ctx::ctx_resolver))
+        .merge(api::art::router(state.clone()))
+        .merge(api::jwks::router())
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            api::mw::ctx::ctx_resolver,
+        ))
         .layer(CookieManagerLayer::new())
 }
@@ -1,6 +1,6 @@
 use anyhow::Result;
-use ripfy_server::{build_app, config, db, keys, AppState};
-use std::net::SocketAddr;
+use ripfy_server::{build_app, config, db, jobs, keys, util::yt_dlp::YtDlp, AppState};
+use std::{net::SocketAddr, sync::Arc};
 use tokio::net::TcpListener;
 use tracing_subscriber::EnvFilter;
 
@@ -17,7 +17,14 @@ async fn main() -> Result<()> {
     keys();
 
     let db = db::connect().await?;
-    let state = AppState { db };
+    let state = AppState {
+        db,
+        downloader: Arc::new(YtDlp::default()),
+    };
+
+    jobs::start_workers(&state).await?;
+    jobs::start_gc(&state);
+    jobs::start_reconcile(&state);
 
     let app = build_app(state);
 
@@ -26,7 +33,11 @@ async fn main() -> Result<()> {
 
     tracing::info!("Listening on {}", socket_address);
 
-    axum::serve(listener, app.into_make_service()).await?;
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
 
     Ok(())
 }
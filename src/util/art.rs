@@ -0,0 +1,45 @@
+use super::{error::Error, source::media_stem};
+use crate::config;
+use image::{imageops::FilterType, ImageFormat};
+use std::path::{Path, PathBuf};
+
+/// Sizes in pixels of the square variants generated for every cover
+pub const ART_SIZES: [u32; 2] = [256, 512];
+
+/// Returns where the cover of the song is stored, next to its audio, size None is the thumbnail
+/// as yt-dlp downloaded it
+pub fn art_path(song_id: &str, size: Option<u32>) -> PathBuf {
+    let media_stem = media_stem(song_id);
+    let file_name = match size {
+        Some(size) => format!("{media_stem}.{size}.jpg"),
+        None => format!("{media_stem}.jpg"),
+    };
+
+    Path::new(&config().yt_dlp_output_path).join(file_name)
+}
+
+/// Crops the cover of the song to a square from its center and stores a copy of it for every size
+/// of ART_SIZES
+///
+/// Decoding and encoding the images blocks, so it should run on a blocking task
+pub fn gen_variants(song_id: &str) -> Result<(), Error> {
+    let cover = image::open(art_path(song_id, None)).map_err(|e| Error::ArtError(e.to_string()))?;
+
+    let side = cover.width().min(cover.height());
+    let square = cover.crop_imm(
+        (cover.width() - side) / 2,
+        (cover.height() - side) / 2,
+        side,
+        side,
+    );
+
+    for size in ART_SIZES {
+        square
+            .resize_exact(size, size, FilterType::Lanczos3)
+            .to_rgb8()
+            .save_with_format(art_path(song_id, Some(size)), ImageFormat::Jpeg)
+            .map_err(|e| Error::ArtError(e.to_string()))?;
+    }
+
+    Ok(())
+}
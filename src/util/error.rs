@@ -9,6 +9,16 @@ pub enum Error {
     // link parsing
     #[error("The provided link is invalid")]
     InvalidLink,
+    #[error("The provided song id is invalid")]
+    InvalidSongId,
+    #[error("Uploaded songs are not downloaded from anywhere")]
+    NotDownloadable,
+    #[error("Direct audio links are disabled on this server")]
+    DirectLinksDisabled,
+    #[error("The host of the link could not be resolved")]
+    UnresolvableHost,
+    #[error("The host of the link resolves to an address that is not public")]
+    PrivateHost,
 
     // yt-dlp
     #[error("The providad yt-dlp install and/or output path is invalid!")]
@@ -21,4 +31,18 @@ pub enum Error {
     YtDlpExitCode(i32, String),
     #[error("Could not process the output of the yt-dlp process!")]
     YtDlpOutputParseError,
+
+    // ffmpeg
+    #[error("Could not spawn the ffmpeg process with the specified parameters!")]
+    FfmpegSpawnError,
+    #[error("Could not read/write something into/from the ffmpeg process!\nReason: {0}")]
+    FfmpegIOError(String),
+    #[error("Ffmpeg failed with exit code {0}!\nStderr: {1}")]
+    FfmpegExitCode(i32, String),
+    #[error("The file is not audio ffmpeg can read!\nReason: {0}")]
+    UnsupportedAudio(String),
+
+    // cover art
+    #[error("Could not generate the cover art variants!\nReason: {0}")]
+    ArtError(String),
 }
@@ -0,0 +1,252 @@
+use super::{error::Error, source::Source, yt_dlp::YtDlpResult};
+use crate::config;
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+use tokio::{process::Command, time::timeout};
+
+/// Bitrate of the opus files made from uploads, about what yt-dlp gets from youtube
+const OPUS_BITRATE: &str = "160k";
+
+/// Creates ffprobe and ffmpeg processes that turn local audio files into songs
+/// Requires ffmpeg and ffprobe binaries, which yt-dlp needs too
+#[derive(Debug)]
+pub struct Ffmpeg {
+    ffmpeg_path: PathBuf,
+    ffprobe_path: PathBuf,
+    output_path: PathBuf,
+    timeout: Duration,
+}
+
+impl Default for Ffmpeg {
+    fn default() -> Self {
+        Self {
+            ffmpeg_path: Path::new(&config().ffmpeg_binary_path).to_path_buf(),
+            ffprobe_path: Path::new(&config().ffprobe_binary_path).to_path_buf(),
+            output_path: Path::new(&config().yt_dlp_output_path).to_path_buf(),
+            timeout: Duration::from_millis(config().yt_dlp_timeout_milisecs),
+        }
+    }
+}
+
+/// What ffprobe knows about a file, only the parts used to build the metadata of a song
+#[derive(Debug, Deserialize)]
+pub(super) struct Probe {
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+    format: Option<ProbeFormat>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProbeStream {
+    index: u32,
+    codec_type: Option<String>,
+    #[serde(default)]
+    disposition: HashMap<String, u8>,
+    #[serde(default)]
+    tags: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProbeFormat {
+    duration: Option<String>, // in seconds
+    #[serde(default)]
+    tags: HashMap<String, String>,
+}
+
+impl Probe {
+    fn audio_stream(&self) -> Option<&ProbeStream> {
+        self.streams
+            .iter()
+            .find(|s| s.codec_type.as_deref() == Some("audio"))
+    }
+
+    /// Index of the picture embedded as cover, which ffprobe lists as a video stream
+    fn cover_stream(&self) -> Option<u32> {
+        self.streams
+            .iter()
+            .find(|s| s.disposition.get("attached_pic") == Some(&1))
+            .map(|s| s.index)
+    }
+
+    /// Tags by lowercased name, mp3 and mp4 keep them on the file, ogg and opus on the audio
+    /// stream
+    fn tags(&self) -> HashMap<String, String> {
+        let file_tags = self.format.iter().flat_map(|f| f.tags.iter());
+        let stream_tags = self.audio_stream().into_iter().flat_map(|s| s.tags.iter());
+
+        file_tags
+            .chain(stream_tags)
+            .map(|(name, value)| (name.to_lowercase(), value.trim().to_string()))
+            .filter(|(_, value)| !value.is_empty())
+            .collect()
+    }
+
+    /// Metadata of the song, the title is left empty when the file has no title tag
+    pub(super) fn into_result(self) -> YtDlpResult {
+        let mut tags = self.tags();
+        let title = tags.remove("title");
+        let artist = tags
+            .remove("artist")
+            .or_else(|| tags.remove("album_artist"));
+        let release_year = tags
+            .get("date")
+            .or_else(|| tags.get("year"))
+            .and_then(|date| date.get(..4))
+            .and_then(|year| year.parse().ok());
+
+        YtDlpResult {
+            channel: artist.clone().unwrap_or_default(),
+            fulltitle: title.clone().unwrap_or_default(),
+            duration: self
+                .format
+                .and_then(|f| f.duration)
+                .and_then(|d| d.parse().ok()),
+            artist,
+            album: tags.remove("album"),
+            track: title,
+            release_year,
+            ..Default::default()
+        }
+    }
+}
+
+impl Ffmpeg {
+    /// Receives a local audio file and the source of the song it becomes as parameters
+    /// Reads its tags, transcodes its audio to opus and extracts its cover as jpg to the
+    /// output_path, the same way yt-dlp outputs a download
+    /// Returns the tags as a YtDlpResult
+    pub async fn import(&self, input: &Path, source: &Source) -> Result<YtDlpResult, Error> {
+        let probe = self.probe_audio(input).await?;
+
+        let input = file_url(input)?;
+        let media_stem = source.media_stem();
+        let opus = file_url(&self.output_path.join(format!("{media_stem}.opus")))?;
+
+        let args = vec![
+            "-y",
+            "-v",
+            "error",
+            "-i",
+            &input,
+            "-map",
+            "0:a:0",
+            "-vn",
+            "-c:a",
+            "libopus",
+            "-b:a",
+            OPUS_BITRATE,
+            &opus,
+        ];
+
+        self.execute(&self.ffmpeg_path, args).await?;
+
+        // a song without a cover is still playable, /art serves a placeholder for it
+        if let Some(cover_stream) = probe.cover_stream() {
+            let map = format!("0:{cover_stream}");
+            let jpg = file_url(&self.output_path.join(format!("{media_stem}.jpg")))?;
+            let args = vec![
+                "-y",
+                "-v",
+                "error",
+                "-i",
+                &input,
+                "-map",
+                &map,
+                "-frames:v",
+                "1",
+                &jpg,
+            ];
+
+            if let Err(err) = self.execute(&self.ffmpeg_path, args).await {
+                tracing::warn!("COULD NOT EXTRACT COVER OF {media_stem} - CAUSE: {err}");
+            }
+        }
+
+        Ok(probe.into_result())
+    }
+
+    /// Receives a media file as parameter
+    /// Fails with Error::UnsupportedAudio when ffprobe can not read it or it has no audio
+    pub async fn check_audio(&self, input: &Path) -> Result<(), Error> {
+        self.probe_audio(input).await?;
+
+        Ok(())
+    }
+
+    async fn probe_audio(&self, input: &Path) -> Result<Probe, Error> {
+        let probe = self.probe(input).await?;
+
+        if probe.audio_stream().is_none() {
+            return Err(Error::UnsupportedAudio(
+                "The file has no audio stream".into(),
+            ));
+        }
+
+        Ok(probe)
+    }
+
+    async fn probe(&self, input: &Path) -> Result<Probe, Error> {
+        let input = file_url(input)?;
+        let args = vec![
+            "-v",
+            "error",
+            "-print_format",
+            "json",
+            "-show_format",
+            "-show_streams",
+            &input,
+        ];
+
+        let stdout = self
+            .execute(&self.ffprobe_path, args)
+            .await
+            .map_err(|e| match e {
+                // ffprobe could not make sense of the file
+                Error::FfmpegExitCode(_, stderr) => Error::UnsupportedAudio(stderr),
+                e => e,
+            })?;
+
+        serde_json::from_slice(&stdout).map_err(|e| Error::UnsupportedAudio(e.to_string()))
+    }
+
+    /// Runs binary with args until it exits
+    /// Returns its stdout, or its exit code and stderr if it did not exit with exit code 0
+    /// Process WILL timeout if it takes longer than self.timeout duration
+    async fn execute(&self, binary: &Path, args: Vec<&str>) -> Result<Vec<u8>, Error> {
+        let child = Command::new(binary)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .args(args)
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|_| Error::FfmpegSpawnError)?;
+
+        let output = timeout(self.timeout, child.wait_with_output())
+            .await
+            .map_err(|_| Error::FfmpegExitCode(1, "Ffmpeg process timeout".to_string()))?
+            .map_err(|e| Error::FfmpegIOError(e.to_string()))?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8(output.stderr).unwrap_or_default();
+            return Err(Error::FfmpegExitCode(
+                output.status.code().unwrap_or(1),
+                stderr,
+            ));
+        }
+
+        Ok(output.stdout)
+    }
+}
+
+/// Paths as file: urls, media stems hold colons, which ffmpeg would take for a protocol
+fn file_url(path: &Path) -> Result<String, Error> {
+    let path = path.to_str().ok_or(Error::InvalidYtDlpPath)?;
+
+    Ok(format!("file:{path}"))
+}
@@ -1,9 +1,245 @@
-use super::error::Error;
-use lazy_regex::regex_captures;
+use super::{
+    error::Error,
+    source::{encode_http_id, Provider, Source},
+};
+use lazy_regex::{regex_captures, regex_is_match};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use url::{Host, Url};
 
+/// Hosts of youtube watch, shorts, embed and live links
+const YT_HOSTS: [&str; 6] = [
+    "youtube.com",
+    "www.youtube.com",
+    "m.youtube.com",
+    "music.youtube.com",
+    "youtube-nocookie.com",
+    "www.youtube-nocookie.com",
+];
+/// Hosts of youtube share links, the path is the video id
+const YT_SHORT_HOSTS: [&str; 2] = ["youtu.be", "www.youtu.be"];
+
+/// Longest link of a plain audio file, its base64 ends up in file names, which can not be longer
+/// than 255 bytes
+const MAX_HTTP_LINK_LEN: usize = 160;
+
+/// Returns the external id of the song the link points to
+type LinkParser = fn(&str) -> Result<String, Error>;
+
+/// Link parser of every provider, the first one that accepts the link wins
+const LINK_PARSERS: [(Provider, LinkParser); 4] = [
+    (Provider::YouTube, parse_yt_link),
+    (Provider::SoundCloud, parse_soundcloud_link),
+    (Provider::Bandcamp, parse_bandcamp_link),
+    (Provider::Http, parse_http_audio_link),
+];
+
+/// Parses a link of any supported provider into the source of the song
+pub fn parse_link(link: &str) -> Result<Source, Error> {
+    let link = link.trim();
+
+    LINK_PARSERS
+        .iter()
+        .find_map(|(provider, parse)| {
+            parse(link)
+                .ok()
+                .map(|external_id| Source::new(*provider, external_id))
+        })
+        .ok_or(Error::InvalidLink)
+}
+
+/// Extracts the video id of a youtube or youtube music link, or Returns the link as is when it is
+/// a video id already
+///
+/// Accepts watch, shorts, embed and live links, on any of the hosts youtube serves videos from,
+/// with or without the scheme, every query parameter except v is ignored
 pub fn parse_yt_link(link: &str) -> Result<String, Error> {
-    let (_, id) =
-        regex_captures!(r#"(?:watch\?v=|youtu\.be\/)([\w-]+)"#, link).ok_or(Error::InvalidLink)?;
+    let link = link.trim();
+
+    if is_yt_video_id(link) {
+        return Ok(link.to_string());
+    }
+
+    // links are often pasted without the scheme
+    let url = Url::parse(link)
+        .or_else(|_| Url::parse(&format!("https://{link}")))
+        .map_err(|_| Error::InvalidLink)?;
+
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(Error::InvalidLink);
+    }
+
+    let host = url.host_str().ok_or(Error::InvalidLink)?;
+    let segments: Vec<&str> = url
+        .path_segments()
+        .map(|segments| segments.filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default();
+
+    let id = if YT_SHORT_HOSTS.contains(&host) {
+        segments.first().map(|id| id.to_string())
+    } else if YT_HOSTS.contains(&host) {
+        match segments.as_slice() {
+            ["watch"] => url
+                .query_pairs()
+                .find(|(key, _)| key == "v")
+                .map(|(_, id)| id.into_owned()),
+            ["shorts" | "embed" | "live" | "v" | "e", id, ..] => Some(id.to_string()),
+            _ => None,
+        }
+    } else {
+        None
+    };
+
+    id.filter(|id| is_yt_video_id(id)).ok_or(Error::InvalidLink)
+}
+
+/// Youtube video ids are always 11 characters long
+fn is_yt_video_id(id: &str) -> bool {
+    regex_is_match!(r#"^[\w-]{11}$"#, id)
+}
+
+/// Extracts the list id of a youtube or youtube music playlist link, albums are playlists too
+pub fn parse_yt_playlist_link(link: &str) -> Result<String, Error> {
+    let (_, id) = regex_captures!(r#"[?&]list=([\w-]+)"#, link).ok_or(Error::InvalidLink)?;
 
     Ok(id.to_string())
 }
+
+/// Accepts soundcloud.com/user/track links, sets, likes and the rest of the pages of a user are
+/// not songs
+pub fn parse_soundcloud_link(link: &str) -> Result<String, Error> {
+    let (_, user, track) = regex_captures!(
+        r#"^https?://(?:www\.|m\.)?soundcloud\.com/([\w-]+)/([\w-]+)/?(?:[?#]\S*)?$"#,
+        link
+    )
+    .ok_or(Error::InvalidLink)?;
+
+    let user_pages = [
+        "albums",
+        "comments",
+        "followers",
+        "following",
+        "likes",
+        "popular-tracks",
+        "reposts",
+        "sets",
+        "tracks",
+    ];
+
+    if user_pages.contains(&track) {
+        return Err(Error::InvalidLink);
+    }
+
+    Ok(format!("{user}:{track}"))
+}
+
+/// Accepts artist.bandcamp.com/track/track links, albums are not a single song
+pub fn parse_bandcamp_link(link: &str) -> Result<String, Error> {
+    let (_, artist, track) = regex_captures!(
+        r#"^https?://([a-z0-9-]+)\.bandcamp\.com/track/([\w-]+)/?(?:[?#]\S*)?$"#,
+        link
+    )
+    .ok_or(Error::InvalidLink)?;
+
+    Ok(format!("{artist}:{track}"))
+}
+
+/// Accepts links to audio files served over http, recognized by their extension
+///
+/// The server fetches them itself, so links to loopback, private, link-local and other hosts that
+/// are not on the public internet are rejected
+pub fn parse_http_audio_link(link: &str) -> Result<String, Error> {
+    let is_audio_file = regex_is_match!(
+        r#"^https?://[^\s/?#]+/[^\s?#]*\.(?:aac|flac|m4a|mp3|oga|ogg|opus|wav)(?:[?#]\S*)?$"#i,
+        link
+    );
+
+    if !is_audio_file || link.len() > MAX_HTTP_LINK_LEN {
+        return Err(Error::InvalidLink);
+    }
+
+    let url = Url::parse(link).map_err(|_| Error::InvalidLink)?;
+
+    // Url normalizes hosts like 0x7f.1 or 2130706433 into the address they stand for
+    let is_public = match url.host() {
+        Some(Host::Domain(domain)) => is_public_domain(domain),
+        Some(Host::Ipv4(ip)) => is_public_ipv4(ip),
+        Some(Host::Ipv6(ip)) => is_public_ipv6(ip),
+        None => false,
+    };
+
+    if !is_public {
+        return Err(Error::InvalidLink);
+    }
+
+    Ok(encode_http_id(link))
+}
+
+/// Resolves the host of a direct audio link and fails unless every address it resolves to is public
+///
+/// Done right before the download, as a domain can be pointed at a private address after the link
+/// was accepted. yt-dlp still resolves the host on its own and follows redirects, so this narrows
+/// what direct links can reach but does NOT make them SSRF-safe
+pub async fn check_http_link_resolves_public(link: &str) -> Result<(), Error> {
+    let url = Url::parse(link).map_err(|_| Error::InvalidLink)?;
+    let host = url.host_str().ok_or(Error::InvalidLink)?;
+    let port = url.port_or_known_default().ok_or(Error::InvalidLink)?;
+    // host_str keeps the brackets around ipv6 hosts, lookup_host does not take them
+    let addrs: Vec<IpAddr> = tokio::net::lookup_host((host.trim_matches(['[', ']']), port))
+        .await
+        .map_err(|_| Error::UnresolvableHost)?
+        .map(|addr| addr.ip())
+        .collect();
+
+    let is_public = |ip: &IpAddr| match ip {
+        IpAddr::V4(ip) => is_public_ipv4(*ip),
+        IpAddr::V6(ip) => is_public_ipv6(*ip),
+    };
+
+    if addrs.is_empty() {
+        return Err(Error::UnresolvableHost);
+    }
+
+    if !addrs.iter().all(is_public) {
+        return Err(Error::PrivateHost);
+    }
+
+    Ok(())
+}
+
+/// Names that only resolve inside a network, single label names included
+fn is_public_domain(domain: &str) -> bool {
+    let domain = domain.trim_end_matches('.').to_lowercase();
+    let local_suffixes = [".localhost", ".local", ".internal", ".lan", ".home.arpa"];
+
+    domain.contains('.') && !local_suffixes.iter().any(|suffix| domain.ends_with(suffix))
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    // 0.0.0.0/8 and the carrier-grade nat range 100.64.0.0/10
+    let is_reserved = a == 0 || (a == 100 && (64..128).contains(&b));
+
+    !(ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local() // cloud metadata endpoints live here
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || is_reserved)
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    if let Some(ip) = ip.to_ipv4_mapped() {
+        return is_public_ipv4(ip);
+    }
+
+    let first_segment = ip.segments()[0];
+    let is_unique_local = first_segment & 0xfe00 == 0xfc00;
+    let is_link_local = first_segment & 0xffc0 == 0xfe80;
+
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        || is_unique_local
+        || is_link_local)
+}
@@ -1,5 +1,8 @@
+pub mod art;
 pub mod error;
+pub mod ffmpeg;
 pub mod link;
+pub mod source;
 pub mod time;
 pub mod yt_dlp;
 
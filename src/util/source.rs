@@ -0,0 +1,172 @@
+use super::error::Error;
+use crate::config;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use lazy_regex::regex_is_match;
+use std::path::{Path, PathBuf};
+
+/// Where a song is downloaded from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Provider {
+    YouTube,
+    SoundCloud,
+    Bandcamp,
+    Http,   // a plain audio file
+    Upload, // uploaded by a user, never downloaded
+}
+
+impl Provider {
+    /// Namespace of the ids of the songs of the provider
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            Provider::YouTube => "yt",
+            Provider::SoundCloud => "sc",
+            Provider::Bandcamp => "bc",
+            Provider::Http => "http",
+            Provider::Upload => "up",
+        }
+    }
+
+    fn from_prefix(prefix: &str) -> Option<Self> {
+        [
+            Provider::YouTube,
+            Provider::SoundCloud,
+            Provider::Bandcamp,
+            Provider::Http,
+            Provider::Upload,
+        ]
+        .into_iter()
+        .find(|provider| provider.prefix() == prefix)
+    }
+}
+
+/// A song as its provider knows it
+///
+/// The external id is what the provider calls the song, it only ever holds characters that are
+/// safe in a path:
+/// - YouTube: the video id
+/// - SoundCloud: user:track, from soundcloud.com/user/track
+/// - Bandcamp: artist:track, from artist.bandcamp.com/track/track
+/// - Http: the url of the file as url safe base64
+/// - Upload: a uuid generated when it was uploaded
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Source {
+    provider: Provider,
+    external_id: String,
+}
+
+impl Source {
+    pub fn new(provider: Provider, external_id: impl Into<String>) -> Self {
+        Self {
+            provider,
+            external_id: external_id.into(),
+        }
+    }
+
+    pub fn youtube(id: impl Into<String>) -> Self {
+        Self::new(Provider::YouTube, id)
+    }
+
+    /// Parses the id of a song, as Source::song_id makes them, back into its source
+    pub fn from_song_id(song_id: &str) -> Result<Self, Error> {
+        let (prefix, external_id) = song_id.split_once(':').ok_or(Error::InvalidSongId)?;
+        let provider = Provider::from_prefix(prefix).ok_or(Error::InvalidSongId)?;
+
+        let is_valid = match provider {
+            Provider::YouTube => regex_is_match!(r#"^[\w-]+$"#, external_id),
+            Provider::SoundCloud => regex_is_match!(r#"^[\w-]+:[\w-]+$"#, external_id),
+            Provider::Bandcamp => regex_is_match!(r#"^[a-z0-9-]+:[\w-]+$"#, external_id),
+            Provider::Http => decode_http_id(external_id).is_some(),
+            Provider::Upload => regex_is_match!(
+                r#"^[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}$"#,
+                external_id
+            ),
+        };
+
+        if !is_valid {
+            return Err(Error::InvalidSongId);
+        }
+
+        Ok(Self::new(provider, external_id))
+    }
+
+    pub fn provider(&self) -> Provider {
+        self.provider
+    }
+
+    pub fn external_id(&self) -> &str {
+        &self.external_id
+    }
+
+    /// Id of the song in the database, the external id namespaced by the provider, so songs of
+    /// different providers never share an id
+    pub fn song_id(&self) -> String {
+        format!("{}:{}", self.provider.prefix(), self.external_id)
+    }
+
+    /// Fails for plain audio files unless config().direct_links_enabled is set, those are fetched
+    /// from whatever host the link points to, redirects included
+    pub fn check_enabled(&self) -> Result<(), Error> {
+        if self.provider == Provider::Http && !config().direct_links_enabled {
+            return Err(Error::DirectLinksDisabled);
+        }
+
+        Ok(())
+    }
+
+    /// Link the song is downloaded from, uploads have none
+    pub fn url(&self) -> Result<String, Error> {
+        let url = match self.provider {
+            Provider::YouTube => format!("https://www.youtube.com/watch?v={}", self.external_id),
+            Provider::SoundCloud => {
+                format!(
+                    "https://soundcloud.com/{}",
+                    self.external_id.replace(':', "/")
+                )
+            }
+            Provider::Bandcamp => {
+                let (artist, track) = self
+                    .external_id
+                    .split_once(':')
+                    .ok_or(Error::InvalidSongId)?;
+
+                format!("https://{artist}.bandcamp.com/track/{track}")
+            }
+            Provider::Http => decode_http_id(&self.external_id).ok_or(Error::InvalidSongId)?,
+            Provider::Upload => return Err(Error::NotDownloadable),
+        };
+
+        Ok(url)
+    }
+
+    /// Name of the media files of the song in the output path, without extension
+    pub fn media_stem(&self) -> String {
+        media_stem(&self.song_id()).to_string()
+    }
+}
+
+/// Name of the media files of a song in the output path, without extension
+///
+/// YouTube songs keep the bare video id, which is what their files were named before song ids
+/// were namespaced, the rest use the song id
+pub fn media_stem(song_id: &str) -> &str {
+    song_id
+        .strip_prefix(Provider::YouTube.prefix())
+        .and_then(|id| id.strip_prefix(':'))
+        .unwrap_or(song_id)
+}
+
+/// Returns where the audio of the song is stored
+pub fn audio_path(song_id: &str) -> PathBuf {
+    Path::new(&config().yt_dlp_output_path).join(format!("{}.opus", media_stem(song_id)))
+}
+
+/// External id of a plain audio file
+pub(super) fn encode_http_id(url: &str) -> String {
+    URL_SAFE_NO_PAD.encode(url)
+}
+
+fn decode_http_id(external_id: &str) -> Option<String> {
+    let url = String::from_utf8(URL_SAFE_NO_PAD.decode(external_id).ok()?).ok()?;
+
+    regex_is_match!(r#"^https?://\S+$"#, &url).then_some(url)
+}
@@ -1,46 +1,365 @@
 use super::{
-    link::parse_yt_link,
-    yt_dlp::{YtDlp, YtDlpResult},
+    error::Error,
+    ffmpeg::Probe,
+    link::{check_http_link_resolves_public, parse_link, parse_yt_link, parse_yt_playlist_link},
+    source::{media_stem, Provider, Source},
+    yt_dlp::{Phase, Progress, YtDlp, YtDlpResult},
 };
 use anyhow::Result;
 
 #[test]
-fn match_link() -> Result<()> {
+fn match_link() {
     let expected = "fJ9rUzIMcZQ";
 
-    let normal_link = "https://www.youtube.com/watch?v=fJ9rUzIMcZQ";
-    let parsed_id = parse_yt_link(normal_link)?;
-    assert_eq!(parsed_id, expected);
+    let valid_links = [
+        // watch
+        "https://www.youtube.com/watch?v=fJ9rUzIMcZQ",
+        "http://www.youtube.com/watch?v=fJ9rUzIMcZQ",
+        "https://youtube.com/watch?v=fJ9rUzIMcZQ",
+        "www.youtube.com/watch?v=fJ9rUzIMcZQ",
+        "youtube.com/watch?v=fJ9rUzIMcZQ",
+        "https://WWW.YouTube.com/watch?v=fJ9rUzIMcZQ",
+        "https://www.youtube.com/watch/?v=fJ9rUzIMcZQ",
+        "https://www.youtube.com/watch?v=fJ9rUzIMcZQ#t=30",
+        "  https://www.youtube.com/watch?v=fJ9rUzIMcZQ\n",
+        // v is not the first query parameter, the rest are ignored
+        "https://www.youtube.com/watch?feature=share&v=fJ9rUzIMcZQ",
+        "https://www.youtube.com/watch?v=fJ9rUzIMcZQ&t=42s",
+        "https://www.youtube.com/watch?v=fJ9rUzIMcZQ&list=PLFgquLnL59alCl_2TQvOiD5Vgm1hCaGSI&index=3",
+        "https://www.youtube.com/watch?app=desktop&v=fJ9rUzIMcZQ&si=lPjc-24fbVW_AzmG",
+        "https://www.youtube.com/watch?v=fJ9rUzIMcZQ&pp=ygUPYm9oZW1pYW4gcmhhcHNvZHk%3D",
+        // mobile and music
+        "https://m.youtube.com/watch?v=fJ9rUzIMcZQ",
+        "https://m.youtube.com/watch?v=fJ9rUzIMcZQ&feature=youtu.be",
+        "https://music.youtube.com/watch?v=fJ9rUzIMcZQ",
+        "https://music.youtube.com/watch?v=fJ9rUzIMcZQ&si=HxCAVbUy121XhYYM",
+        "https://music.youtube.com/watch?v=fJ9rUzIMcZQ&list=RDAMVMfJ9rUzIMcZQ",
+        // share
+        "https://youtu.be/fJ9rUzIMcZQ",
+        "https://youtu.be/fJ9rUzIMcZQ?si=lPjc-24fbVW_AzmG",
+        "https://youtu.be/fJ9rUzIMcZQ?t=42",
+        "https://www.youtu.be/fJ9rUzIMcZQ",
+        "youtu.be/fJ9rUzIMcZQ",
+        // shorts, embeds and lives
+        "https://www.youtube.com/shorts/fJ9rUzIMcZQ",
+        "https://youtube.com/shorts/fJ9rUzIMcZQ?feature=share",
+        "https://m.youtube.com/shorts/fJ9rUzIMcZQ",
+        "https://www.youtube.com/embed/fJ9rUzIMcZQ",
+        "https://www.youtube.com/embed/fJ9rUzIMcZQ?autoplay=1&start=10",
+        "https://www.youtube-nocookie.com/embed/fJ9rUzIMcZQ",
+        "https://youtube-nocookie.com/embed/fJ9rUzIMcZQ?rel=0",
+        "https://www.youtube.com/live/fJ9rUzIMcZQ",
+        "https://www.youtube.com/live/fJ9rUzIMcZQ?si=lPjc-24fbVW_AzmG",
+        "https://www.youtube.com/v/fJ9rUzIMcZQ",
+        "https://www.youtube.com/e/fJ9rUzIMcZQ",
+        // bare video id
+        "fJ9rUzIMcZQ",
+    ];
 
-    let share_link = "https://youtu.be/fJ9rUzIMcZQ?si=lPjc-24fbVW_AzmG";
-    let parsed_id = parse_yt_link(share_link)?;
-    assert_eq!(parsed_id, expected);
+    for link in valid_links {
+        let parsed_id = parse_yt_link(link);
+        assert_eq!(parsed_id.ok().as_deref(), Some(expected), "{link}");
+    }
 
-    let yt_music_link = "https://music.youtube.com/watch?v=fJ9rUzIMcZQ";
-    let parsed_id = parse_yt_link(yt_music_link)?;
-    assert_eq!(parsed_id, expected);
+    let ids_with_symbols = [
+        ("https://www.youtube.com/watch?v=Nnjh-zp6pP4", "Nnjh-zp6pP4"),
+        ("https://youtu.be/_a_b-c_d-e_", "_a_b-c_d-e_"),
+        ("-abcdefghij", "-abcdefghij"),
+    ];
 
-    let yt_music_share_link = "https://music.youtube.com/watch?v=fJ9rUzIMcZQ&si=HxCAVbUy121XhYYM";
-    let parsed_id = parse_yt_link(yt_music_share_link)?;
-    assert_eq!(parsed_id, expected);
+    for (link, expected) in ids_with_symbols {
+        let parsed_id = parse_yt_link(link);
+        assert_eq!(parsed_id.ok().as_deref(), Some(expected), "{link}");
+    }
+
+    let invalid_links = [
+        "",
+        "   ",
+        "not a link",
+        // ids of the wrong shape
+        "fJ9rUzIMcZ",
+        "fJ9rUzIMcZQQ",
+        "fJ9rUzIMc!Q",
+        "https://www.youtube.com/watch?v=fJ9rUzIMcZ",
+        "https://www.youtube.com/watch?v=fJ9rUzIMcZQQ",
+        "https://www.youtube.com/watch?v=fJ9rUzIMcZQ<script>",
+        "https://www.youtube.com/watch?v=",
+        "https://youtu.be/fJ9rUzIMcZ",
+        "https://youtu.be/",
+        "https://www.youtube.com/shorts/",
+        "https://www.youtube.com/embed/fJ9rUzIMcZQQ",
+        // no video
+        "https://www.youtube.com/",
+        "https://www.youtube.com/watch",
+        "https://www.youtube.com/watch?list=PLFgquLnL59alCl_2TQvOiD5Vgm1hCaGSI",
+        "https://www.youtube.com/playlist?list=PLFgquLnL59alCl_2TQvOiD5Vgm1hCaGSI",
+        "https://www.youtube.com/@QueenOfficial",
+        "https://www.youtube.com/channel/UCiMhD4jzUqG-IgPzUmmytRQ",
+        "https://www.youtube.com/results?search_query=fJ9rUzIMcZQ",
+        "https://www.youtube.com/fJ9rUzIMcZQ",
+        "https://www.youtube.com/shortsfJ9rUzIMcZQ",
+        // not youtube
+        "https://www.notyoutube.com/watch?v=fJ9rUzIMcZQ",
+        "https://youtube.com.evil.com/watch?v=fJ9rUzIMcZQ",
+        "https://evil.com/watch?v=fJ9rUzIMcZQ",
+        "https://evil.com/?u=https://youtu.be/fJ9rUzIMcZQ",
+        "https://vimeo.com/fJ9rUzIMcZQ",
+        "https://gaming.youtube.com/watch?v=fJ9rUzIMcZQ",
+        "ftp://www.youtube.com/watch?v=fJ9rUzIMcZQ",
+        "javascript:alert('fJ9rUzIMcZQ')",
+    ];
+
+    for link in invalid_links {
+        assert!(parse_yt_link(link).is_err(), "{link}");
+    }
+}
+
+#[test]
+fn match_playlist_link() -> Result<()> {
+    let playlist_link = "https://www.youtube.com/playlist?list=PLFgquLnL59alCl_2TQvOiD5Vgm1hCaGSI";
+    let parsed_id = parse_yt_playlist_link(playlist_link)?;
+    assert_eq!(parsed_id, "PLFgquLnL59alCl_2TQvOiD5Vgm1hCaGSI");
+
+    let yt_music_album_link =
+        "https://music.youtube.com/playlist?list=OLAK5uy_nMr8X3gPh5-dSTFfSMNyMHzU4ZoDSRD-k&si=x1";
+    let parsed_id = parse_yt_playlist_link(yt_music_album_link)?;
+    assert_eq!(parsed_id, "OLAK5uy_nMr8X3gPh5-dSTFfSMNyMHzU4ZoDSRD-k");
+
+    let watch_in_playlist_link =
+        "https://www.youtube.com/watch?v=fJ9rUzIMcZQ&list=PLFgquLnL59alCl_2TQvOiD5Vgm1hCaGSI";
+    let parsed_id = parse_yt_playlist_link(watch_in_playlist_link)?;
+    assert_eq!(parsed_id, "PLFgquLnL59alCl_2TQvOiD5Vgm1hCaGSI");
+
+    assert!(parse_yt_playlist_link("https://www.youtube.com/watch?v=fJ9rUzIMcZQ").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn match_provider_link() -> Result<()> {
+    let source = parse_link("https://youtu.be/fJ9rUzIMcZQ")?;
+    assert_eq!(source.provider(), Provider::YouTube);
+    assert_eq!(source.song_id(), "yt:fJ9rUzIMcZQ");
+
+    let source =
+        parse_link("https://soundcloud.com/queen-69312/bohemian-rhapsody-remastered?in=x")?;
+    assert_eq!(source.provider(), Provider::SoundCloud);
+    assert_eq!(
+        source.song_id(),
+        "sc:queen-69312:bohemian-rhapsody-remastered"
+    );
+    assert_eq!(
+        source.url()?,
+        "https://soundcloud.com/queen-69312/bohemian-rhapsody-remastered"
+    );
+
+    let source = parse_link("https://m.soundcloud.com/queen-69312/killer-queen/")?;
+    assert_eq!(source.song_id(), "sc:queen-69312:killer-queen");
+
+    let source = parse_link("https://hrsr.bandcamp.com/track/one-more-time?from=fanpub")?;
+    assert_eq!(source.provider(), Provider::Bandcamp);
+    assert_eq!(source.song_id(), "bc:hrsr:one-more-time");
+    assert_eq!(
+        source.url()?,
+        "https://hrsr.bandcamp.com/track/one-more-time"
+    );
+
+    let file_link = "https://example.com/music/Song%20One.MP3?token=abc";
+    let source = parse_link(file_link)?;
+    assert_eq!(source.provider(), Provider::Http);
+    assert_eq!(source.url()?, file_link);
+
+    // playlists, albums and pages that are not audio files are not songs
+    assert!(parse_link("https://soundcloud.com/queen-69312/sets/greatest-hits").is_err());
+    assert!(parse_link("https://soundcloud.com/queen-69312").is_err());
+    assert!(parse_link("https://hrsr.bandcamp.com/album/discovery").is_err());
+    assert!(parse_link("https://example.com/music/index.html").is_err());
+    assert!(parse_link("https://example.com/page?file=song.mp3").is_err());
+    assert!(parse_link(&format!("https://example.com/{}.mp3", "a".repeat(200))).is_err());
+
+    // the server would fetch them from inside its own network
+    for link in [
+        "http://127.0.0.1:7717/song.mp3",
+        "http://2130706433/song.mp3",
+        "http://localhost/song.mp3",
+        "http://media.localhost/song.mp3",
+        "http://nas/song.mp3",
+        "http://nas.local/song.mp3",
+        "http://metadata.google.internal/song.mp3",
+        "http://169.254.169.254/latest/meta-data/song.mp3",
+        "http://10.0.0.5/song.mp3",
+        "http://172.16.0.1/song.mp3",
+        "http://192.168.1.10/song.mp3",
+        "http://100.64.0.1/song.mp3",
+        "http://0.0.0.0/song.mp3",
+        "http://[::1]/song.mp3",
+        "http://[fd00::1]/song.mp3",
+        "http://[fe80::1]/song.mp3",
+        "http://[::ffff:127.0.0.1]/song.mp3",
+    ] {
+        assert!(parse_link(link).is_err(), "{link}");
+    }
+
+    let source = parse_link("http://93.184.216.34/song.ogg")?;
+    assert_eq!(source.provider(), Provider::Http);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn http_link_resolves_public() -> Result<()> {
+    check_http_link_resolves_public("http://93.184.216.34/song.ogg").await?;
+    check_http_link_resolves_public("https://[2606:2800:220:1::]/song.ogg").await?;
+
+    for link in [
+        "http://127.0.0.1:7717/song.mp3",
+        "http://10.0.0.5/song.mp3",
+        "http://[::1]/song.mp3",
+    ] {
+        assert!(matches!(
+            check_http_link_resolves_public(link).await,
+            Err(Error::PrivateHost)
+        ));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn song_id_round_trip() -> Result<()> {
+    for link in [
+        "https://youtu.be/fJ9rUzIMcZQ",
+        "https://soundcloud.com/queen-69312/killer-queen",
+        "https://hrsr.bandcamp.com/track/one-more-time",
+        "http://example.com/song.flac",
+    ] {
+        let source = parse_link(link)?;
+        assert_eq!(Source::from_song_id(&source.song_id())?, source);
+    }
+
+    // youtube files keep the names they had before ids were namespaced
+    assert_eq!(media_stem("yt:fJ9rUzIMcZQ"), "fJ9rUzIMcZQ");
+    assert_eq!(media_stem("bc:hrsr:one-more-time"), "bc:hrsr:one-more-time");
+
+    assert!(Source::from_song_id("fJ9rUzIMcZQ").is_err());
+    assert!(Source::from_song_id("xx:fJ9rUzIMcZQ").is_err());
+    assert!(Source::from_song_id("yt:../../conf").is_err());
+    assert!(Source::from_song_id("sc:queen-69312").is_err());
+    assert!(Source::from_song_id("http:bm90IGEgdXJs").is_err()); // "not a url"
+
+    Ok(())
+}
+
+#[test]
+fn parse_progress() -> Result<()> {
+    let downloading =
+        Progress::from_line("[ripfy-progress] downloading 2500 10000 NA 1048576.5 12").unwrap();
+    assert_eq!(downloading.phase, Phase::Downloading);
+    assert_eq!(downloading.percent, Some(25.0));
+    assert_eq!(downloading.speed_bytes_per_sec, Some(1048576.5));
+    assert_eq!(downloading.eta_secs, Some(12));
+
+    // falls back to the estimate when the total size is unknown
+    let estimated =
+        Progress::from_line("[ripfy-progress] downloading 500 NA 1000.0 NA NA").unwrap();
+    assert_eq!(estimated.percent, Some(50.0));
+    assert_eq!(estimated.speed_bytes_per_sec, None);
+    assert_eq!(estimated.eta_secs, None);
+
+    let unknown = Progress::from_line("[ripfy-progress] downloading NA NA NA NA NA").unwrap();
+    assert_eq!(unknown.percent, None);
+
+    let extracting = Progress::from_line("[ripfy-progress] ExtractAudio").unwrap();
+    assert_eq!(extracting.phase, Phase::Extracting);
+
+    let converting = Progress::from_line("[ripfy-progress] FFmpegMetadata").unwrap();
+    assert_eq!(converting.phase, Phase::Converting);
+
+    assert!(Progress::from_line(r#"{"channel": "Queen Official"}"#).is_none());
+    assert!(Progress::from_line("[download] 25.0% of 10.00MiB").is_none());
+
+    Ok(())
+}
+
+#[test]
+fn format_upload_date() -> Result<()> {
+    let result = YtDlpResult {
+        upload_date: Some("20080801".into()),
+        ..Default::default()
+    };
+    assert_eq!(result.upload_date_iso().as_deref(), Some("2008-08-01"));
+
+    let result = YtDlpResult {
+        upload_date: Some("NA".into()),
+        ..Default::default()
+    };
+    assert_eq!(result.upload_date_iso(), None);
+
+    assert_eq!(YtDlpResult::default().upload_date_iso(), None);
+
+    Ok(())
+}
+
+#[test]
+fn parse_probe() -> Result<()> {
+    // mp3 keeps its tags on the file, the cover is a video stream
+    let mp3: Probe = serde_json::from_str(
+        r#"{
+        "streams": [
+            {"index": 0, "codec_type": "audio", "disposition": {"attached_pic": 0}},
+            {"index": 1, "codec_type": "video", "disposition": {"attached_pic": 1}}
+        ],
+        "format": {
+            "duration": "354.320000",
+            "tags": {"TITLE": "Bohemian Rhapsody", "ARTIST": "Queen", "album": "A Night at the Opera", "date": "1975-10-31"}
+        }
+    }"#,
+    )?;
+    let result = mp3.into_result();
+    assert_eq!(result.fulltitle, "Bohemian Rhapsody");
+    assert_eq!(result.track.as_deref(), Some("Bohemian Rhapsody"));
+    assert_eq!(result.channel, "Queen");
+    assert_eq!(result.artist.as_deref(), Some("Queen"));
+    assert_eq!(result.album.as_deref(), Some("A Night at the Opera"));
+    assert_eq!(result.release_year, Some(1975));
+    assert_eq!(result.duration, Some(354.32));
+
+    // opus keeps them on the audio stream
+    let opus: Probe = serde_json::from_str(
+        r#"{
+        "streams": [{"index": 0, "codec_type": "audio", "tags": {"album_artist": "Queen", "YEAR": "1974"}}],
+        "format": {"duration": "180.0"}
+    }"#,
+    )?;
+    let result = opus.into_result();
+    assert_eq!(result.fulltitle, "");
+    assert_eq!(result.track, None);
+    assert_eq!(result.channel, "Queen");
+    assert_eq!(result.release_year, Some(1974));
+    assert_eq!(result.duration, Some(180.0));
 
     Ok(())
 }
 
 #[tokio::test]
+#[ignore = "needs network and a yt-dlp binary, run with --ignored"]
 async fn yt_dlp_process() -> Result<()> {
     let process = YtDlp::default();
 
-    let id = "fJ9rUzIMcZQ";
+    let source = Source::youtube("fJ9rUzIMcZQ");
 
-    let output = process.run(id).await?;
+    let output = process.run(&source).await?;
 
     let expected = YtDlpResult {
         channel: "Queen Official".into(),
         fulltitle: "Queen – Bohemian Rhapsody (Official Video Remastered)".into(),
+        ..Default::default()
     };
 
     assert_eq!(output.to_string(), expected.to_string());
+    assert!(output.duration.is_some_and(|d| d > 0.0));
+    assert!(output.upload_date_iso().is_some());
+    assert!(output.thumbnail.is_some());
 
     Ok(())
 }
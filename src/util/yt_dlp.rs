@@ -1,4 +1,8 @@
-use super::error::Error;
+use super::{
+    error::Error,
+    link::check_http_link_resolves_public,
+    source::{Provider, Source},
+};
 use crate::config;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -9,15 +13,33 @@ use std::{
     time::Duration,
 };
 use tokio::{
-    io::AsyncReadExt,
-    process::{Child, Command},
+    io::{AsyncBufReadExt, AsyncReadExt, BufReader},
+    process::{Child, ChildStdout, Command},
     time::timeout,
 };
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Marks the lines printed by --progress-template, so they can be told apart from the output of
+/// --print
+const PROGRESS_PREFIX: &str = "[ripfy-progress]";
+const DOWNLOAD_PROGRESS_TEMPLATE: &str = "download:[ripfy-progress] downloading %(progress.downloaded_bytes)s %(progress.total_bytes)s %(progress.total_bytes_estimate)s %(progress.speed)s %(progress.eta)s";
+const POSTPROCESS_PROGRESS_TEMPLATE: &str =
+    "postprocess:[ripfy-progress] %(progress.postprocessor)s";
+
+/// Info about a downloaded video, the optional fields are only known for some videos, artist,
+/// album and track mostly for music
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct YtDlpResult {
-    pub channel: String,
+    #[serde(default)]
+    pub channel: String, // the uploader for providers without channels
     pub fulltitle: String,
+    pub duration: Option<f64>, // in seconds
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub track: Option<String>,
+    pub release_year: Option<i32>,
+    pub upload_date: Option<String>, // YYYYMMDD
+    pub thumbnail: Option<String>,
+    pub uploader: Option<String>,
 }
 
 impl YtDlpResult {
@@ -25,19 +47,132 @@ impl YtDlpResult {
         let value: Value =
             serde_json::from_reader(stdout.as_slice()).map_err(|_| Error::YtDlpOutputParseError)?;
 
-        let result: Self =
+        let mut result: Self =
             serde_json::from_value(value).map_err(|_| Error::YtDlpOutputParseError)?;
 
+        // only youtube has channels
+        if result.channel.is_empty() {
+            result.channel = result.uploader.clone().unwrap_or_default();
+        }
+
         Ok(result)
     }
 }
 
+impl YtDlpResult {
+    /// Returns the upload date as YYYY-MM-DD
+    pub fn upload_date_iso(&self) -> Option<String> {
+        let date = self.upload_date.as_deref()?;
+
+        if date.len() != 8 || !date.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+
+        Some(format!("{}-{}-{}", &date[..4], &date[4..6], &date[6..]))
+    }
+}
+
 impl Display for YtDlpResult {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "Channel: {}\nTitle: {}", self.channel, self.fulltitle)
     }
 }
 
+/// A playlist or album as listed by yt-dlp, entries are in the order of the playlist
+#[derive(Debug, Serialize, Deserialize)]
+pub struct YtDlpPlaylist {
+    pub title: Option<String>,
+    #[serde(default)]
+    pub entries: Vec<Option<YtDlpPlaylistEntry>>, // None for entries yt-dlp could not read at all
+}
+
+/// A video of a playlist or of search results, private and deleted videos are still listed, with a
+/// placeholder title
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct YtDlpPlaylistEntry {
+    pub id: Option<String>,
+    pub title: Option<String>,
+    pub channel: Option<String>,
+    pub duration: Option<f64>, // in seconds
+    #[serde(default)]
+    pub thumbnails: Vec<YtDlpThumbnail>, // smallest first
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct YtDlpThumbnail {
+    pub url: String,
+}
+
+impl YtDlpPlaylistEntry {
+    /// Returns false for videos that can not be downloaded by anyone
+    pub fn is_available(&self) -> bool {
+        !matches!(
+            self.title.as_deref(),
+            Some("[Private video]" | "[Deleted video]" | "[Unavailable video]")
+        )
+    }
+}
+
+/// What yt-dlp is doing with a song
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, strum_macros::AsRefStr)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum Phase {
+    Downloading,
+    Extracting, // extracting the audio from the downloaded video
+    Converting, // any other post-processing step
+}
+
+/// Progress of a yt-dlp process, fields yt-dlp could not tell are None
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Progress {
+    pub phase: Phase,
+    pub percent: Option<f64>,
+    pub speed_bytes_per_sec: Option<f64>,
+    pub eta_secs: Option<u64>,
+}
+
+impl Progress {
+    /// Parses a line printed with the progress templates, returns None for any other line
+    ///
+    /// yt-dlp prints NA for the fields it does not know
+    pub fn from_line(line: &str) -> Option<Self> {
+        let mut fields = line
+            .trim()
+            .strip_prefix(PROGRESS_PREFIX)?
+            .split_whitespace();
+
+        match fields.next()? {
+            "downloading" => {
+                let mut number = || fields.next().and_then(|f| f.parse::<f64>().ok());
+                let (downloaded, total, total_estimate, speed, eta) =
+                    (number(), number(), number(), number(), number());
+
+                let percent = downloaded
+                    .zip(total.or(total_estimate))
+                    .filter(|(_, total)| *total > 0.0)
+                    .map(|(downloaded, total)| (downloaded / total * 100.0).min(100.0));
+
+                Some(Self {
+                    phase: Phase::Downloading,
+                    percent,
+                    speed_bytes_per_sec: speed,
+                    eta_secs: eta.map(|eta| eta as u64),
+                })
+            }
+            postprocessor => Some(Self {
+                phase: match postprocessor {
+                    "ExtractAudio" => Phase::Extracting,
+                    _ => Phase::Converting,
+                },
+                percent: None,
+                speed_bytes_per_sec: None,
+                eta_secs: None,
+            }),
+        }
+    }
+}
+
 /// Creates a new yt-dlp process template
 /// Requires a yt-dlp binary in the install_path and ffmpeg
 #[derive(Debug)]
@@ -45,6 +180,7 @@ pub struct YtDlp {
     install_path: PathBuf,
     output_path: PathBuf,
     timeout: Duration,
+    search_timeout: Duration,
 }
 
 impl Default for YtDlp {
@@ -53,37 +189,141 @@ impl Default for YtDlp {
             install_path: Path::new(&config().yt_dlp_binary_path).to_path_buf(),
             output_path: Path::new(&config().yt_dlp_output_path).to_path_buf(),
             timeout: Duration::from_millis(config().yt_dlp_timeout_milisecs),
+            search_timeout: Duration::from_secs(config().search_timeout_secs),
         }
     }
 }
 
 impl YtDlp {
-    /// Receives a yt video id as parameter
-    /// Downloads video from url, extracts audio as opus and outputs it to the output_path
+    /// Receives the source of a song as parameter
+    /// Downloads it from its url, extracts audio as opus and outputs it to the output_path, along
+    /// with its thumbnail as jpg
     /// Returns some info about the video as a YtDlpOutput
-    pub async fn run(&self, id: &str) -> Result<YtDlpResult, Error> {
-        let url = get_url(id);
+    pub async fn run(&self, source: &Source) -> Result<YtDlpResult, Error> {
+        self.run_with_progress(source, |_| {}).await
+    }
 
-        // extract audio - convert to opus - output to output_path - name is the video id
+    /// Same as run, but calls on_progress every time yt-dlp reports progress
+    pub async fn run_with_progress(
+        &self,
+        source: &Source,
+        on_progress: impl FnMut(Progress),
+    ) -> Result<YtDlpResult, Error> {
+        let url = source.url()?;
+        let media_stem = source.media_stem();
+
+        if source.provider() == Provider::Http {
+            check_http_link_resolves_public(&url).await?;
+        }
+
+        // extract audio - convert to opus - write the thumbnail as jpg - output to output_path - name
+        // is the media stem of the song
+        // --print implies --quiet, --progress brings the progress lines back, one per line
         let args = vec![
             "--print",
-            "before_dl:%(.{channel,fulltitle})#j",
+            "before_dl:%(.{channel,uploader,fulltitle,duration,artist,album,track,release_year,upload_date,thumbnail})#j",
+            "--progress",
+            "--newline",
+            "--progress-template",
+            DOWNLOAD_PROGRESS_TEMPLATE,
+            "--progress-template",
+            POSTPROCESS_PROGRESS_TEMPLATE,
             "-x",
             "--audio-format",
             "opus",
+            "--write-thumbnail",
+            "--convert-thumbnails",
+            "jpg",
             "-P",
             self.output_path.to_str().ok_or(Error::InvalidYtDlpPath)?,
             "-o",
-            "%(id)s",
+            &media_stem,
             &url,
         ];
 
+        let stdout = self.execute(args, self.timeout, on_progress).await?;
+
+        let yt_dlp_result = YtDlpResult::from_stdout(stdout)?;
+
+        Ok(yt_dlp_result)
+    }
+
+    /// Receives a yt playlist id as parameter
+    /// Lists the videos of the playlist without downloading anything, up to
+    /// config().playlist_import_max_entries of them
+    /// Returns the title of the playlist and its entries in order as a YtDlpPlaylist
+    pub async fn list_playlist(&self, list_id: &str) -> Result<YtDlpPlaylist, Error> {
+        self.list_flat(
+            &get_playlist_url(list_id),
+            config().playlist_import_max_entries,
+            self.timeout,
+        )
+        .await
+    }
+
+    /// Searches youtube for query without downloading anything
+    /// Returns up to limit videos, best match first
+    /// Has its own shorter timeout, users wait for the results
+    pub async fn search(
+        &self,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<YtDlpPlaylistEntry>, Error> {
+        // yt-dlp treats search results as a playlist
+        let results = self
+            .list_flat(
+                &format!("ytsearch{limit}:{query}"),
+                limit,
+                self.search_timeout,
+            )
+            .await?;
+
+        Ok(results.entries.into_iter().flatten().collect())
+    }
+
+    /// Lists the entries of a playlist-like url, up to max_entries of them
+    async fn list_flat(
+        &self,
+        url: &str,
+        max_entries: usize,
+        timeout: Duration,
+    ) -> Result<YtDlpPlaylist, Error> {
+        let items = format!("1:{max_entries}");
+
+        // prints the whole playlist as a single json, entries only carry what the playlist page has
+        let args = vec![
+            "--flat-playlist",
+            "--dump-single-json",
+            "--playlist-items",
+            &items,
+            url,
+        ];
+
+        let stdout = self.execute(args, timeout, |_| {}).await?;
+
+        serde_json::from_slice(&stdout).map_err(|_| Error::YtDlpOutputParseError)
+    }
+
+    /// Runs a yt-dlp process with args until it exits
+    /// Returns its stdout, or its exit code and stderr if it did not exit with exit code 0
+    async fn execute(
+        &self,
+        args: Vec<&str>,
+        timeout: Duration,
+        on_progress: impl FnMut(Progress),
+    ) -> Result<Vec<u8>, Error> {
         let mut child = self.spawn_child(args).await?;
-        let exit_code = self.execute_until_exit(&mut child).await?;
 
-        // reads stdout and stderr
-        let stdout = read_std_buffer(child.stdout).await?;
-        let stderr = read_std_buffer(child.stderr).await?;
+        // stderr is read on its own task, a full pipe would block the process
+        let stderr = tokio::spawn(read_std_buffer(child.stderr.take()));
+
+        let (exit_code, stdout) = self
+            .execute_until_exit(&mut child, timeout, on_progress)
+            .await?;
+
+        let stderr = stderr
+            .await
+            .map_err(|e| Error::YtDlpIOError(e.to_string()))??;
 
         // if the process did not exit with exit code 0, we return early
         if !exit_code.success() {
@@ -91,9 +331,7 @@ impl YtDlp {
             return Err(Error::YtDlpExitCode(exit_code.code().unwrap_or(1), stderr));
         }
 
-        let yt_dlp_result = YtDlpResult::from_stdout(stdout)?;
-
-        Ok(yt_dlp_result)
+        Ok(stdout)
     }
 
     /// spawns child process
@@ -108,11 +346,29 @@ impl YtDlp {
         Ok(child)
     }
 
-    /// Tries to run the process until exit
-    /// Process WILL timeout if it takes longer than self.timeout duration
-    async fn execute_until_exit(&self, child: &mut Child) -> Result<ExitStatus, Error> {
-        let exit_code = match timeout(self.timeout, child.wait()).await {
-            Ok(result) => result.map_err(|e| Error::YtDlpIOError(e.to_string()))?,
+    /// Tries to run the process until exit, reading stdout as it is written
+    /// Returns the exit code and every line of stdout that is not a progress line
+    /// Process WILL timeout if it takes longer than the timeout duration
+    async fn execute_until_exit(
+        &self,
+        child: &mut Child,
+        timeout_duration: Duration,
+        on_progress: impl FnMut(Progress),
+    ) -> Result<(ExitStatus, Vec<u8>), Error> {
+        let stdout = child.stdout.take();
+
+        let execution = async {
+            let stdout = read_stdout_lines(stdout, on_progress).await?;
+            let exit_code = child
+                .wait()
+                .await
+                .map_err(|e| Error::YtDlpIOError(e.to_string()))?;
+
+            Ok::<_, Error>((exit_code, stdout))
+        };
+
+        let result = match timeout(timeout_duration, execution).await {
+            Ok(result) => result?,
             Err(_) => {
                 child
                     .kill()
@@ -125,10 +381,41 @@ impl YtDlp {
             }
         };
 
-        Ok(exit_code)
+        Ok(result)
     }
 }
 
+/// Reads stdout line by line as the process writes it, progress lines are handed to on_progress
+/// and the rest is returned
+async fn read_stdout_lines(
+    stdout: Option<ChildStdout>,
+    mut on_progress: impl FnMut(Progress),
+) -> Result<Vec<u8>, Error> {
+    let mut output = vec![];
+
+    let Some(stdout) = stdout else {
+        return Ok(output);
+    };
+
+    let mut lines = BufReader::new(stdout).lines();
+
+    while let Some(line) = lines
+        .next_line()
+        .await
+        .map_err(|e| Error::YtDlpIOError(e.to_string()))?
+    {
+        match Progress::from_line(&line) {
+            Some(progress) => on_progress(progress),
+            None => {
+                output.extend_from_slice(line.as_bytes());
+                output.push(b'\n');
+            }
+        }
+    }
+
+    Ok(output)
+}
+
 /// Reads stdout and stderr from child processes
 async fn read_std_buffer<T: AsyncReadExt + Unpin>(reader: Option<T>) -> Result<Vec<u8>, Error> {
     let mut output = vec![];
@@ -143,6 +430,6 @@ async fn read_std_buffer<T: AsyncReadExt + Unpin>(reader: Option<T>) -> Result<V
     Ok(output)
 }
 
-fn get_url(id: &str) -> String {
-    "https://www.youtube.com/watch?v=".to_string() + id
+fn get_playlist_url(list_id: &str) -> String {
+    "https://www.youtube.com/playlist?list=".to_string() + list_id
 }
@@ -1,6 +1,7 @@
 use anyhow::Result;
 use axum::http::StatusCode;
 use dev_utils::{spawn_test_app, util::get_port};
+use ripfy_server::{crypt::totp, db, util::time::now_utc};
 use serde_json::json;
 
 #[tokio::test]
@@ -46,7 +47,8 @@ async fn auth_permissions_integration_test() -> Result<()> {
         }),
     );
 
-    assert_eq!(add_song.await?.status(), StatusCode::OK.as_u16());
+    // the song is downloaded in the background
+    assert_eq!(add_song.await?.status(), StatusCode::ACCEPTED.as_u16());
 
     client
         .do_post(
@@ -68,3 +70,722 @@ async fn auth_permissions_integration_test() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn refresh_token_integration_test() -> Result<()> {
+    let port = get_port();
+    spawn_test_app(port, true).await?;
+
+    let client = httpc_test::new_client(format!("http://localhost:{}", port))?;
+
+    let refresh = client.do_post("/api/refresh", json!({}));
+    assert_eq!(refresh.await?.status(), StatusCode::UNAUTHORIZED.as_u16());
+
+    client
+        .do_post(
+            "/api/login",
+            json!({
+            "username": "demo1",
+            "pwd": "demo1passwd"
+            }),
+        )
+        .await?;
+
+    // rotates the refresh token twice, the client always holds the newest one
+    for _ in 0..2 {
+        let refresh = client.do_post("/api/refresh", json!({}));
+        assert_eq!(refresh.await?.status(), StatusCode::OK.as_u16());
+    }
+
+    let playlists = client.do_get("/api/playlists");
+    assert_eq!(playlists.await?.status(), StatusCode::OK.as_u16());
+
+    client
+        .do_post(
+            "/api/logout",
+            json!({
+            "logoff": true
+            }),
+        )
+        .await?;
+
+    let refresh = client.do_post("/api/refresh", json!({}));
+    assert_eq!(refresh.await?.status(), StatusCode::UNAUTHORIZED.as_u16());
+
+    let playlists = client.do_get("/api/playlists");
+    assert_eq!(playlists.await?.status(), StatusCode::UNAUTHORIZED.as_u16());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn session_revocation_integration_test() -> Result<()> {
+    let port = get_port();
+    spawn_test_app(port, true).await?;
+
+    let laptop = httpc_test::new_client(format!("http://localhost:{}", port))?;
+    let lost_phone = httpc_test::new_client(format!("http://localhost:{}", port))?;
+
+    for client in [&laptop, &lost_phone] {
+        client
+            .do_post(
+                "/api/login",
+                json!({
+                "username": "demo1",
+                "pwd": "demo1passwd"
+                }),
+            )
+            .await?;
+    }
+
+    let sessions = laptop.do_get("/api/sessions").await?.json_body()?;
+    let sessions = sessions["data"].as_array().cloned().unwrap_or_default();
+    assert_eq!(sessions.len(), 2);
+
+    let phone_session = sessions
+        .iter()
+        .find(|session| session["current"] == false)
+        .and_then(|session| session["id"].as_str())
+        .unwrap_or_default();
+
+    let playlists = lost_phone.do_get("/api/playlists");
+    assert_eq!(playlists.await?.status(), StatusCode::OK.as_u16());
+
+    let revoke = laptop
+        .do_delete(format!("/api/sessions/{}", phone_session).as_str())
+        .await?;
+    assert_eq!(revoke.status(), StatusCode::OK.as_u16());
+
+    // the phone can neither use its access token nor get a new one
+    let playlists = lost_phone.do_get("/api/playlists");
+    assert_eq!(playlists.await?.status(), StatusCode::UNAUTHORIZED.as_u16());
+
+    let refresh = lost_phone.do_post("/api/refresh", json!({}));
+    assert_eq!(refresh.await?.status(), StatusCode::UNAUTHORIZED.as_u16());
+
+    let playlists = laptop.do_get("/api/playlists");
+    assert_eq!(playlists.await?.status(), StatusCode::OK.as_u16());
+
+    // logs out everywhere
+    laptop.do_delete("/api/sessions").await?;
+
+    let playlists = laptop.do_get("/api/playlists");
+    assert_eq!(playlists.await?.status(), StatusCode::UNAUTHORIZED.as_u16());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn token_in_body_integration_test() -> Result<()> {
+    let port = get_port();
+    spawn_test_app(port, true).await?;
+
+    let client = httpc_test::new_client(format!("http://localhost:{}", port))?;
+
+    let login = client
+        .do_post(
+            "/api/login",
+            json!({
+            "username": "demo1",
+            "pwd": "demo1passwd",
+            "token_delivery": "body"
+            }),
+        )
+        .await?
+        .json_body()?;
+
+    let refresh_token = login["result"]["refresh_token"]
+        .as_str()
+        .unwrap_or_default();
+    assert!(login["result"]["access_token"].is_string());
+
+    // no cookie was set, so the client is not authenticated by itself
+    let playlists = client.do_get("/api/playlists");
+    assert_eq!(playlists.await?.status(), StatusCode::UNAUTHORIZED.as_u16());
+
+    let refresh = client
+        .do_post("/api/refresh", json!({ "refresh_token": refresh_token }))
+        .await?;
+    assert_eq!(refresh.status(), StatusCode::OK.as_u16());
+    assert!(refresh.json_body()?["result"]["access_token"].is_string());
+
+    // the used refresh token was rotated
+    let refresh = client.do_post("/api/refresh", json!({ "refresh_token": refresh_token }));
+    assert_eq!(refresh.await?.status(), StatusCode::UNAUTHORIZED.as_u16());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn api_key_integration_test() -> Result<()> {
+    let port = get_port();
+    spawn_test_app(port, true).await?;
+
+    let client = httpc_test::new_client(format!("http://localhost:{}", port))?;
+
+    client
+        .do_post(
+            "/api/login",
+            json!({
+            "username": "demo1",
+            "pwd": "demo1passwd"
+            }),
+        )
+        .await?;
+
+    let created = client
+        .do_post(
+            "/api/keys",
+            json!({
+            "name": "cron",
+            "scope": "add-songs"
+            }),
+        )
+        .await?
+        .json_body()?;
+
+    let key_id = created["result"]["id"].as_str().unwrap_or_default();
+    assert!(created["result"]["key"]
+        .as_str()
+        .is_some_and(|key| key.starts_with("ripfy_")));
+
+    // the key itself is never listed
+    let keys = client.do_get("/api/keys").await?.json_body()?;
+    assert_eq!(keys["data"][0]["id"].as_str(), Some(key_id));
+    assert_eq!(keys["data"][0]["scope"].as_str(), Some("add-songs"));
+    assert!(keys["data"][0].get("key").is_none());
+
+    let invalid_scope = client.do_post("/api/keys", json!({ "name": "x", "scope": "admin" }));
+    assert_eq!(
+        invalid_scope.await?.status(),
+        StatusCode::UNPROCESSABLE_ENTITY.as_u16()
+    );
+
+    let delete = client
+        .do_delete(format!("/api/keys/{key_id}").as_str())
+        .await?;
+    assert_eq!(delete.status(), StatusCode::OK.as_u16());
+
+    let delete = client
+        .do_delete(format!("/api/keys/{key_id}").as_str())
+        .await?;
+    assert_eq!(delete.status(), StatusCode::NOT_FOUND.as_u16());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn jwks_integration_test() -> Result<()> {
+    let port = get_port();
+    spawn_test_app(port, false).await?;
+
+    let client = httpc_test::new_client(format!("http://localhost:{}", port))?;
+
+    let jwks = client.do_get("/.well-known/jwks.json").await?;
+    assert_eq!(jwks.status(), StatusCode::OK.as_u16());
+
+    let jwks = jwks.json_body()?;
+    let keys = jwks["keys"].as_array().cloned().unwrap_or_default();
+    assert!(!keys.is_empty());
+    assert!(keys
+        .iter()
+        .all(|k| k["kty"] == "RSA" && k["alg"] == "RS512" && k["n"].is_string()));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn passwd_change_integration_test() -> Result<()> {
+    let port = get_port();
+    spawn_test_app(port, true).await?;
+
+    let client = httpc_test::new_client(format!("http://localhost:{}", port))?;
+
+    client
+        .do_post(
+            "/api/login",
+            json!({
+            "username": "demo1",
+            "pwd": "demo1passwd"
+            }),
+        )
+        .await?;
+
+    let wrong_passwd = client.do_put(
+        "/api/me/password",
+        json!({
+        "current_pwd": "not-demo1passwd",
+        "new_pwd": "new-demo1passwd"
+        }),
+    );
+    assert_eq!(
+        wrong_passwd.await?.status(),
+        StatusCode::UNAUTHORIZED.as_u16()
+    );
+
+    let change = client.do_put(
+        "/api/me/password",
+        json!({
+        "current_pwd": "demo1passwd",
+        "new_pwd": "new-demo1passwd"
+        }),
+    );
+    assert_eq!(change.await?.status(), StatusCode::OK.as_u16());
+
+    // the session that changed the password is still valid
+    let playlists = client.do_get("/api/playlists");
+    assert_eq!(playlists.await?.status(), StatusCode::OK.as_u16());
+
+    let old_login = client.do_post(
+        "/api/login",
+        json!({
+        "username": "demo1",
+        "pwd": "demo1passwd"
+        }),
+    );
+    assert_eq!(old_login.await?.status(), StatusCode::UNAUTHORIZED.as_u16());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn passwd_reset_integration_test() -> Result<()> {
+    let port = get_port();
+    spawn_test_app(port, false).await?;
+
+    let admin = httpc_test::new_client(format!("http://localhost:{}", port))?;
+    let user = httpc_test::new_client(format!("http://localhost:{}", port))?;
+
+    // the first account is the admin
+    for username in ["owner", "user"] {
+        admin
+            .do_post(
+                "/api/signup",
+                json!({ "username": username, "pwd": "passwd" }),
+            )
+            .await?;
+    }
+
+    admin
+        .do_post(
+            "/api/login",
+            json!({ "username": "owner", "pwd": "passwd" }),
+        )
+        .await?;
+
+    user.do_post("/api/login", json!({ "username": "user", "pwd": "passwd" }))
+        .await?;
+
+    let users = admin.do_get("/api/admin/users").await?.json_body()?;
+    let user_id = users["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|u| u["username"] == "user")
+        .map(|u| u["id"].as_str().unwrap().to_string())
+        .unwrap();
+
+    let reset = admin
+        .do_post(
+            &format!("/api/admin/users/{user_id}/password-reset"),
+            json!({}),
+        )
+        .await?;
+    assert_eq!(reset.status(), StatusCode::OK.as_u16());
+
+    let token = reset.json_body()?["result"]["token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    // the password is locked and every session revoked until the token is used
+    let playlists = user.do_get("/api/playlists");
+    assert_eq!(playlists.await?.status(), StatusCode::UNAUTHORIZED.as_u16());
+
+    let login = user.do_post("/api/login", json!({ "username": "user", "pwd": "passwd" }));
+    assert_eq!(login.await?.status(), StatusCode::UNAUTHORIZED.as_u16());
+
+    let wrong_token = user.do_post(
+        "/api/password-reset",
+        json!({ "token": format!("{token}x"), "new_pwd": "new-passwd" }),
+    );
+    assert_eq!(
+        wrong_token.await?.status(),
+        StatusCode::UNAUTHORIZED.as_u16()
+    );
+
+    let reset = user.do_post(
+        "/api/password-reset",
+        json!({ "token": token, "new_pwd": "new-passwd" }),
+    );
+    assert_eq!(reset.await?.status(), StatusCode::OK.as_u16());
+
+    // the token works once
+    let reset = user.do_post(
+        "/api/password-reset",
+        json!({ "token": token, "new_pwd": "other-passwd" }),
+    );
+    assert_eq!(reset.await?.status(), StatusCode::UNAUTHORIZED.as_u16());
+
+    let login = user.do_post(
+        "/api/login",
+        json!({ "username": "user", "pwd": "new-passwd" }),
+    );
+    assert_eq!(login.await?.status(), StatusCode::OK.as_u16());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn delete_account_integration_test() -> Result<()> {
+    let port = get_port();
+    let state = spawn_test_app(port, true).await?;
+
+    let client = httpc_test::new_client(format!("http://localhost:{}", port))?;
+
+    client
+        .do_post(
+            "/api/login",
+            json!({ "username": "demo1", "pwd": "demo1passwd" }),
+        )
+        .await?;
+
+    client
+        .do_post("/api/playlists", json!({ "title": "Queen" }))
+        .await?;
+
+    let api_key = client.do_post("/api/keys", json!({ "name": "cli", "scope": "read-only" }));
+    assert_eq!(api_key.await?.status(), StatusCode::OK.as_u16());
+
+    let user = db::user::first_by_username(&state, "demo1").await?.unwrap();
+    let other_user = db::user::first_by_username(&state, "demo2").await?.unwrap();
+
+    db::playlist::create_new(&state, &other_user.id, "Other").await?;
+
+    // what DELETE /api/me runs once the password is checked
+    db::user::delete_with_data(&state, &user.id).await?;
+
+    assert!(db::user::first_by_id(&state, &user.id).await?.is_none());
+    assert!(db::playlist::all_by_user_id(&state, &user.id)
+        .await?
+        .is_empty());
+    assert!(db::api_key::all_by_user_id(&state, &user.id)
+        .await?
+        .is_empty());
+    assert!(db::session::all_active_by_user_id(&state, &user.id)
+        .await?
+        .is_empty());
+
+    // nothing of the other users is touched
+    assert_eq!(
+        db::playlist::all_by_user_id(&state, &other_user.id)
+            .await?
+            .len(),
+        1
+    );
+
+    let playlists = client.do_get("/api/playlists");
+    assert_eq!(playlists.await?.status(), StatusCode::UNAUTHORIZED.as_u16());
+
+    let login = client.do_post(
+        "/api/login",
+        json!({ "username": "demo1", "pwd": "demo1passwd" }),
+    );
+    assert_eq!(login.await?.status(), StatusCode::UNAUTHORIZED.as_u16());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn username_integration_test() -> Result<()> {
+    let port = get_port();
+    spawn_test_app(port, true).await?;
+
+    let client = httpc_test::new_client(format!("http://localhost:{}", port))?;
+
+    // demo1 already exists, usernames are unique regardless of case
+    let signup = client.do_post(
+        "/api/signup",
+        json!({ "username": "DEMO1", "pwd": "passwd" }),
+    );
+    assert_eq!(signup.await?.status(), StatusCode::CONFLICT.as_u16());
+
+    let signup = client.do_post("/api/signup", json!({ "username": "a b", "pwd": "passwd" }));
+    assert_eq!(signup.await?.status(), StatusCode::BAD_REQUEST.as_u16());
+
+    // a prefix of an existing username is not a match
+    let login = client.do_post(
+        "/api/login",
+        json!({ "username": "demo", "pwd": "demo1passwd" }),
+    );
+    assert_eq!(login.await?.status(), StatusCode::UNAUTHORIZED.as_u16());
+
+    let login = client.do_post(
+        "/api/login",
+        json!({ "username": "Demo1", "pwd": "demo1passwd" }),
+    );
+    assert_eq!(login.await?.status(), StatusCode::OK.as_u16());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn admin_permissions_integration_test() -> Result<()> {
+    let port = get_port();
+    spawn_test_app(port, true).await?;
+
+    let client = httpc_test::new_client(format!("http://localhost:{}", port))?;
+
+    let users = client.do_get("/api/admin/users");
+    assert_eq!(users.await?.status(), StatusCode::UNAUTHORIZED.as_u16());
+
+    client
+        .do_post(
+            "/api/login",
+            json!({
+            "username": "demo1",
+            "pwd": "demo1passwd"
+            }),
+        )
+        .await?;
+
+    // demo users are not admins
+    let users = client.do_get("/api/admin/users");
+    assert_eq!(users.await?.status(), StatusCode::FORBIDDEN.as_u16());
+
+    let usage = client.do_get("/api/admin/usage");
+    assert_eq!(usage.await?.status(), StatusCode::FORBIDDEN.as_u16());
+
+    let gc = client.do_post("/api/admin/gc", json!({ "dry_run": true }));
+    assert_eq!(gc.await?.status(), StatusCode::FORBIDDEN.as_u16());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn signup_bootstrap_integration_test() -> Result<()> {
+    let port = get_port();
+    spawn_test_app(port, false).await?;
+
+    let client = httpc_test::new_client(format!("http://localhost:{}", port))?;
+
+    // the first account of a fresh server becomes an admin
+    let signup = client
+        .do_post(
+            "/api/signup",
+            json!({
+            "username": "owner",
+            "pwd": "passwd"
+            }),
+        )
+        .await?;
+
+    assert_eq!(signup.status(), StatusCode::OK.as_u16());
+    assert_eq!(signup.json_body()?["result"]["role"], "admin");
+
+    let signup = client
+        .do_post(
+            "/api/signup",
+            json!({
+            "username": "second",
+            "pwd": "passwd"
+            }),
+        )
+        .await?;
+
+    assert_eq!(signup.json_body()?["result"]["role"], "user");
+
+    client
+        .do_post(
+            "/api/login",
+            json!({
+            "username": "owner",
+            "pwd": "passwd"
+            }),
+        )
+        .await?;
+
+    let users = client.do_get("/api/admin/users");
+    assert_eq!(users.await?.status(), StatusCode::OK.as_u16());
+
+    // nothing was removed, so there is nothing to collect
+    let gc = client
+        .do_post("/api/admin/gc", json!({ "dry_run": true }))
+        .await?;
+
+    assert_eq!(gc.status(), StatusCode::OK.as_u16());
+    assert_eq!(gc.json_body()?["data"]["dry_run"], true);
+    assert_eq!(gc.json_body()?["data"]["song_ids"], json!([]));
+
+    let invite = client
+        .do_post(
+            "/api/invites",
+            json!({
+            "max_uses": 0
+            }),
+        )
+        .await?;
+
+    assert_eq!(invite.status(), StatusCode::BAD_REQUEST.as_u16());
+
+    let invite = client
+        .do_post(
+            "/api/invites",
+            json!({
+            "max_uses": 2,
+            "expires_in_secs": 3600
+            }),
+        )
+        .await?;
+
+    assert_eq!(invite.status(), StatusCode::OK.as_u16());
+
+    let code = invite.json_body()?["result"]["code"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let invites = client.do_get("/api/invites").await?;
+    assert_eq!(invites.json_body()?["data"][0]["uses"], 0);
+
+    let delete = client.do_delete(&format!("/api/invites/{code}")).await?;
+    assert_eq!(delete.status(), StatusCode::OK.as_u16());
+
+    let delete = client.do_delete(&format!("/api/invites/{code}")).await?;
+    assert_eq!(delete.status(), StatusCode::NOT_FOUND.as_u16());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn login_lockout_integration_test() -> Result<()> {
+    let port = get_port();
+    spawn_test_app(port, true).await?;
+
+    let client = httpc_test::new_client(format!("http://localhost:{}", port))?;
+
+    for _ in 0..5 {
+        let login = client
+            .do_post(
+                "/api/login",
+                json!({
+                "username": "demo1",
+                "pwd": "wrongpasswd"
+                }),
+            )
+            .await?;
+
+        assert_eq!(login.status(), StatusCode::UNAUTHORIZED.as_u16());
+    }
+
+    // the right password is not even checked while locked out
+    let login = client
+        .do_post(
+            "/api/login",
+            json!({
+            "username": "demo1",
+            "pwd": "demo1passwd"
+            }),
+        )
+        .await?;
+
+    assert_eq!(login.status(), StatusCode::TOO_MANY_REQUESTS.as_u16());
+    assert!(login.header("retry-after").is_some());
+
+    // other users are not affected
+    let login = client
+        .do_post(
+            "/api/login",
+            json!({
+            "username": "demo2",
+            "pwd": "demo2passwd"
+            }),
+        )
+        .await?;
+
+    assert_eq!(login.status(), StatusCode::OK.as_u16());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn totp_integration_test() -> Result<()> {
+    let port = get_port();
+    spawn_test_app(port, true).await?;
+
+    let client = httpc_test::new_client(format!("http://localhost:{}", port))?;
+
+    client
+        .do_post(
+            "/api/login",
+            json!({
+            "username": "demo1",
+            "pwd": "demo1passwd"
+            }),
+        )
+        .await?;
+
+    let enrollment = client.do_post("/api/me/totp", json!({})).await?;
+    let secret = enrollment.json_body()?["result"]["secret"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let step = totp::time_step(now_utc().unix_timestamp() as u64);
+
+    let confirm = client
+        .do_post(
+            "/api/me/totp/confirm",
+            json!({
+            "code": totp::code_at(&secret, step)?
+            }),
+        )
+        .await?;
+
+    assert_eq!(confirm.status(), StatusCode::OK.as_u16());
+
+    // a correct password is no longer enough to log in
+    let login = client
+        .do_post(
+            "/api/login",
+            json!({
+            "username": "demo1",
+            "pwd": "demo1passwd"
+            }),
+        )
+        .await?;
+
+    assert_eq!(login.json_body()?["result"]["mfa_required"], true);
+
+    let mfa_token = login.json_body()?["result"]["mfa_token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let login_mfa = client
+        .do_post(
+            "/api/login/mfa",
+            json!({
+            "mfa_token": mfa_token,
+            "code": "000000"
+            }),
+        )
+        .await?;
+
+    assert_eq!(login_mfa.status(), StatusCode::UNAUTHORIZED.as_u16());
+
+    // the code used to confirm was spent, the next one is still accepted
+    let login_mfa = client
+        .do_post(
+            "/api/login/mfa",
+            json!({
+            "mfa_token": mfa_token,
+            "code": totp::code_at(&secret, step + 1)?
+            }),
+        )
+        .await?;
+
+    assert_eq!(login_mfa.status(), StatusCode::OK.as_u16());
+
+    Ok(())
+}
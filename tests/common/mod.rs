@@ -0,0 +1,40 @@
+use anyhow::{anyhow, Result};
+use axum::http::StatusCode;
+use httpc_test::Client;
+use serde_json::json;
+use std::time::Duration;
+
+/// Adds a song to the library of the logged in user and waits until it is downloaded
+///
+/// Songs are downloaded by a background job, so they only show up in the library once the job
+/// is done
+pub async fn add_song(client: &Client, link: &str) -> Result<()> {
+    let add_song = client
+        .do_post(
+            "/api/songs",
+            json!({
+            "link": link
+            }),
+        )
+        .await?;
+
+    // songs that were already downloaded are added right away
+    if add_song.status() != StatusCode::ACCEPTED.as_u16() {
+        return Ok(());
+    }
+
+    let job_id: String = add_song.json_value("/data/id")?;
+
+    loop {
+        let job = client
+            .do_get(format!("/api/jobs/{job_id}").as_str())
+            .await?
+            .json_body()?;
+
+        match job["data"]["state"].as_str() {
+            Some("done") => return Ok(()),
+            Some("failed") => return Err(anyhow!("Download job failed: {}", job["data"]["error"])),
+            _ => tokio::time::sleep(Duration::from_millis(500)).await,
+        }
+    }
+}
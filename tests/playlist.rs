@@ -1,8 +1,12 @@
+mod common;
+
 use anyhow::Result;
 use axum::http::StatusCode;
-use dev_utils::{spawn_test_app, util::get_port};
+use common::add_song;
+use dev_utils::{spawn_test_app, util::get_port, DEMO_PLAYLIST_ID};
 use ripfy_server::api::ModelResponse;
 use serde_json::json;
+use std::time::Duration;
 
 #[tokio::test]
 async fn playlist_songs_insertion_deletion_integration_test() -> Result<()> {
@@ -11,8 +15,8 @@ async fn playlist_songs_insertion_deletion_integration_test() -> Result<()> {
 
     let client = httpc_test::new_client(format!("http://localhost:{}", port))?;
 
-    let queen_classics_songs = ["fJ9rUzIMcZQ", "2ZBtPf7FOoM"];
-    let acdc_song = "Nnjh-zp6pP4";
+    let queen_classics_songs = ["yt:fJ9rUzIMcZQ", "yt:2ZBtPf7FOoM"];
+    let acdc_song = "yt:Nnjh-zp6pP4";
 
     client
         .do_post(
@@ -25,27 +29,11 @@ async fn playlist_songs_insertion_deletion_integration_test() -> Result<()> {
         .await?;
 
     for song in queen_classics_songs.iter() {
-        client
-            .do_post(
-                "/api/songs",
-                json!({
-                    "link": format!("https://youtu.be/{}", song)
-                    }
-                ),
-            )
-            .await?;
+        add_song(&client, &yt_link(song)).await?;
     }
 
     // song to test later
-    client
-        .do_post(
-            "/api/songs",
-            json!({
-                "link": format!("https://youtu.be/{}", acdc_song)
-                }
-            ),
-        )
-        .await?;
+    add_song(&client, &yt_link(acdc_song)).await?;
 
     // creates playlist
     let playlist: ModelResponse<entity::playlist::Model> = client
@@ -156,7 +144,7 @@ async fn playlist_exclusivity_integration_test() -> Result<()> {
     let client_one = httpc_test::new_client(format!("http://localhost:{}", port))?;
     let client_two = httpc_test::new_client(format!("http://localhost:{}", port))?;
 
-    let queen_song = "fJ9rUzIMcZQ";
+    let queen_song = "yt:fJ9rUzIMcZQ";
 
     client_one
         .do_post(
@@ -178,15 +166,7 @@ async fn playlist_exclusivity_integration_test() -> Result<()> {
         )
         .await?;
 
-    client_one
-        .do_post(
-            "/api/songs",
-            json!({
-                "link": format!("https://youtu.be/{}", queen_song)
-                }
-            ),
-        )
-        .await?;
+    add_song(&client_one, &yt_link(queen_song)).await?;
 
     // creates playlist
     let playlist: ModelResponse<entity::playlist::Model> = client_one
@@ -268,7 +248,7 @@ async fn user_song_deletion_integration_test() -> Result<()> {
     spawn_test_app(port, true).await?;
 
     let client = httpc_test::new_client(format!("http://localhost:{}", port))?;
-    let acdc_song = "Nnjh-zp6pP4";
+    let acdc_song = "yt:Nnjh-zp6pP4";
 
     client
         .do_post(
@@ -281,15 +261,7 @@ async fn user_song_deletion_integration_test() -> Result<()> {
         .await?;
 
     // adds song for user
-    client
-        .do_post(
-            "/api/songs",
-            json!({
-                "link": format!("https://youtu.be/{}", acdc_song)
-                }
-            ),
-        )
-        .await?;
+    add_song(&client, &yt_link(acdc_song)).await?;
 
     let playlist: ModelResponse<entity::playlist::Model> = client
         .do_post(
@@ -341,3 +313,124 @@ async fn user_song_deletion_integration_test() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn playlist_import_rejection_integration_test() -> Result<()> {
+    let port = get_port();
+    spawn_test_app(port, true).await?;
+
+    let client = httpc_test::new_client(format!("http://localhost:{}", port))?;
+
+    client
+        .do_post(
+            "/api/login",
+            json!({
+            "username": "demo1",
+            "pwd": "demo1passwd"
+            }),
+        )
+        .await?;
+
+    // asserts a link without a playlist is rejected
+    assert_eq!(
+        client
+            .do_post(
+                "/api/playlists/import",
+                json!({
+                "link": "https://youtu.be/fJ9rUzIMcZQ"
+                }),
+            )
+            .await?
+            .status()
+            .as_u16(),
+        StatusCode::BAD_REQUEST
+    );
+
+    // asserts a playlist that does not exist is rejected
+    assert_eq!(
+        client
+            .do_post(
+                "/api/playlists/import",
+                json!({
+                "link": "https://www.youtube.com/playlist?list=PLdoesnotexist0000000000000000000"
+                }),
+            )
+            .await?
+            .status()
+            .as_u16(),
+        StatusCode::BAD_REQUEST
+    );
+
+    // asserts no playlist was created
+    assert!(client
+        .do_get("/api/playlists")
+        .await?
+        .json_body_as::<ModelResponse<Vec<entity::playlist::Model>>>()?
+        .data
+        .is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn playlist_import_integration_test() -> Result<()> {
+    let port = get_port();
+    spawn_test_app(port, true).await?;
+
+    let client = httpc_test::new_client(format!("http://localhost:{}", port))?;
+
+    client
+        .do_post(
+            "/api/login",
+            json!({
+            "username": "demo1",
+            "pwd": "demo1passwd"
+            }),
+        )
+        .await?;
+
+    let import = client
+        .do_post(
+            "/api/playlists/import",
+            json!({
+            "link": format!("https://www.youtube.com/playlist?list={}", DEMO_PLAYLIST_ID)
+            }),
+        )
+        .await?;
+
+    // asserts the available songs were queued and the unavailable one failed
+    assert_eq!(import.status().as_u16(), StatusCode::ACCEPTED);
+
+    let body = import.json_body()?;
+
+    assert_eq!(body["data"]["playlist"]["title"], "Queen Classics");
+    assert_eq!(body["data"]["entries"][0]["status"], "queued");
+    assert_eq!(body["data"]["entries"][1]["status"], "failed");
+    assert_eq!(body["data"]["entries"][2]["status"], "queued");
+
+    let playlist_id = body["data"]["playlist"]["id"].as_str().unwrap_or_default();
+
+    // asserts the songs end up in the playlist, in the order of the original one
+    let songs = loop {
+        let songs: ModelResponse<Vec<entity::song::Model>> = client
+            .do_get(format!("/api/playlists/{}/songs", playlist_id).as_str())
+            .await?
+            .json_body_as()?;
+
+        if songs.data.len() == 2 {
+            break songs.data;
+        }
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    };
+
+    assert_eq!(songs[0].id, "yt:fJ9rUzIMcZQ");
+    assert_eq!(songs[1].id, "yt:2ZBtPf7FOoM");
+
+    Ok(())
+}
+
+/// Youtube link of the video a song was downloaded from
+fn yt_link(song_id: &str) -> String {
+    format!("https://youtu.be/{}", song_id.trim_start_matches("yt:"))
+}
@@ -1,7 +1,20 @@
+mod common;
+
 use anyhow::Result;
-use axum::http::StatusCode;
+use axum::{
+    body::{Body, Bytes},
+    http::{header, Request, StatusCode},
+    Router,
+};
+use common::add_song;
 use dev_utils::{spawn_test_app, util::get_port};
-use serde_json::json;
+use ripfy_server::{build_app, config};
+use sea_orm::ConnectionTrait;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use tower::ServiceExt;
+
+const UPLOAD_BOUNDARY: &str = "ripfy-test-boundary";
 
 #[tokio::test]
 async fn song_exclusivity_integration_test() -> Result<()> {
@@ -31,27 +44,58 @@ async fn song_exclusivity_integration_test() -> Result<()> {
         )
         .await?;
 
-    client_one
+    add_song(&client_one, "https://www.youtube.com/watch?v=fJ9rUzIMcZQ").await?;
+
+    let get_song_status = client_one
+        .do_get("/api/songs/yt:fJ9rUzIMcZQ")
+        .await?
+        .status();
+    assert_eq!(get_song_status, StatusCode::OK.as_u16());
+
+    let get_song_status = client_two
+        .do_get("/api/songs/yt:fJ9rUzIMcZQ")
+        .await?
+        .status();
+    assert_eq!(get_song_status, StatusCode::NOT_FOUND.as_u16());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn song_existance_integration_test() -> Result<()> {
+    let port = get_port();
+    spawn_test_app(port, true).await?;
+
+    let client = httpc_test::new_client(format!("http://localhost:{}", port))?;
+
+    client
         .do_post(
-            "/api/songs",
+            "/api/login",
             json!({
-                "link": "https://www.youtube.com/watch?v=fJ9rUzIMcZQ"
-                }
-            ),
+            "username": "demo1",
+            "pwd": "demo1passwd"
+            }),
         )
         .await?;
 
-    let get_song_status = client_one.do_get("/api/songs/fJ9rUzIMcZQ").await?.status();
+    let get_song_status = client.do_get("/api/songs/yt:fJ9rUzIMcZQ").await?.status();
+    assert_eq!(get_song_status, StatusCode::NOT_FOUND.as_u16());
+
+    add_song(&client, "https://www.youtube.com/watch?v=fJ9rUzIMcZQ").await?;
+
+    let get_song_status = client.do_get("/api/songs/yt:fJ9rUzIMcZQ").await?.status();
     assert_eq!(get_song_status, StatusCode::OK.as_u16());
 
-    let get_song_status = client_two.do_get("/api/songs/fJ9rUzIMcZQ").await?.status();
+    client.do_delete("/api/songs/yt:fJ9rUzIMcZQ").await?;
+
+    let get_song_status = client.do_get("/api/songs/yt:fJ9rUzIMcZQ").await?.status();
     assert_eq!(get_song_status, StatusCode::NOT_FOUND.as_u16());
 
     Ok(())
 }
 
 #[tokio::test]
-async fn song_existance_integration_test() -> Result<()> {
+async fn song_search_integration_test() -> Result<()> {
     let port = get_port();
     spawn_test_app(port, true).await?;
 
@@ -67,26 +111,245 @@ async fn song_existance_integration_test() -> Result<()> {
         )
         .await?;
 
-    let get_song_status = client.do_get("/api/songs/fJ9rUzIMcZQ").await?.status();
-    assert_eq!(get_song_status, StatusCode::NOT_FOUND.as_u16());
+    // asserts an empty query is rejected
+    let search_status = client.do_get("/api/search?q=%20").await?.status();
+    assert_eq!(search_status, StatusCode::BAD_REQUEST.as_u16());
+
+    // asserts a query that is too long is rejected
+    let search_status = client
+        .do_get(&format!("/api/search?q={}", "a".repeat(201)))
+        .await?
+        .status();
+    assert_eq!(search_status, StatusCode::BAD_REQUEST.as_u16());
+
+    add_song(&client, "https://www.youtube.com/watch?v=fJ9rUzIMcZQ").await?;
+
+    let results = client
+        .do_get("/api/search?q=queen%20bohemian%20rhapsody&limit=5")
+        .await?
+        .json_body()?;
+
+    let results = results["data"].as_array().unwrap();
+    assert!(!results.is_empty() && results.len() <= 5);
+
+    // asserts the song that was just added is marked as owned, and only it
+    for result in results {
+        let owned = result["id"] == "yt:fJ9rUzIMcZQ";
+        assert_eq!(result["owned"], owned);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn song_metadata_integration_test() -> Result<()> {
+    let port = get_port();
+    spawn_test_app(port, true).await?;
+
+    let client = httpc_test::new_client(format!("http://localhost:{}", port))?;
 
     client
         .do_post(
-            "/api/songs",
+            "/api/login",
             json!({
-                "link": "https://www.youtube.com/watch?v=fJ9rUzIMcZQ"
-                }
-            ),
+            "username": "demo1",
+            "pwd": "demo1passwd"
+            }),
         )
         .await?;
 
-    let get_song_status = client.do_get("/api/songs/fJ9rUzIMcZQ").await?.status();
-    assert_eq!(get_song_status, StatusCode::OK.as_u16());
+    add_song(&client, "https://www.youtube.com/watch?v=fJ9rUzIMcZQ").await?;
 
-    client.do_delete("/api/songs/fJ9rUzIMcZQ").await?;
+    let song = client
+        .do_get("/api/songs/yt:fJ9rUzIMcZQ")
+        .await?
+        .json_body()?;
 
-    let get_song_status = client.do_get("/api/songs/fJ9rUzIMcZQ").await?.status();
-    assert_eq!(get_song_status, StatusCode::NOT_FOUND.as_u16());
+    // asserts what players need to show the song was captured
+    assert!(song["data"]["duration_secs"]
+        .as_i64()
+        .is_some_and(|d| d > 0));
+    assert!(song["data"]["thumbnail_url"].is_string());
+    assert!(song["data"]["upload_date"].is_string());
+    assert!(song["data"]["created_at"].is_string());
 
     Ok(())
 }
+
+#[tokio::test]
+async fn song_art_integration_test() -> Result<()> {
+    let port = get_port();
+    spawn_test_app(port, true).await?;
+
+    let client = httpc_test::new_client(format!("http://localhost:{}", port))?;
+
+    client
+        .do_post(
+            "/api/login",
+            json!({
+            "username": "demo1",
+            "pwd": "demo1passwd"
+            }),
+        )
+        .await?;
+
+    // asserts a song without a cover gets the placeholder
+    let placeholder = client.do_get("/art/yt:notAddedYet").await?;
+    assert_eq!(placeholder.status(), StatusCode::NOT_FOUND.as_u16());
+    assert_eq!(
+        placeholder.header("content-type").as_deref(),
+        Some("image/svg+xml")
+    );
+
+    add_song(&client, "https://www.youtube.com/watch?v=fJ9rUzIMcZQ").await?;
+
+    for size in ["", "?size=256", "?size=512"] {
+        let art = client
+            .do_get(format!("/art/yt:fJ9rUzIMcZQ{size}").as_str())
+            .await?;
+
+        assert_eq!(art.status(), StatusCode::OK.as_u16());
+        assert_eq!(art.header("content-type").as_deref(), Some("image/jpeg"));
+    }
+
+    // asserts only the generated sizes exist
+    let art_status = client
+        .do_get("/art/yt:fJ9rUzIMcZQ?size=100")
+        .await?
+        .status();
+    assert_eq!(art_status, StatusCode::BAD_REQUEST.as_u16());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn song_upload_integration_test() -> Result<()> {
+    let port = get_port();
+    let state = spawn_test_app(port, true).await?;
+    let app = build_app(state.clone());
+
+    let uploader = login_token(port, "demo1", "demo1passwd").await?;
+    let other = login_token(port, "demo2", "demo2passwd").await?;
+
+    let file = Bytes::from_static(b"ID3\x04\x00\x00\x00\x00\x00\x00 some audio");
+    let (status, body) = upload_song(&app, &uploader, "My Demo.mp3", vec![file.clone()]).await?;
+    assert_eq!(status, StatusCode::OK, "{body}");
+
+    // asserts the song gets a generated id and is only in the library of the uploader
+    let song_id = body["data"]["id"].as_str().unwrap_or_default().to_string();
+    assert!(song_id.starts_with("up:"), "{song_id}");
+    assert_eq!(body["data"]["title"], "My Demo");
+
+    let song_uri = format!("/api/songs/{song_id}");
+    assert_eq!(
+        get_status(&app, &uploader, &song_uri).await?,
+        StatusCode::OK
+    );
+    assert_eq!(
+        get_status(&app, &other, &song_uri).await?,
+        StatusCode::NOT_FOUND
+    );
+
+    // asserts files that are not audio by their name are rejected
+    let (status, body) = upload_song(&app, &uploader, "notes.txt", vec![file.clone()]).await?;
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE, "{body}");
+
+    // asserts files past config().upload_max_bytes are rejected, the body is streamed so it is
+    // never whole in memory here either
+    let chunk = Bytes::from(vec![0; 1024 * 1024]);
+    let chunk_count = config().upload_max_bytes as usize / chunk.len() + 1;
+    let (status, body) = upload_song(&app, &uploader, "big.mp3", vec![chunk; chunk_count]).await?;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE, "{body}");
+
+    // asserts a failed insert leaves neither the upload nor the transcoded files behind
+    let upload_files_before = upload_files()?;
+
+    state
+        .db
+        .execute_unprepared(
+            "CREATE TRIGGER fail_song_insert BEFORE INSERT ON song
+            BEGIN SELECT RAISE(ABORT, 'song inserts fail'); END",
+        )
+        .await?;
+
+    let (status, body) = upload_song(&app, &uploader, "Lost.mp3", vec![file]).await?;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR, "{body}");
+    assert_eq!(upload_files()?, upload_files_before);
+
+    Ok(())
+}
+
+/// Logs in with the token in the body, the uploads are sent straight to the app and not through
+/// the client, so they have no cookies
+async fn login_token(port: u16, username: &str, pwd: &str) -> Result<String> {
+    let client = httpc_test::new_client(format!("http://localhost:{}", port))?;
+
+    let token = client
+        .do_post(
+            "/api/login",
+            json!({
+            "username": username,
+            "pwd": pwd,
+            "token_delivery": "body"
+            }),
+        )
+        .await?
+        .json_value("/result/access_token")?;
+
+    Ok(token)
+}
+
+/// Sends the chunks as the file of a multipart upload
+async fn upload_song(
+    app: &Router,
+    token: &str,
+    file_name: &str,
+    chunks: Vec<Bytes>,
+) -> Result<(StatusCode, Value)> {
+    let head = format!(
+        "--{UPLOAD_BOUNDARY}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{file_name}\"\r\nContent-Type: application/octet-stream\r\n\r\n"
+    );
+    let tail = format!("\r\n--{UPLOAD_BOUNDARY}--\r\n");
+
+    let body = std::iter::once(Bytes::from(head))
+        .chain(chunks)
+        .chain(std::iter::once(Bytes::from(tail)))
+        .map(Ok::<_, std::io::Error>);
+
+    let request = Request::post("/api/songs/upload")
+        .header(header::AUTHORIZATION, format!("Bearer {token}"))
+        .header(
+            header::CONTENT_TYPE,
+            format!("multipart/form-data; boundary={UPLOAD_BOUNDARY}"),
+        )
+        .body(Body::from_stream(tokio_stream::iter(body)))?;
+
+    let response = app.clone().oneshot(request).await?;
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+
+    Ok((status, serde_json::from_slice(&body).unwrap_or(Value::Null)))
+}
+
+async fn get_status(app: &Router, token: &str, uri: &str) -> Result<StatusCode> {
+    let request = Request::get(uri)
+        .header(header::AUTHORIZATION, format!("Bearer {token}"))
+        .body(Body::empty())?;
+
+    Ok(app.clone().oneshot(request).await?.status())
+}
+
+/// Names of the files of uploaded songs and of uploads being imported in the output path
+fn upload_files() -> Result<HashSet<String>> {
+    let mut files = HashSet::new();
+
+    for entry in std::fs::read_dir(&config().yt_dlp_output_path)? {
+        let name = entry?.file_name().to_string_lossy().to_string();
+
+        if name.starts_with("up:") || name.ends_with(".upload") {
+            files.insert(name);
+        }
+    }
+
+    Ok(files)
+}